@@ -0,0 +1,5 @@
+from ._pycurse import *  # noqa: F401,F403
+from .api import get, post, request  # noqa: F401
+from .batch import download_many  # noqa: F401
+from .discovery import fetch_feeds, sitemap_urls  # noqa: F401
+from . import aio  # noqa: F401
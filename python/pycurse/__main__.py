@@ -0,0 +1,116 @@
+"""Download URLs concurrently to a directory.
+
+    python -m pycurse -o downloads urls.txt
+    cat urls.txt | python -m pycurse -o downloads --retries 3
+
+The URLs are read one per line, blank lines and lines starting with `#` are
+ignored. Each URL is saved under the last segment of its path, made unique.
+A line with a URL followed by a name saves it under that name instead.
+"""
+
+import argparse
+import os
+import sys
+import time
+from urllib.parse import unquote, urlsplit
+
+from . import download_many
+
+
+def read_urls(lines):
+    for line in lines:
+        line = line.strip()
+        if not line or line.startswith('#'):
+            continue
+        url, _, name = line.partition(' ')
+        yield url, name.strip() or None
+
+
+def file_name(url, taken):
+    """A file name for `url` not in `taken`."""
+    split = urlsplit(url)
+    name = os.path.basename(unquote(split.path.rstrip('/'))) or split.hostname or 'index'
+    name = name.replace(os.sep, '_')
+    base, extension = os.path.splitext(name)
+    index = 1
+    while name in taken:
+        name = '%s-%d%s' % (base, index, extension)
+        index += 1
+    taken.add(name)
+    return name
+
+
+def format_size(size):
+    if size < 1024:
+        return '%d B' % size
+    for unit in ('KiB', 'MiB', 'GiB'):
+        size /= 1024
+        if size < 1024 or unit == 'GiB':
+            return '%.1f %s' % (size, unit)
+
+
+def main(argv=None):
+    parser = argparse.ArgumentParser(prog='pycurse', description='Download URLs concurrently to a directory.')
+    parser.add_argument('input', nargs='?', help='file with one URL per line, stdin by default')
+    parser.add_argument('-o', '--output-dir', default='.', help='directory the files are saved to')
+    parser.add_argument('-j', '--concurrency', type=int, default=16, help='transfers running at a time')
+    parser.add_argument('--retries', type=int, default=2, help='tries again failed downloads this many times')
+    parser.add_argument('--resume', action='store_true', help='continue interrupted downloads')
+    parser.add_argument('--only-if-newer', action='store_true', help='skip files up to date')
+    parser.add_argument('-q', '--quiet', action='store_true', help='only print the summary')
+    args = parser.parse_args(argv)
+
+    if args.input in (None, '-'):
+        urls = list(read_urls(sys.stdin))
+    else:
+        with open(args.input, encoding='utf-8') as f:
+            urls = list(read_urls(f))
+    os.makedirs(args.output_dir, exist_ok=True)
+
+    taken = set()
+    paths = {}
+    for url, name in urls:
+        paths.setdefault(url, os.path.join(args.output_dir, name or file_name(url, taken)))
+
+    def progress(done, total, response):
+        if response.status_code < 0 or response.status_code >= 400:
+            status = response.error or str(response.status_code)
+        elif response.not_modified:
+            status = 'not modified'
+        else:
+            status = str(response.status_code)
+        if not args.quiet:
+            print('[%d/%d] %s %s' % (done, total, status, response.url), file=sys.stderr)
+
+    start = time.monotonic()
+    results = download_many(
+        paths,
+        max_concurrent=args.concurrency,
+        progress=progress,
+        max_retries=args.retries,
+        resume=args.resume,
+        only_if_newer=args.only_if_newer,
+    )
+    succeeded, skipped, failed, size = 0, 0, [], 0
+    for response in results.values():
+        if response.status_code < 0 or response.status_code >= 400:
+            failed.append(response)
+        elif response.not_modified:
+            skipped += 1
+        else:
+            succeeded += 1
+            size += os.path.getsize(response.path)
+
+    elapsed = time.monotonic() - start
+    print(
+        '%d downloaded (%s), %d not modified, %d failed in %.1f s'
+        % (succeeded, format_size(size), skipped, len(failed), elapsed),
+        file=sys.stderr,
+    )
+    for response in failed:
+        print('failed: %s: %s' % (response.url, response.error or response.status_code), file=sys.stderr)
+    return 1 if failed else 0
+
+
+if __name__ == '__main__':
+    sys.exit(main())
@@ -0,0 +1,74 @@
+"""Await responses from asyncio, trio, or anyio running on either.
+
+    response = await pycurse.aio.get('https://example.com/')
+
+The transfers run in the thread of the downloader as usual: awaiting one
+blocks no thread, its callback wakes the awaiting task up in its event
+loop.
+"""
+
+import asyncio
+import sys
+
+from .api import DEFAULT_DOWNLOADER
+from ._pycurse import get_downloader
+
+
+def _event_loop():
+    """An event for the running event loop, and a function calling another
+    one in it from any thread."""
+    trio = sys.modules.get('trio')
+    if trio is not None:
+        try:
+            token = trio.lowlevel.current_trio_token()
+        except RuntimeError:
+            pass
+        else:
+            return trio.Event(), token.run_sync_soon
+    try:
+        loop = asyncio.get_running_loop()
+    except RuntimeError:
+        raise RuntimeError('pycurse.aio needs a running asyncio or trio event loop') from None
+    return asyncio.Event(), loop.call_soon_threadsafe
+
+
+async def send(downloader, url, **options):
+    """Add a request to `downloader` and await its response.
+
+    `options` are the ones of `CurlDownloader.add_request()`, but for
+    `callback`, used to deliver the response. A cancelled await leaves the
+    request running, its response is dropped.
+    """
+    event, call_soon = _event_loop()
+    result = []
+
+    def callback(response):
+        result.append(response)
+        try:
+            call_soon(event.set)
+        except RuntimeError:
+            # The event loop is closed, no one awaits anymore.
+            pass
+
+    downloader.add_request(url, callback=callback, **options)
+    await event.wait()
+    return result[0]
+
+
+async def request(url, **options):
+    """Perform a request with the shared downloader of `pycurse.request()`
+    and await its response."""
+    return await send(get_downloader(DEFAULT_DOWNLOADER), url, **options)
+
+
+async def get(url, **options):
+    """Send a GET request, see `request()`."""
+    return await request(url, **options)
+
+
+async def post(url, data=None, json=None, **options):
+    """Send a POST request with `data` or `json` as the body, see
+    `request()`."""
+    if data is None and json is None:
+        data = b''
+    return await request(url, data=data, json=json, **options)
@@ -0,0 +1,31 @@
+"""One-shot requests on a downloader shared by the whole process."""
+
+from ._pycurse import get_downloader
+
+#: The name of the shared downloader for `get_downloader()`.
+DEFAULT_DOWNLOADER = 'pycurse.default'
+
+def request(url, timeout=None, **options):
+    """Perform a request with the shared downloader and return its response.
+
+    `options` are the ones of `CurlDownloader.add_request()`. Waits at most
+    `timeout` seconds, forever by default, then raises `TimeoutError`.
+    Connections are reused from one call to the next, and calls from
+    several threads run concurrently.
+    """
+    downloader = get_downloader(DEFAULT_DOWNLOADER)
+    handle = downloader.add_request(url, **options)
+    return downloader.wait(handle, timeout)
+
+
+def get(url, **options):
+    """Send a GET request, see `request()`."""
+    return request(url, **options)
+
+
+def post(url, data=None, json=None, **options):
+    """Send a POST request with `data` or `json` as the body, see
+    `request()`."""
+    if data is None and json is None:
+        data = b''
+    return request(url, data=data, json=json, **options)
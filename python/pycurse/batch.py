@@ -0,0 +1,37 @@
+from ._pycurse import CurlDownloader
+
+
+def download_many(urls, max_concurrent=16, progress=None, max_retries=2, **options):
+    """Download each URL of the `urls` dict to its path.
+
+    At most `max_concurrent` transfers run at a time. Failed transfers and
+    4xx or 5xx responses are tried again up to `max_retries` times, and
+    `options` are passed to `CurlDownloader.add_request()`, like `resume`
+    or `headers`.
+
+    `progress` is called with the number of URLs done, the total and the
+    response of each URL as it completes.
+
+    Returns a dict of each URL to its response: check `status_code` and
+    `error` to tell failures, `path` is set once the file was saved.
+    """
+    if max_concurrent < 1:
+        raise ValueError('max_concurrent must be at least 1')
+    downloader = CurlDownloader(max_retries=max_retries, fail_on_status=True)
+    pending = iter(urls.items())
+    results = {}
+
+    def submit():
+        for url, path in pending:
+            downloader.add_request(url, path=path, **options)
+            return
+
+    for _ in range(max_concurrent):
+        submit()
+    # Each completed transfer makes room for the next URL.
+    for response in downloader:
+        results[response.url] = response
+        if progress is not None:
+            progress(len(results), len(urls), response)
+        submit()
+    return results
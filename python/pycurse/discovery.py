@@ -0,0 +1,60 @@
+"""Discover URLs from sitemaps and feeds, parsed in the downloader thread
+by the `sitemap` and `feed` processors rather than in Python."""
+
+import logging
+
+from ._pycurse import CurlDownloader
+
+logger = logging.getLogger(__name__)
+
+
+def sitemap_urls(url, max_sitemaps=1000, max_retries=2, **options):
+    """Yield the URL entries of the sitemap at `url`, a dict with their
+    `loc`, `lastmod`, `changefreq` and `priority`.
+
+    The sitemaps of a sitemap index are downloaded in parallel, each at
+    most once and up to `max_sitemaps` of them, and their entries are
+    yielded as they arrive; gzipped sitemaps are decompressed. A sitemap
+    that fails to download or parse is logged and skipped. `options` are
+    passed to `CurlDownloader.add_request()`.
+    """
+    downloader = CurlDownloader(max_retries=max_retries, fail_on_status=True)
+    seen = {url}
+    downloader.add_request(url, process=['sitemap'], **options)
+    for response in downloader:
+        sitemap = _processed(response, 'sitemap')
+        if sitemap is None:
+            continue
+        yield from sitemap['urls']
+        for child in sitemap['sitemaps']:
+            if child['loc'] in seen:
+                continue
+            if len(seen) >= max_sitemaps:
+                logger.warning('more than %d sitemaps from %s, skipping %s', max_sitemaps, url, child['loc'])
+                continue
+            seen.add(child['loc'])
+            downloader.add_request(child['loc'], process=['sitemap'], **options)
+
+
+def fetch_feeds(urls, max_retries=2, **options):
+    """Download the RSS or Atom feeds at `urls` in parallel.
+
+    Returns a dict of each URL to its feed, a dict with its `format`,
+    `title`, `link` and `entries`, or None when it failed to download or
+    parse, which is logged. `options` are passed to
+    `CurlDownloader.add_request()`.
+    """
+    downloader = CurlDownloader(max_retries=max_retries, fail_on_status=True)
+    handles = {downloader.add_request(url, process=['feed'], **options): url for url in urls}
+    return {url: _processed(downloader.wait(handle), 'feed') for handle, url in handles.items()}
+
+
+def _processed(response, name):
+    """The result of the processor `name`, None when the request failed."""
+    if response.error is not None or response.status_code >= 400:
+        logger.warning('failed to download %s: %s', response.url, response.error or response.status_code)
+        return None
+    if name in response.processing_errors:
+        logger.warning('failed to parse %s: %s', response.url, response.processing_errors[name])
+        return None
+    return response.processed[name]
@@ -0,0 +1,260 @@
+"""Test download pipelines without the network: a mock transport serving
+canned responses, downloaders wired to it, and a fake clock.
+
+The module is a pytest plugin, loaded by pytest once pycurse is installed,
+which provides the `mock_transport`, `mock_downloader` and `fake_clock`
+fixtures:
+
+    def test_fetch(mock_transport, mock_downloader):
+        mock_transport.add('http://example.com/', body=b'hello')
+        assert mock_downloader.request('http://example.com/').content == b'hello'
+
+A test using both `mock_downloader` and `fake_clock` gets a downloader on
+the fake clock: advancing it starts the retries waiting for their backoff.
+`MockTransport`, `downloader()` and `FakeClock` work without pytest too.
+"""
+
+import json as jsonlib
+import sys
+import threading
+import time
+from collections import namedtuple
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+
+from ._pycurse import CurlDownloader, ManualClock
+
+try:
+    import pytest
+except ImportError:
+    pytest = None
+
+__all__ = ['MockTransport', 'MockRequest', 'FakeClock', 'downloader']
+
+MockRequest = namedtuple('MockRequest', 'method url headers body')
+MockRequest.__doc__ = """A request received by a `MockTransport`, its
+`headers` a dict with lowercase names."""
+
+# The real sleep, for the responses delayed while a `FakeClock` is installed.
+_sleep = time.sleep
+
+PROXY_VARIABLES = ('http_proxy', 'HTTP_PROXY', 'https_proxy', 'HTTPS_PROXY', 'all_proxy', 'ALL_PROXY')
+
+
+class MockTransport:
+    """Canned responses served by a local HTTP server, to the downloaders
+    created by `downloader()` whatever the host of the URLs.
+
+    Only `http` URLs are served: the server does not speak TLS. The
+    requests received are recorded in `requests`, in order.
+    """
+
+    def __init__(self):
+        self.requests = []
+        self._routes = {}
+        self._lock = threading.Lock()
+        self._server = _Server(('127.0.0.1', 0), _handler(self))
+        self._thread = threading.Thread(target=self._server.serve_forever, name='pycurse-mock', daemon=True)
+        self._thread.start()
+
+    @property
+    def port(self):
+        return self._server.server_address[1]
+
+    @property
+    def connect_to(self):
+        """The `connect_to` entry sending the requests of every host and
+        port to the server."""
+        return '::127.0.0.1:%d' % self.port
+
+    def add(self, url, status=200, body=b'', headers=None, json=None, method='GET', delay=0):
+        """Serve a response to the `method` requests of `url`, matched with
+        its query, the response waiting `delay` seconds before its headers.
+
+        The responses added for the same request are served in turn, the
+        last one repeated: an error followed by a success tests a retry,
+        with `fail_on_status=True` for the error to be an HTTP status.
+        `body` can be a string, encoded in UTF-8, and `json` a value sent
+        as JSON instead.
+        """
+        headers = dict(headers or {})
+        if json is not None:
+            body = jsonlib.dumps(json)
+            headers.setdefault('Content-Type', 'application/json')
+        if isinstance(body, str):
+            body = body.encode()
+        with self._lock:
+            self._routes.setdefault(_route(method, url), []).append((status, headers, body, delay))
+
+    def requests_to(self, url, method='GET'):
+        """The requests received for `url`."""
+        key = _route(method, url)
+        return [request for request in self.requests if _route(request.method, request.url) == key]
+
+    def reset(self):
+        """Forget the responses added and the requests received."""
+        with self._lock:
+            self._routes.clear()
+            self.requests.clear()
+
+    def close(self):
+        self._server.shutdown()
+        self._server.server_close()
+        self._thread.join()
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc_info):
+        self.close()
+
+    def _respond(self, request):
+        with self._lock:
+            self.requests.append(request)
+            responses = self._routes.get(_route(request.method, request.url))
+            if not responses:
+                return None
+            return responses.pop(0) if len(responses) > 1 else responses[0]
+
+
+class _Server(ThreadingHTTPServer):
+    daemon_threads = True
+    # The connections of many transfers starting at once, instead of SYN
+    # retransmissions delaying some by a second.
+    request_queue_size = 128
+
+    def handle_error(self, request, client_address):
+        # A downloader closing its connection early, when cancelled or
+        # dropped, is not an error of the transport.
+        if not isinstance(sys.exc_info()[1], ConnectionError):
+            super().handle_error(request, client_address)
+
+
+def _route(method, url):
+    rest = url.partition('://')[2]
+    host, slash, path = rest.partition('/')
+    return method.upper(), host.lower(), slash + path.partition('#')[0] or '/'
+
+
+def _handler(transport):
+    class Handler(BaseHTTPRequestHandler):
+        protocol_version = 'HTTP/1.1'
+
+        def handle_one_request(self):
+            self.raw_requestline = self.rfile.readline(65537)
+            if not self.raw_requestline:
+                self.close_connection = True
+                return
+            if not self.parse_request():
+                return
+            length = int(self.headers.get('Content-Length') or 0)
+            body = self.rfile.read(length) if length else b''
+            url = 'http://%s%s' % (self.headers.get('Host', ''), self.path)
+            headers = {name.lower(): value for name, value in self.headers.items()}
+            response = transport._respond(MockRequest(self.command, url, headers, body))
+            if response is None:
+                response = 404, {}, ('no mock response for %s %s' % (self.command, url)).encode(), 0
+            status, headers, body, delay = response
+            if delay:
+                _sleep(delay)
+            self.send_response(status)
+            for name, value in headers.items():
+                self.send_header(name, value)
+            if not any(name.lower() == 'content-length' for name in headers):
+                self.send_header('Content-Length', str(len(body)))
+            self.end_headers()
+            if self.command != 'HEAD':
+                self.wfile.write(body)
+            self.wfile.flush()
+
+        def log_message(self, format, *args):
+            pass
+
+    return Handler
+
+
+def downloader(transport, **options):
+    """A new `CurlDownloader` sending its requests to `transport`, not
+    shared with the rest of the program; `options` are passed to the
+    constructor, like `clock`, a `FakeClock`.
+
+    The proxy environment variables are not unset: clear them for the
+    requests to reach the transport.
+    """
+    options['connect_to'] = [transport.connect_to] + list(options.get('connect_to') or [])
+    return CurlDownloader(**options)
+
+
+class FakeClock(ManualClock):
+    """A clock only moving when told to, or when slept on, which returns
+    immediately, for retry, backoff and deadline logic to run in no time
+    and the same way every run.
+
+    Given as the `clock` of a downloader, it drives its retry backoff and
+    its deadlines, see `CurlDownloader`: advancing the clock past a backoff
+    starts the retry. `install()` replaces `time.time`, `time.monotonic`
+    and `time.sleep` with it, for the logic written in Python.
+    """
+
+    def __init__(self, start=0.0):
+        # `start` is taken by `ManualClock.__new__()`.
+        self.sleeps = []
+        self._saved = None
+
+    @property
+    def now(self):
+        return self.time()
+
+    def monotonic(self):
+        return self.time()
+
+    def sleep(self, seconds):
+        """Advance the clock by `seconds` instead of waiting, recorded in
+        `sleeps`."""
+        if seconds < 0:
+            raise ValueError('sleep length must be non-negative')
+        self.sleeps.append(seconds)
+        self.advance(seconds)
+
+    def install(self):
+        self._saved = time.time, time.monotonic, time.sleep
+        time.time, time.monotonic, time.sleep = self.time, self.monotonic, self.sleep
+        return self
+
+    def uninstall(self):
+        if self._saved is not None:
+            time.time, time.monotonic, time.sleep = self._saved
+            self._saved = None
+
+    def __enter__(self):
+        return self.install()
+
+    def __exit__(self, *exc_info):
+        self.uninstall()
+
+
+if pytest is not None:
+
+    @pytest.fixture
+    def mock_transport():
+        """A `MockTransport`, closed after the test."""
+        with MockTransport() as transport:
+            yield transport
+
+    @pytest.fixture
+    def mock_downloader(request, mock_transport, monkeypatch):
+        """A new `CurlDownloader` wired to `mock_transport`, the proxy
+        environment variables unset, and with the `fake_clock` of the test
+        as its clock when it uses one."""
+        for name in PROXY_VARIABLES:
+            monkeypatch.delenv(name, raising=False)
+        options = {}
+        if 'fake_clock' in request.fixturenames:
+            options['clock'] = request.getfixturevalue('fake_clock')
+        return downloader(mock_transport, **options)
+
+    @pytest.fixture
+    def fake_clock():
+        """A `FakeClock` installed for the test, starting at the real
+        time."""
+        with FakeClock(time.time()) as clock:
+            yield clock
@@ -0,0 +1,193 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::httpdate;
+use crate::json::Value;
+use crate::trace::random_u64;
+
+/// The headers describing the body as received, which no longer apply to
+/// the body decoded by curl: WARC records keep them renamed, the way web
+/// archives do, with the `Content-Length` of the body archived.
+const DECODED_HEADERS: [&str; 3] = ["Content-Encoding", "Content-Length", "Transfer-Encoding"];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    /// WARC 1.1, a `response` record per response.
+    Warc,
+    /// A JSON object per line.
+    Jsonl,
+}
+
+impl Format {
+    /// The format named `name`, or the one of the extension of `path`.
+    pub fn new(name: Option<&str>, path: &str) -> Result<Self, String> {
+        let name = name.unwrap_or_else(|| path.rsplit('.').next().unwrap_or_default());
+        match name.to_ascii_lowercase().as_str() {
+            "warc" => Ok(Format::Warc),
+            "jsonl" | "ndjson" => Ok(Format::Jsonl),
+            _ => Err("archive_format must be 'warc' or 'jsonl'".to_owned()),
+        }
+    }
+}
+
+/// A response as archived.
+pub struct Record<'a> {
+    pub url: &'a str,
+    /// None for a failed request.
+    pub status_code: Option<i64>,
+    pub http_version: Option<&'a str>,
+    pub headers: &'a [(String, String)],
+    pub body: &'a [u8],
+    pub error: Option<&'a str>,
+    pub redirects: &'a [String],
+    pub queued: Option<SystemTime>,
+    pub started: Option<SystemTime>,
+    pub finished: SystemTime,
+}
+
+/// A file the responses are appended to, each record flushed as it is
+/// written.
+pub struct Archive {
+    format: Format,
+    file: BufWriter<File>,
+}
+
+impl Archive {
+    /// Open `path` to append to it; a new WARC file starts with a
+    /// `warcinfo` record.
+    pub fn open(path: &str, format: Format) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut archive = Archive {
+            format,
+            file: BufWriter::new(file),
+        };
+        if empty && format == Format::Warc {
+            let fields = format!(
+                "software: pycurse/{}\r\nformat: WARC File Format 1.1\r\n",
+                env!("CARGO_PKG_VERSION")
+            );
+            archive.write_warc("warcinfo", None, SystemTime::now(), "application/warc-fields", fields.as_bytes())?;
+            archive.file.flush()?;
+        }
+        Ok(archive)
+    }
+
+    pub fn write(&mut self, record: &Record<'_>) -> io::Result<()> {
+        match self.format {
+            Format::Warc => {
+                // A failed request has no response to archive.
+                let Some(status_code) = record.status_code else {
+                    return Ok(());
+                };
+                let mut block = format!("HTTP/{} {}\r\n", record.http_version.unwrap_or("1.1"), status_code);
+                for (name, value) in record.headers {
+                    if DECODED_HEADERS.iter().any(|decoded| decoded.eq_ignore_ascii_case(name)) {
+                        block.push_str("X-Archive-Orig-");
+                    }
+                    block.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                block.push_str(&format!("Content-Length: {}\r\n\r\n", record.body.len()));
+                let mut block = block.into_bytes();
+                block.extend_from_slice(record.body);
+                let content_type = "application/http;msgtype=response";
+                self.write_warc("response", Some(record.url), record.finished, content_type, &block)?;
+            }
+            Format::Jsonl => {
+                let mut line = String::new();
+                to_json(record).write(&mut line);
+                line.push('\n');
+                self.file.write_all(line.as_bytes())?;
+            }
+        }
+        self.file.flush()
+    }
+
+    fn write_warc(
+        &mut self,
+        kind: &str,
+        url: Option<&str>,
+        date: SystemTime,
+        content_type: &str,
+        block: &[u8],
+    ) -> io::Result<()> {
+        let mut header = format!(
+            "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Record-ID: <urn:uuid:{}>\r\nWARC-Date: {}\r\n",
+            kind,
+            uuid(),
+            httpdate::format_iso8601(epoch_seconds(date) as i64)
+        );
+        if let Some(url) = url {
+            header.push_str(&format!("WARC-Target-URI: {}\r\n", url));
+        }
+        header.push_str(&format!("Content-Type: {}\r\nContent-Length: {}\r\n\r\n", content_type, block.len()));
+        self.file.write_all(header.as_bytes())?;
+        self.file.write_all(block)?;
+        self.file.write_all(b"\r\n\r\n")
+    }
+}
+
+/// The JSONL line of a record.
+fn to_json(record: &Record<'_>) -> Value {
+    let headers = record
+        .headers
+        .iter()
+        .map(|(name, value)| Value::Array(vec![Value::String(name.clone()), Value::String(value.clone())]))
+        .collect();
+    // The body as text when it is UTF-8, in base64 otherwise.
+    let (body, body_base64) = match std::str::from_utf8(record.body) {
+        Ok(text) => (Some(text), None),
+        Err(_) => (None, Some(base64(record.body))),
+    };
+    let time = |time: Option<SystemTime>| time.map_or(Value::Null, |time| Value::Float(epoch_seconds(time)));
+    Value::object([
+        ("url", Value::String(record.url.to_owned())),
+        ("status_code", record.status_code.map_or(Value::Null, Value::Int)),
+        ("http_version", record.http_version.into()),
+        ("headers", Value::Array(headers)),
+        ("body", body.into()),
+        ("body_base64", body_base64.as_deref().into()),
+        ("error", record.error.into()),
+        ("redirects", Value::Array(record.redirects.iter().cloned().map(Value::String).collect())),
+        ("queued", time(record.queued)),
+        ("started", time(record.started)),
+        ("finished", time(Some(record.finished))),
+    ])
+}
+
+fn epoch_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
+
+/// A random UUID, version 4.
+fn uuid() -> String {
+    let (high, low) = (random_u64(), random_u64());
+    let high = (high & !0xf000) | 0x4000;
+    let low = (low & !(0b11 << 62)) | (0b10 << 62);
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
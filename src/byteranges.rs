@@ -0,0 +1,199 @@
+//! Split the body of a 206 Partial Content response into its ranges: the
+//! parts of a `multipart/byteranges` body, RFC 9110, or the single range
+//! of its `Content-Range`.
+
+use std::ops::Range;
+
+/// A range of the resource, from its `first` byte to its `last` one, and
+/// where its bytes are in the body.
+pub struct Part {
+    pub first: u64,
+    pub last: u64,
+    pub body: Range<usize>,
+}
+
+pub fn parse(headers: &[(String, String)], body: &[u8]) -> Result<Vec<Part>, String> {
+    let header = |name: &str| {
+        headers
+            .iter()
+            .rev()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+    let content_type = header("Content-Type").unwrap_or_default();
+    let (media_type, parameters) = content_type.split_once(';').unwrap_or((content_type, ""));
+    if !media_type.trim().eq_ignore_ascii_case("multipart/byteranges") {
+        let range = header("Content-Range").ok_or("206 response without Content-Range")?;
+        let (first, last) = content_range(range)?;
+        return Ok(vec![Part { first, last, body: 0..body.len() }]);
+    }
+    let boundary = parameters
+        .split(';')
+        .find_map(|parameter| {
+            let (name, value) = parameter.split_once('=')?;
+            name.trim().eq_ignore_ascii_case("boundary").then(|| value.trim().trim_matches('"'))
+        })
+        .filter(|boundary| !boundary.is_empty())
+        .ok_or("multipart/byteranges without boundary")?;
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut position = find(body, &delimiter, 0).ok_or("multipart/byteranges body without parts")?;
+    let mut parts = Vec::new();
+    loop {
+        position += delimiter.len();
+        if body[position..].starts_with(b"--") {
+            return Ok(parts);
+        }
+        // The rest of the delimiter line is padding.
+        position = find(body, b"\n", position).ok_or("truncated multipart/byteranges body")? + 1;
+        let mut range = None;
+        loop {
+            let end = find(body, b"\n", position).ok_or("truncated multipart/byteranges part headers")?;
+            let line = String::from_utf8_lossy(&body[position..end]);
+            position = end + 1;
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("Content-Range") {
+                    range = Some(content_range(value)?);
+                }
+            }
+        }
+        let (first, last) = range.ok_or("multipart/byteranges part without Content-Range")?;
+        let length = last
+            .checked_sub(first)
+            .and_then(|length| length.checked_add(1))
+            .and_then(|length| usize::try_from(length).ok())
+            .unwrap_or(usize::MAX);
+        let (end, next) =
+            content_end(body, position, length, &delimiter).ok_or("multipart/byteranges part not closed")?;
+        parts.push(Part { first, last, body: position..end });
+        position = next;
+    }
+}
+
+/// The first and last bytes of a `Content-Range` like `bytes 0-99/1000`.
+fn content_range(value: &str) -> Result<(u64, u64), String> {
+    let invalid = || format!("invalid Content-Range: {}", value.trim());
+    let (unit, range) = value.trim().split_once(' ').ok_or_else(invalid)?;
+    if !unit.eq_ignore_ascii_case("bytes") {
+        return Err(invalid());
+    }
+    let (range, _size) = range.trim().split_once('/').ok_or_else(invalid)?;
+    let (first, last) = range.split_once('-').ok_or_else(invalid)?;
+    match (first.trim().parse(), last.trim().parse()) {
+        (Ok(first), Ok(last)) if first <= last => Ok((first, last)),
+        _ => Err(invalid()),
+    }
+}
+
+/// The end of the content of a part starting at `start`, and the position
+/// of the delimiter after it, with the line break before the delimiter not
+/// part of the content. The content of the expected `length` is trusted
+/// when a delimiter follows it, as it may hold the boundary itself.
+fn content_end(body: &[u8], start: usize, length: usize, delimiter: &[u8]) -> Option<(usize, usize)> {
+    let delimiter_at = |end: usize| {
+        let rest = body.get(end..)?;
+        [&b"\r\n"[..], b"\n"]
+            .iter()
+            .find(|eol| rest.starts_with(eol) && rest[eol.len()..].starts_with(delimiter))
+            .map(|eol| end + eol.len())
+    };
+    if let Some(next) = start.checked_add(length).and_then(delimiter_at) {
+        return Some((start + length, next));
+    }
+    let mut from = start;
+    while let Some(found) = find(body, delimiter, from) {
+        if found > start && body[found - 1] == b'\n' {
+            let crlf = found - 1 > start && body[found - 2] == b'\r';
+            return Some((found - 1 - crlf as usize, found));
+        }
+        from = found + 1;
+    }
+    None
+}
+
+fn find(data: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| position + from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(content_type: &str) -> Vec<(String, String)> {
+        vec![("Content-Type".to_owned(), content_type.to_owned())]
+    }
+
+    /// The range and the body of a part.
+    type Split<'a> = (u64, u64, &'a [u8]);
+
+    fn split<'a>(headers: &[(String, String)], body: &'a [u8]) -> Result<Vec<Split<'a>>, String> {
+        let parts = parse(headers, body)?;
+        Ok(parts.into_iter().map(|part| (part.first, part.last, &body[part.body])).collect())
+    }
+
+    #[test]
+    fn single_range() {
+        let headers = vec![("content-range".to_owned(), "bytes 100-104/1000".to_owned())];
+        assert_eq!(split(&headers, b"hello").unwrap(), [(100, 104, &b"hello"[..])]);
+        assert_eq!(split(&headers, b"").unwrap(), [(100, 104, &b""[..])]);
+        assert_eq!(split(&[], b"hello").unwrap_err(), "206 response without Content-Range");
+    }
+
+    #[test]
+    fn multipart() {
+        let body = b"preamble\r\n--THIS\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-4/20\r\n\r\nhello\r\n\
+            --THIS  \r\ncontent-range: bytes 10-14/20\r\n\r\nworld\r\n--THIS--\r\nepilogue";
+        let expected = [(0, 4, &b"hello"[..]), (10, 14, &b"world"[..])];
+        assert_eq!(split(&headers("multipart/byteranges; boundary=THIS"), body).unwrap(), expected);
+        let quoted = headers("Multipart/ByteRanges ; charset=x; Boundary=\"THIS\"");
+        assert_eq!(split(&quoted, body).unwrap(), expected);
+        // Bare line feeds.
+        let body = b"--b\nContent-Range: bytes 5-7/8\n\nabc\n--b--\n";
+        assert_eq!(split(&headers("multipart/byteranges; boundary=b"), body).unwrap(), [(5, 7, &b"abc"[..])]);
+    }
+
+    #[test]
+    fn boundary_in_content() {
+        // The content of the announced length is trusted when a delimiter
+        // follows it, even if it holds the delimiter.
+        let body = b"--b\r\nContent-Range: bytes 0-7/8\r\n\r\nx\r\n--b\ny\r\n--b--";
+        let multipart = headers("multipart/byteranges; boundary=b");
+        assert_eq!(split(&multipart, body).unwrap(), [(0, 7, &b"x\r\n--b\ny"[..])]);
+        // A wrong length: the content ends at the next delimiter.
+        let body = b"--b\r\nContent-Range: bytes 0-99/100\r\n\r\nshort\r\n--b--";
+        assert_eq!(split(&multipart, body).unwrap(), [(0, 99, &b"short"[..])]);
+    }
+
+    #[test]
+    fn huge_range() {
+        let body = b"--b\r\nContent-Range: bytes 0-18446744073709551615/*\r\n\r\nabc\r\n--b--";
+        assert_eq!(split(&headers("multipart/byteranges; boundary=b"), body).unwrap(), [(0, u64::MAX, &b"abc"[..])]);
+        let body = b"--b\r\nContent-Range: bytes 18446744073709551615-18446744073709551615/*\r\n\r\nz\r\n--b--";
+        let expected = [(u64::MAX, u64::MAX, &b"z"[..])];
+        assert_eq!(split(&headers("multipart/byteranges; boundary=b"), body).unwrap(), expected);
+    }
+
+    #[test]
+    fn invalid() {
+        let multipart = headers("multipart/byteranges; boundary=b");
+        let error = |headers: &[(String, String)], body: &[u8]| split(headers, body).unwrap_err();
+        assert_eq!(error(&headers("multipart/byteranges"), b"--b--"), "multipart/byteranges without boundary");
+        assert_eq!(error(&multipart, b"no delimiter"), "multipart/byteranges body without parts");
+        assert_eq!(error(&multipart, b"--b"), "truncated multipart/byteranges body");
+        let headers_error = "truncated multipart/byteranges part headers";
+        assert_eq!(error(&multipart, b"--b\r\nContent-Range: bytes 0-1/2"), headers_error);
+        assert_eq!(error(&multipart, b"--b\r\n\r\nab\r\n--b--"), "multipart/byteranges part without Content-Range");
+        let unclosed = b"--b\r\nContent-Range: bytes 0-1/2\r\n\r\nab";
+        assert_eq!(error(&multipart, unclosed), "multipart/byteranges part not closed");
+        for range in ["bytes 5-4/10", "items 0-1/2", "bytes 0-1", "bytes -1/2", "bytes 0-x/2", "0-1/2"] {
+            let headers = vec![("Content-Range".to_owned(), range.to_owned())];
+            assert_eq!(error(&headers, b""), format!("invalid Content-Range: {}", range));
+        }
+    }
+}
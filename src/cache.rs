@@ -0,0 +1,125 @@
+/// The directives of the `Cache-Control` headers of a response, RFC 9111.
+pub struct CacheControl {
+    /// The lowercase names, with their argument.
+    directives: Vec<(String, Option<String>)>,
+}
+
+impl CacheControl {
+    pub fn parse<'a>(values: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut directives = Vec::new();
+        for value in values {
+            for directive in split_directives(value) {
+                let (name, argument) = match directive.split_once('=') {
+                    Some((name, argument)) => (name, Some(unquote(argument.trim()))),
+                    None => (directive, None),
+                };
+                let name = name.trim().to_ascii_lowercase();
+                if !name.is_empty() {
+                    directives.push((name, argument));
+                }
+            }
+        }
+        CacheControl { directives }
+    }
+
+    pub fn directives(&self) -> &[(String, Option<String>)] {
+        &self.directives
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.directives.iter().any(|(directive, _)| directive == name)
+    }
+
+    /// The delta-seconds argument of a directive like `max-age`.
+    ///
+    /// A value too large for a `u64` is the largest one, as RFC 9111 says.
+    pub fn seconds(&self, name: &str) -> Option<u64> {
+        let (_, argument) = self.directives.iter().find(|(directive, _)| directive == name)?;
+        let argument = argument.as_deref()?;
+        if argument.is_empty() || !argument.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        Some(argument.parse().unwrap_or(u64::MAX))
+    }
+}
+
+/// Split on the commas outside quoted strings.
+fn split_directives(value: &str) -> Vec<&str> {
+    let mut directives = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                directives.push(&value[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    directives.push(&value[start..]);
+    directives.into_iter().map(str::trim).filter(|directive| !directive.is_empty()).collect()
+}
+
+fn unquote(value: &str) -> String {
+    let Some(inner) = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) else {
+        return value.to_owned();
+    };
+    let mut unquoted = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            _ => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+/// The status codes whose responses may get a heuristic freshness
+/// lifetime.
+const HEURISTICALLY_CACHEABLE: [i64; 12] = [200, 203, 204, 206, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// How long a response stays fresh for a private cache, in seconds,
+/// RFC 9111 section 4.2.1.
+///
+/// `max-age` comes first, then `Expires` minus `Date`. Otherwise a response
+/// with a `Last-Modified` date gets 10% of the time since, as browsers do.
+/// `date` defaults to `response_time`; an invalid `Expires` means already
+/// expired.
+pub fn freshness_lifetime(
+    cache_control: &CacheControl,
+    status_code: i64,
+    date: Option<i64>,
+    expires: Option<Option<i64>>,
+    last_modified: Option<i64>,
+    response_time: i64,
+) -> Option<u64> {
+    if let Some(max_age) = cache_control.seconds("max-age") {
+        return Some(max_age);
+    }
+    let date = date.unwrap_or(response_time);
+    if let Some(expires) = expires {
+        return Some(expires.map_or(0, |expires| expires.saturating_sub(date).max(0) as u64));
+    }
+    let explicit = cache_control.has("public");
+    match last_modified {
+        Some(last_modified) if explicit || HEURISTICALLY_CACHEABLE.contains(&status_code) => {
+            Some((date.saturating_sub(last_modified).max(0) / 10) as u64)
+        }
+        _ => None,
+    }
+}
+
+/// The current age of a response in seconds, RFC 9111 section 4.2.3.
+///
+/// The times are in seconds since the epoch; `age` is the `Age` header.
+pub fn current_age(date: Option<i64>, age: Option<u64>, request_time: f64, response_time: f64, now: f64) -> f64 {
+    let apparent_age = date.map_or(0.0, |date| (response_time - date as f64).max(0.0));
+    let response_delay = (response_time - request_time).max(0.0);
+    let corrected_age = age.unwrap_or(0) as f64 + response_delay;
+    let initial_age = apparent_age.max(corrected_age);
+    initial_age + (now - response_time).max(0.0)
+}
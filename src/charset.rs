@@ -0,0 +1,100 @@
+/// How many bytes of an HTML body are searched for a `<meta>` charset, as
+/// in the HTML prescan.
+const PRESCAN_SIZE: usize = 1024;
+
+/// The character encoding of a body, as a Python codec name.
+///
+/// As browsers do, a byte order mark comes first, then the `charset` of
+/// the `Content-Type` header. With `sniff`, an HTML body without either is
+/// searched for `<meta charset>` or `<meta http-equiv>`.
+pub fn detect(content_type: Option<&str>, data: &[u8], sniff: bool) -> Option<String> {
+    if let Some(encoding) = from_bom(data) {
+        return Some(encoding.to_owned());
+    }
+    if let Some(charset) = content_type.and_then(from_content_type) {
+        return Some(normalize(&charset));
+    }
+    let is_html = content_type.is_none_or(|content_type| {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        mime.eq_ignore_ascii_case("text/html") || mime.eq_ignore_ascii_case("application/xhtml+xml")
+    });
+    if sniff && is_html {
+        return from_meta(&data[..data.len().min(PRESCAN_SIZE)]).map(|charset| normalize(&charset));
+    }
+    None
+}
+
+fn from_bom(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\xef\xbb\xbf") {
+        // Decodes the BOM away.
+        Some("utf-8-sig")
+    } else if data.starts_with(b"\xfe\xff") || data.starts_with(b"\xff\xfe") {
+        Some("utf-16")
+    } else {
+        None
+    }
+}
+
+/// The `charset` parameter of a `Content-Type` value.
+fn from_content_type(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        let value = value.trim().trim_matches(['"', '\'']).trim();
+        (!value.is_empty()).then(|| value.to_owned())
+    })
+}
+
+/// The charset declared by a `<meta>` tag: `<meta charset="...">`, or
+/// `<meta http-equiv="Content-Type" content="text/html; charset=...">`.
+fn from_meta(data: &[u8]) -> Option<String> {
+    let html = data.to_ascii_lowercase();
+    let mut rest = html.as_slice();
+    while let Some(start) = find(rest, b"<meta") {
+        rest = &rest[start + 5..];
+        let tag = &rest[..find(rest, b">").unwrap_or(rest.len())];
+        if let Some(charset) = charset_attribute(tag) {
+            return Some(charset);
+        }
+    }
+    None
+}
+
+/// The value following `charset=` in a tag, which covers both the
+/// `charset` attribute and the `charset` parameter in `content`.
+fn charset_attribute(tag: &[u8]) -> Option<String> {
+    let mut rest = tag;
+    while let Some(start) = find(rest, b"charset") {
+        rest = rest[start + 7..].trim_ascii_start();
+        let Some(value) = rest.strip_prefix(b"=") else {
+            continue;
+        };
+        let value = value.trim_ascii_start();
+        let value = value.strip_prefix(b"\"").or_else(|| value.strip_prefix(b"'")).unwrap_or(value);
+        let end = value
+            .iter()
+            .position(|c| matches!(c, b'"' | b'\'' | b';' | b'/') || c.is_ascii_whitespace())
+            .unwrap_or(value.len());
+        let charset = String::from_utf8_lossy(&value[..end]).into_owned();
+        if !charset.is_empty() {
+            return Some(charset);
+        }
+    }
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Map the labels browsers treat as windows-1252 to it, Python decodes
+/// the other WHATWG labels itself.
+fn normalize(charset: &str) -> String {
+    let charset = charset.to_ascii_lowercase();
+    match charset.as_str() {
+        "iso-8859-1" | "iso8859-1" | "latin1" | "l1" | "ascii" | "us-ascii" => "cp1252".to_owned(),
+        _ => charset,
+    }
+}
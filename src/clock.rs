@@ -0,0 +1,86 @@
+//! The clock of the retry backoff and of the deadlines of a downloader,
+//! which tests can move forward instead of waiting.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// A clock ahead of the real one by the time it was advanced.
+///
+/// A downloader given it as `clock` starts its retries and expires its
+/// deadlines that much earlier, and `request()` advances it instead of
+/// sleeping through its backoff. `time()`, in seconds since the epoch,
+/// stands still between advances.
+#[pyclass(frozen, subclass, module = "pycurse")]
+pub struct ManualClock {
+    start: f64,
+    /// In nanoseconds.
+    advanced: AtomicU64,
+}
+
+impl ManualClock {
+    /// The instant the downloader compares its deadlines and retry times
+    /// to.
+    pub fn now(&self) -> Instant {
+        Instant::now() + Duration::from_nanos(self.advanced.load(Ordering::Relaxed))
+    }
+
+    /// Move the clock forward by `duration`.
+    pub fn skip(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        let _ = self
+            .advanced
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |advanced| {
+                Some(advanced.saturating_add(nanos))
+            });
+    }
+
+    fn advanced_seconds(&self) -> f64 {
+        Duration::from_nanos(self.advanced.load(Ordering::Relaxed)).as_secs_f64()
+    }
+}
+
+#[pymethods]
+impl ManualClock {
+    /// `start` is the time in seconds since the epoch before any advance.
+    #[new]
+    #[pyo3(signature = (start=0.0))]
+    fn new(start: f64) -> Self {
+        ManualClock {
+            start,
+            advanced: AtomicU64::new(0),
+        }
+    }
+
+    /// Move the clock forward by `seconds`.
+    fn advance(&self, seconds: f64) -> PyResult<()> {
+        let duration = Duration::try_from_secs_f64(seconds)
+            .map_err(|_| PyValueError::new_err("seconds must be a non-negative number"))?;
+        self.skip(duration);
+        Ok(())
+    }
+
+    /// `start` plus the seconds advanced.
+    fn time(&self) -> f64 {
+        self.start + self.advanced_seconds()
+    }
+
+    /// The seconds advanced so far.
+    #[getter]
+    fn advanced(&self) -> f64 {
+        self.advanced_seconds()
+    }
+}
+
+/// The instant and the seconds since the epoch, of `clock` or real.
+pub fn now(clock: Option<&ManualClock>) -> (Instant, f64) {
+    match clock {
+        Some(clock) => (clock.now(), clock.time()),
+        None => {
+            let epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            (Instant::now(), epoch.as_secs_f64())
+        }
+    }
+}
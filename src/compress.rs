@@ -0,0 +1,90 @@
+//! Keep the bodies of the responses waiting to be fetched compressed with
+//! zstd.
+
+use std::cell::RefCell;
+
+use zstd::bulk::{Compressor, Decompressor};
+
+/// Bodies shorter than this are not worth compressing.
+const MIN_SIZE: usize = 1024;
+/// The fastest regular level: the bodies are compressed on the downloader
+/// thread.
+const LEVEL: i32 = 1;
+
+thread_local! {
+    /// The contexts of a thread, allocated once.
+    static COMPRESSOR: RefCell<Option<Compressor<'static>>> = const { RefCell::new(None) };
+    static DECOMPRESSOR: RefCell<Option<Decompressor<'static>>> = const { RefCell::new(None) };
+}
+
+/// `data` compressed, unless it is short or does not get shorter.
+pub fn compress(data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < MIN_SIZE {
+        return None;
+    }
+    let compressed = COMPRESSOR.with_borrow_mut(|compressor| {
+        let compressor = match compressor {
+            Some(compressor) => compressor,
+            None => compressor.insert(Compressor::new(LEVEL).ok()?),
+        };
+        compressor.compress(data).ok()
+    })?;
+    (compressed.len() < data.len()).then_some(compressed)
+}
+
+/// Decompress into `out` the `size` bytes `data` was compressed from.
+pub fn decompress(data: &[u8], size: usize, out: &mut Vec<u8>) -> Result<(), String> {
+    out.clear();
+    out.reserve_exact(size);
+    let length = DECOMPRESSOR.with_borrow_mut(|decompressor| {
+        let decompressor = match decompressor {
+            Some(decompressor) => decompressor,
+            None => decompressor.insert(Decompressor::new()?),
+        };
+        decompressor.decompress_to_buffer(data, out)
+    });
+    match length {
+        Ok(length) if length == size => Ok(()),
+        Ok(_) => Err("the buffered body decompressed to the wrong size".to_owned()),
+        Err(error) => Err(format!("could not decompress the buffered body: {error}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data = b"<p>compressible</p>".repeat(500);
+        let compressed = compress(&data).unwrap();
+        assert!(compressed.len() < data.len() / 10);
+        let mut out = b"stale".to_vec();
+        decompress(&compressed, data.len(), &mut out).unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn short_or_incompressible() {
+        assert_eq!(compress(b"short"), None);
+        let mut state = 0x2545f491u32;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        assert_eq!(compress(&noise), None);
+    }
+
+    #[test]
+    fn wrong_size() {
+        let data = vec![b'a'; 2048];
+        let compressed = compress(&data).unwrap();
+        assert!(decompress(&compressed, 100, &mut Vec::new()).is_err());
+        assert!(decompress(&compressed, 4096, &mut Vec::new()).is_err());
+        assert!(decompress(&compressed[..compressed.len() / 2], data.len(), &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use std::ffi::{c_long, CString};
+
+use curl::easy::{Easy2, Handler};
+use curl::multi::Easy2Handle;
+use curl_sys::{CURLoption, CURLINFO, CURLINFO_LONG, CURLOPTTYPE_LONG, CURLOPTTYPE_OBJECTPOINT};
+
+// Options and infos the curl crate has no method for.
+pub const DNS_INTERFACE: CURLoption = CURLOPTTYPE_OBJECTPOINT + 221;
+pub const DNS_LOCAL_IP4: CURLoption = CURLOPTTYPE_OBJECTPOINT + 222;
+pub const DNS_LOCAL_IP6: CURLoption = CURLOPTTYPE_OBJECTPOINT + 223;
+pub const SSL_ENABLE_ALPN: CURLoption = CURLOPTTYPE_LONG + 226;
+pub const TLS13_CIPHERS: CURLoption = CURLOPTTYPE_OBJECTPOINT + 276;
+pub const PROXY_TLS13_CIPHERS: CURLoption = CURLOPTTYPE_OBJECTPOINT + 277;
+pub const MAXLIFETIME_CONN: CURLoption = CURLOPTTYPE_LONG + 314;
+pub const INFO_HTTP_VERSION: CURLINFO = CURLINFO_LONG + 46;
+
+/// `CURLFTP_CREATE_DIR_RETRY`: create missing directories, and retry when
+/// the directory change fails.
+pub const FTP_CREATE_DIR_RETRY: c_long = 2;
+
+/// Set a string option with `curl_easy_setopt`.
+pub fn setopt_str<H: Handler>(easy: &mut Easy2<H>, option: CURLoption, value: &str) -> Result<(), curl::Error> {
+    let value = CString::new(value)?;
+    // SAFETY: libcurl copies string options.
+    check(unsafe { curl_sys::curl_easy_setopt(easy.raw(), option, value.as_ptr()) })
+}
+
+/// Set a long option with `curl_easy_setopt`.
+pub fn setopt_long<H: Handler>(easy: &mut Easy2<H>, option: CURLoption, value: c_long) -> Result<(), curl::Error> {
+    check(unsafe { curl_sys::curl_easy_setopt(easy.raw(), option, value) })
+}
+
+/// Read a long info with `curl_easy_getinfo`.
+pub fn getinfo_long<H: Handler>(easy: &Easy2<H>, info: CURLINFO) -> Result<c_long, curl::Error> {
+    let mut value: c_long = 0;
+    check(unsafe { curl_sys::curl_easy_getinfo(easy.raw(), info, &mut value) })?;
+    Ok(value)
+}
+
+/// Pause both directions of a transfer, or resume them, with
+/// `curl_easy_pause`.
+pub fn pause<H>(handle: &Easy2Handle<H>, paused: bool) -> Result<(), curl::Error> {
+    let bitmask = if paused {
+        curl_sys::CURLPAUSE_RECV | curl_sys::CURLPAUSE_SEND
+    } else {
+        curl_sys::CURLPAUSE_RECV_CONT | curl_sys::CURLPAUSE_SEND_CONT
+    };
+    check(unsafe { curl_sys::curl_easy_pause(handle.raw(), bitmask) })
+}
+
+fn check(code: curl_sys::CURLcode) -> Result<(), curl::Error> {
+    if code == curl_sys::CURLE_OK {
+        Ok(())
+    } else {
+        Err(curl::Error::new(code))
+    }
+}
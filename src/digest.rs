@@ -0,0 +1,174 @@
+use std::fmt::Write;
+
+use sha2::Digest;
+
+/// A hash function computing a digest of a body as it is received.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    Sha256,
+    Blake3,
+}
+
+impl Algorithm {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "sha256" => Ok(Algorithm::Sha256),
+            "blake3" => Ok(Algorithm::Blake3),
+            _ => Err(format!("unknown digest: {}, expected sha256 or blake3", name)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+/// The digests of a body being received.
+#[derive(Default)]
+pub struct Digests {
+    hashers: Vec<(Algorithm, Hasher)>,
+}
+
+enum Hasher {
+    Sha256(Box<sha2::Sha256>),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Digests {
+    pub fn new(algorithms: &[Algorithm]) -> Self {
+        let hashers = algorithms
+            .iter()
+            .map(|algorithm| {
+                let hasher = match algorithm {
+                    Algorithm::Sha256 => Hasher::Sha256(Box::default()),
+                    Algorithm::Blake3 => Hasher::Blake3(Box::default()),
+                };
+                (*algorithm, hasher)
+            })
+            .collect();
+        Digests { hashers }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for (_, hasher) in &mut self.hashers {
+            match hasher {
+                Hasher::Sha256(hasher) => hasher.update(data),
+                Hasher::Blake3(hasher) => {
+                    hasher.update(data);
+                }
+            }
+        }
+    }
+
+    /// The digests, as lowercase hex.
+    pub fn finish(self) -> Vec<(Algorithm, String)> {
+        self.hashers
+            .into_iter()
+            .map(|(algorithm, hasher)| {
+                let digest = match hasher {
+                    Hasher::Sha256(hasher) => hex(&hasher.finalize()),
+                    Hasher::Blake3(hasher) => hex(hasher.finalize().as_bytes()),
+                };
+                (algorithm, digest)
+            })
+            .collect()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut out, byte| {
+        write!(out, "{:02x}", byte).unwrap();
+        out
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The lengths and the SHA-256 and BLAKE3 digests of the input of the
+    /// official BLAKE3 test vectors, bytes counting up modulo 251, around
+    /// the 1024-byte chunks of BLAKE3.
+    const VECTORS: [(usize, &str, &str); 7] = [
+        (
+            0,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+        ),
+        (
+            1,
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213",
+        ),
+        (
+            1023,
+            "1c5e88a585b61754df6137d66632a7348557a88358afc401b0a0a4fc427104a9",
+            "10108970eeda3eb932baac1428c7a2163b0e924c9a9e25b35bba72b28f70bd11",
+        ),
+        (
+            1024,
+            "2bce1ba628720664be4b9fdd77aae0678e5f0f3f02fc6ff641ec879094f6a404",
+            "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+        ),
+        (
+            1025,
+            "bc0b6b10b89b9487a12fda2a8cc13194e7091c217aabf8b92846274026f4bcd0",
+            "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+        ),
+        (
+            2048,
+            "b2a8170614e23194ae2951423d601987f518ce2f11205d7b0b708080103b9f76",
+            "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a",
+        ),
+        (
+            31744,
+            "3cfe29c8d109f9f2c47826c78f931f31fdec70a2cf0ddfbba8fe8009a729dd42",
+            "62b6960e1a44bcc1eb1a611a8d6235b6b4b78f32e7abc4fb4c6cdcce94895c47",
+        ),
+    ];
+
+    fn digests(pieces: impl IntoIterator<Item = impl AsRef<[u8]>>) -> Vec<String> {
+        let mut digests = Digests::new(&[Algorithm::Sha256, Algorithm::Blake3]);
+        for piece in pieces {
+            digests.update(piece.as_ref());
+        }
+        digests.finish().into_iter().map(|(_, digest)| digest).collect()
+    }
+
+    #[test]
+    fn vectors() {
+        for (length, sha256, blake3) in VECTORS {
+            let input: Vec<u8> = (0..length).map(|i| (i % 251) as u8).collect();
+            assert_eq!(digests([&input]), [sha256, blake3], "{length} bytes");
+            // Received in pieces not aligned on the blocks nor the chunks.
+            for size in [1, 63, 1000, 4097] {
+                assert_eq!(digests(input.chunks(size)), [sha256, blake3], "{length} bytes by {size}");
+            }
+        }
+    }
+
+    #[test]
+    fn sha256() {
+        // FIPS 180-4 examples.
+        let sha256 = |pieces: &[&[u8]]| digests(pieces)[0].clone();
+        assert_eq!(sha256(&[b"abc"]), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(
+            sha256(&[b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"]),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        let million = vec![b'a'; 1_000_000];
+        assert_eq!(sha256(&[&million]), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+    }
+
+    #[test]
+    fn names() {
+        for name in ["sha256", "blake3"] {
+            assert_eq!(Algorithm::parse(name).unwrap().name(), name);
+        }
+        assert_eq!(Algorithm::parse("md5").unwrap_err(), "unknown digest: md5, expected sha256 or blake3");
+        assert!(Digests::new(&[]).finish().is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// A file being downloaded.
+///
+/// The body is written to a temporary file next to the destination, which is
+/// only renamed to the destination once the transfer succeeded. Readers of
+/// the destination never see a truncated file.
+///
+/// A resumable download keeps its temporary file, and the ETag it was
+/// downloaded with, when the transfer fails so a later attempt can continue
+/// where it stopped.
+pub struct PartFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    file: File,
+    resumable: bool,
+    offset: u64,
+    etag: Option<String>,
+}
+
+impl PartFile {
+    pub fn create(path: &str, token: usize) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        let temp_path = sibling(&path, &format!(".{}-{}.part", std::process::id(), token))?;
+        let file = File::create(&temp_path)?;
+        Ok(PartFile {
+            path,
+            temp_path,
+            file,
+            resumable: false,
+            offset: 0,
+            etag: None,
+        })
+    }
+
+    /// Open the partial download of `path`, or start a new one.
+    ///
+    /// A partial download is only continued if its ETag is known, so the
+    /// server can tell with `If-Range` whether it still matches.
+    pub fn resume(path: &str) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        let temp_path = sibling(&path, ".part")?;
+        let etag = fs::read_to_string(etag_path(&temp_path)).ok();
+        let file = OpenOptions::new().create(true).append(true).open(&temp_path)?;
+        let mut offset = file.metadata()?.len();
+        if offset > 0 && etag.is_none() {
+            file.set_len(0)?;
+            offset = 0;
+        }
+        Ok(PartFile {
+            path,
+            temp_path,
+            file,
+            resumable: true,
+            offset,
+            etag: etag.filter(|_| offset > 0),
+        })
+    }
+
+    /// The number of bytes already downloaded.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    /// Start the download over, when the server sends the whole body.
+    ///
+    /// `etag` is remembered to resume the new download later.
+    pub fn restart(&mut self, etag: Option<&str>) -> io::Result<()> {
+        if self.offset > 0 {
+            self.file.set_len(0)?;
+            self.file.seek(SeekFrom::Start(0))?;
+            self.offset = 0;
+        }
+        if self.resumable {
+            match etag {
+                Some(etag) => fs::write(etag_path(&self.temp_path), etag)?,
+                None => {
+                    let _ = fs::remove_file(etag_path(&self.temp_path));
+                }
+            }
+        }
+        self.etag = etag.map(str::to_owned);
+        Ok(())
+    }
+
+    /// Read the bytes already downloaded, in chunks.
+    pub fn read_downloaded(&self, mut chunk: impl FnMut(&[u8])) -> io::Result<()> {
+        let mut file = File::open(&self.temp_path)?.take(self.offset);
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            match file.read(&mut buffer)? {
+                0 => return Ok(()),
+                length => chunk(&buffer[..length]),
+            }
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file.write_all(data)
+    }
+
+    /// Move the downloaded file to its destination.
+    ///
+    /// `mtime` is the modification time reported by the server, in seconds
+    /// since the epoch.
+    pub fn commit(mut self, mtime: Option<i64>) -> io::Result<()> {
+        self.file.sync_all()?;
+        if let Some(mtime) = mtime.filter(|mtime| *mtime >= 0) {
+            self.file
+                .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(mtime as u64))?;
+        }
+        fs::rename(&self.temp_path, &self.path)?;
+        let _ = fs::remove_file(etag_path(&self.temp_path));
+        self.resumable = false;
+        Ok(())
+    }
+}
+
+impl Drop for PartFile {
+    /// Remove the temporary file of a failed download.
+    fn drop(&mut self) {
+        if !self.resumable {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// `dir/.name<suffix>` for `dir/name`.
+fn sibling(path: &Path, suffix: &str) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(suffix);
+    Ok(path.with_file_name(temp_name))
+}
+
+fn etag_path(temp_path: &Path) -> PathBuf {
+    let mut path = temp_path.as_os_str().to_owned();
+    path.push(".etag");
+    PathBuf::from(path)
+}
@@ -0,0 +1,97 @@
+/// What kind of failure failed a request, to act on without knowing the
+/// curl error codes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Category {
+    /// The host or the proxy could not be resolved.
+    Dns,
+    /// The connection could not be made, or was lost.
+    Connect,
+    Tls,
+    Timeout,
+    /// The server or the URL broke the protocol, redirects included.
+    Protocol,
+    /// The response is over a size limit.
+    TooLarge,
+    Cancelled,
+    /// The request was refused before being sent, by the host policy, a
+    /// quota, robots.txt or a stop condition, or its response by
+    /// `accept_content_types`.
+    Policy,
+    Other,
+}
+
+impl Category {
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Dns => "dns",
+            Category::Connect => "connect",
+            Category::Tls => "tls",
+            Category::Timeout => "timeout",
+            Category::Protocol => "protocol",
+            Category::TooLarge => "too_large",
+            Category::Cancelled => "cancelled",
+            Category::Policy => "policy",
+            Category::Other => "other",
+        }
+    }
+
+    /// The category of a `CURLE_*` code.
+    pub fn of_curl_code(code: u32) -> Self {
+        match code {
+            5 | 6 => Category::Dns,
+            7 | 18 | 45 | 52 | 55 | 56 | 97 => Category::Connect,
+            35 | 51 | 53 | 54 | 58 | 59 | 60 | 64 | 66 | 77 | 80 | 82 | 83 | 90 | 91 | 98 => Category::Tls,
+            28 => Category::Timeout,
+            1 | 3 | 8 | 16 | 22 | 47 | 61 | 92 | 95 => Category::Protocol,
+            63 | 100 => Category::TooLarge,
+            42 => Category::Cancelled,
+            _ => Category::Other,
+        }
+    }
+}
+
+/// Why a request failed: the message of its response, its category and
+/// the curl error code when curl reported it.
+pub struct Failure {
+    pub category: Category,
+    pub message: String,
+    pub curl_code: Option<u32>,
+}
+
+impl From<curl::Error> for Failure {
+    fn from(error: curl::Error) -> Self {
+        Failure {
+            category: Category::of_curl_code(error.code()),
+            message: error.to_string(),
+            curl_code: Some(error.code()),
+        }
+    }
+}
+
+impl<T: ToString> From<(Category, T)> for Failure {
+    fn from((category, message): (Category, T)) -> Self {
+        Failure {
+            category,
+            message: message.to_string(),
+            curl_code: None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(error: std::io::Error) -> Self {
+        (Category::Other, error).into()
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        (Category::Other, message).into()
+    }
+}
+
+impl From<&str> for Failure {
+    fn from(message: &str) -> Self {
+        (Category::Other, message).into()
+    }
+}
@@ -0,0 +1,182 @@
+use url::Url;
+
+use crate::json::Value;
+use crate::xml::{self, Element};
+
+/// Parse an RSS or Atom feed received from `url`, as a `format`, `rss`
+/// or `atom`, a `title`, a `link` and its `entries`.
+///
+/// Each entry has an `id`, a `title`, a `link`, `published` and `updated`
+/// dates as written, a `summary` and a `content`, None when missing. The
+/// id of an RSS item is its `guid`, or its link; links are resolved
+/// against `url`.
+pub fn parse(data: &[u8], url: &str) -> Result<Value, String> {
+    let root = xml::parse(data)?;
+    let base = Url::parse(url).ok();
+    let resolve = |link: &str| match &base {
+        Some(base) => base.join(link).map_or_else(|_| link.to_owned(), String::from),
+        None => link.to_owned(),
+    };
+    let (format, channel, entries) = match root.name.as_str() {
+        // RSS 1.0 has its items next to the channel, RSS 2.0 in it.
+        "rss" | "RDF" => {
+            let channel = root.child("channel").ok_or("not a feed, the RSS channel is missing")?;
+            let mut items = Vec::new();
+            root.descendants("item", &mut items);
+            let entries = items.into_iter().map(|item| rss_item(item, &resolve)).collect();
+            ("rss", channel, entries)
+        }
+        "feed" => ("atom", &root, root.children("entry").map(|entry| atom_entry(entry, &resolve)).collect()),
+        name => return Err(format!("not a feed, the root element is <{}>", name)),
+    };
+    let link = match format {
+        "rss" => rss_link(channel),
+        _ => atom_link(channel),
+    };
+    Ok(Value::object([
+        ("format", Value::String(format.to_owned())),
+        ("title", channel.child_text("title").into()),
+        ("link", link.map(resolve).as_deref().into()),
+        ("entries", Value::Array(entries)),
+    ]))
+}
+
+fn rss_item(item: &Element, resolve: &impl Fn(&str) -> String) -> Value {
+    let link = rss_link(item).map(resolve);
+    let id = item.child_text("guid").map(str::to_owned).or_else(|| link.clone());
+    Value::object([
+        ("id", id.as_deref().into()),
+        ("title", item.child_text("title").into()),
+        ("link", link.as_deref().into()),
+        // `dc:date` in RSS 1.0.
+        ("published", item.child_text("pubDate").or_else(|| item.child_text("date")).into()),
+        ("updated", item.child_text("updated").into()),
+        ("summary", item.child_text("description").into()),
+        // `content:encoded`.
+        ("content", item.child_text("encoded").into()),
+    ])
+}
+
+fn atom_entry(entry: &Element, resolve: &impl Fn(&str) -> String) -> Value {
+    Value::object([
+        ("id", entry.child_text("id").into()),
+        ("title", entry.child_text("title").into()),
+        ("link", atom_link(entry).map(resolve).as_deref().into()),
+        ("published", entry.child_text("published").into()),
+        ("updated", entry.child_text("updated").into()),
+        ("summary", entry.child_text("summary").into()),
+        ("content", entry.child_text("content").into()),
+    ])
+}
+
+/// The text of the first `<link>` with one: an `<atom:link>` is empty.
+fn rss_link(element: &Element) -> Option<&str> {
+    element
+        .children("link")
+        .map(|link| link.text.trim())
+        .find(|text| !text.is_empty())
+}
+
+/// The `href` of the alternate `<link>`, the one without `rel`.
+fn atom_link(element: &Element) -> Option<&str> {
+    element
+        .children("link")
+        .find(|link| link.attribute("rel").is_none_or(|rel| rel == "alternate"))
+        .and_then(|link| link.attribute("href"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(data: &str) -> String {
+        let mut out = String::new();
+        parse(data.as_bytes(), "https://example.com/blog/feed").unwrap().write(&mut out);
+        out
+    }
+
+    #[test]
+    fn rss2() {
+        let feed = r#"<?xml version="1.0"?>
+            <rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"
+                 xmlns:content="http://purl.org/rss/1.0/modules/content/">
+              <channel>
+                <title>Blog</title>
+                <atom:link href="https://example.com/blog/feed" rel="self"/>
+                <link>/blog/</link>
+                <item>
+                  <title>First</title>
+                  <link>post/1</link>
+                  <guid isPermaLink="false">id-1</guid>
+                  <pubDate>Mon, 01 Jan 2024 00:00:00 GMT</pubDate>
+                  <description>Summary &amp; more</description>
+                  <content:encoded><![CDATA[<p>Body</p>]]></content:encoded>
+                </item>
+                <item><link>https://other.test/2</link></item>
+              </channel>
+            </rss>"#;
+        assert_eq!(
+            parsed(feed),
+            concat!(
+                r#"{"format":"rss","title":"Blog","link":"https://example.com/blog/","entries":["#,
+                r#"{"id":"id-1","title":"First","link":"https://example.com/blog/post/1","#,
+                r#""published":"Mon, 01 Jan 2024 00:00:00 GMT","updated":null,"summary":"Summary & more","#,
+                r#""content":"<p>Body</p>"},"#,
+                r#"{"id":"https://other.test/2","title":null,"link":"https://other.test/2","published":null,"#,
+                r#""updated":null,"summary":null,"content":null}]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn rss1() {
+        let feed = r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"
+                xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns="http://purl.org/rss/1.0/">
+              <channel><title>Old</title><link>https://example.com/</link></channel>
+              <item><title>One</title><link>https://example.com/1</link><dc:date>2004-01-01</dc:date></item>
+            </rdf:RDF>"#;
+        assert_eq!(
+            parsed(feed),
+            concat!(
+                r#"{"format":"rss","title":"Old","link":"https://example.com/","entries":["#,
+                r#"{"id":"https://example.com/1","title":"One","link":"https://example.com/1","#,
+                r#""published":"2004-01-01","updated":null,"summary":null,"content":null}]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn atom() {
+        let feed = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+              <title>Atom</title>
+              <link rel="self" href="/blog/feed"/>
+              <link href="/blog/"/>
+              <entry>
+                <id>urn:uuid:1</id>
+                <title>Entry</title>
+                <link rel="edit" href="/edit/1"/>
+                <link rel="alternate" href="entries/1"/>
+                <published>2024-01-01T00:00:00Z</published>
+                <updated>2024-01-02T00:00:00Z</updated>
+                <summary>Short</summary>
+                <content type="html">&lt;p&gt;Long&lt;/p&gt;</content>
+              </entry>
+            </feed>"#;
+        assert_eq!(
+            parsed(feed),
+            concat!(
+                r#"{"format":"atom","title":"Atom","link":"https://example.com/blog/","entries":["#,
+                r#"{"id":"urn:uuid:1","title":"Entry","link":"https://example.com/blog/entries/1","#,
+                r#""published":"2024-01-01T00:00:00Z","updated":"2024-01-02T00:00:00Z","summary":"Short","#,
+                r#""content":"<p>Long</p>"}]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn not_a_feed() {
+        let url = "https://example.com/";
+        assert_eq!(parse(b"<urlset/>", url).err().unwrap(), "not a feed, the root element is <urlset>");
+        assert_eq!(parse(b"<rss/>", url).err().unwrap(), "not a feed, the RSS channel is missing");
+    }
+}
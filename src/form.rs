@@ -0,0 +1,59 @@
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyString, PyTuple};
+use std::fmt::Write;
+
+/// Encode a dict as an `application/x-www-form-urlencoded` body.
+///
+/// List and tuple values produce one pair per item, `None` values are
+/// skipped and everything else is converted with `str()`.
+pub fn urlencode(dict: &Bound<'_, PyDict>) -> PyResult<String> {
+    let mut out = String::new();
+    for (key, value) in dict.iter() {
+        let key = to_bytes(&key)?;
+        if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+            for item in value.iter()? {
+                push_pair(&mut out, &key, &item?)?;
+            }
+        } else {
+            push_pair(&mut out, &key, &value)?;
+        }
+    }
+    Ok(out)
+}
+
+fn push_pair(out: &mut String, key: &[u8], value: &Bound<'_, PyAny>) -> PyResult<()> {
+    if value.is_none() {
+        return Ok(());
+    }
+    if !out.is_empty() {
+        out.push('&');
+    }
+    quote_plus(key, out);
+    out.push('=');
+    quote_plus(&to_bytes(value)?, out);
+    Ok(())
+}
+
+fn to_bytes(obj: &Bound<'_, PyAny>) -> PyResult<Vec<u8>> {
+    if let Ok(value) = obj.downcast::<PyString>() {
+        Ok(value.to_str()?.as_bytes().to_vec())
+    } else if let Ok(value) = obj.downcast::<PyBytes>() {
+        Ok(value.as_bytes().to_vec())
+    } else if obj.is_instance_of::<PyDict>() {
+        Err(PyTypeError::new_err("form values cannot be dicts"))
+    } else {
+        Ok(obj.str()?.to_str()?.as_bytes().to_vec())
+    }
+}
+
+/// Percent-encode UTF-8 bytes, using `+` for spaces.
+fn quote_plus(value: &[u8], out: &mut String) {
+    for &b in value {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'*' => out.push(b as char),
+            b' ' => out.push('+'),
+            b => write!(out, "%{:02X}", b).unwrap(),
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Decompress gzip files, like the `sitemap.xml.gz` sitemaps, which are
+//! served as they are rather than with a `Content-Encoding` curl decodes,
+//! with the zlib libcurl is linked with.
+
+use std::ffi::{c_int, CStr};
+use std::mem::size_of;
+use std::ptr;
+
+use libz_sys::{uInt, voidpf, z_stream};
+
+/// The window bits of `inflateInit2()` reading a gzip member, header and
+/// trailer included, RFC 1952.
+const GZIP_WINDOW_BITS: c_int = 15 + 16;
+/// How much the output grows by at first.
+const MIN_GROWTH: usize = 32 * 1024;
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(b"\x1f\x8b")
+}
+
+/// Decompress the members of a gzip file, failing once more than
+/// `max_size` bytes come out.
+pub fn decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    let mut inflater = Inflater::new()?;
+    let mut out = Vec::new();
+    let mut rest = data;
+    while is_gzip(rest) {
+        rest = inflater.member(rest, &mut out, max_size)?;
+    }
+    Ok(out)
+}
+
+/// The CRC-32 of zlib and gzip.
+pub fn crc32(data: &[u8]) -> u32 {
+    data.chunks(uInt::MAX as usize).fold(0, |crc, chunk| unsafe {
+        libz_sys::crc32(crc, chunk.as_ptr(), chunk.len() as uInt)
+    }) as u32
+}
+
+/// A zlib inflate stream, boxed as zlib keeps its address.
+struct Inflater(Box<z_stream>);
+
+impl Inflater {
+    fn new() -> Result<Self, String> {
+        let mut stream = Box::new(z_stream {
+            next_in: ptr::null_mut(),
+            avail_in: 0,
+            total_in: 0,
+            next_out: ptr::null_mut(),
+            avail_out: 0,
+            total_out: 0,
+            msg: ptr::null_mut(),
+            state: ptr::null_mut(),
+            zalloc,
+            zfree,
+            opaque: ptr::null_mut(),
+            data_type: 0,
+            adler: 0,
+            reserved: 0,
+        });
+        let code = unsafe {
+            libz_sys::inflateInit2_(
+                &mut *stream,
+                GZIP_WINDOW_BITS,
+                libz_sys::zlibVersion(),
+                size_of::<z_stream>() as c_int,
+            )
+        };
+        if code != libz_sys::Z_OK {
+            return Err(format!("could not start decompressing (zlib error {})", code));
+        }
+        Ok(Inflater(stream))
+    }
+
+    /// Inflate the gzip member `data` starts with into `out`, zlib
+    /// checking its CRC-32 and size, and return what follows it.
+    fn member<'a>(&mut self, data: &'a [u8], out: &mut Vec<u8>, max_size: usize) -> Result<&'a [u8], String> {
+        let stream = &mut *self.0;
+        unsafe { libz_sys::inflateReset(stream) };
+        // One byte over `max_size` tells it is exceeded.
+        let limit = max_size.saturating_add(1);
+        let mut input = data;
+        loop {
+            if out.len() == out.capacity() {
+                out.reserve(out.len().max(MIN_GROWTH).min(limit - out.len()));
+            }
+            let in_length = input.len().min(uInt::MAX as usize);
+            let out_length = (out.capacity().min(limit) - out.len()).min(uInt::MAX as usize);
+            stream.next_in = input.as_ptr().cast_mut();
+            stream.avail_in = in_length as uInt;
+            stream.next_out = unsafe { out.as_mut_ptr().add(out.len()) };
+            stream.avail_out = out_length as uInt;
+            let code = unsafe { libz_sys::inflate(stream, libz_sys::Z_NO_FLUSH) };
+            input = &input[in_length - stream.avail_in as usize..];
+            unsafe { out.set_len(out.len() + out_length - stream.avail_out as usize) };
+            if out.len() > max_size {
+                return Err(format!("decompressed body is over {} bytes", max_size));
+            }
+            match code {
+                libz_sys::Z_STREAM_END => return Ok(input),
+                libz_sys::Z_BUF_ERROR if input.is_empty() => return Err("truncated gzip file".to_owned()),
+                libz_sys::Z_OK | libz_sys::Z_BUF_ERROR => {}
+                code if stream.msg.is_null() => return Err(format!("invalid gzip file (zlib error {})", code)),
+                _ => {
+                    let message = unsafe { CStr::from_ptr(stream.msg) };
+                    return Err(format!("invalid gzip file: {}", message.to_string_lossy()));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Inflater {
+    fn drop(&mut self) {
+        unsafe { libz_sys::inflateEnd(&mut *self.0) };
+    }
+}
+
+unsafe extern "C" fn zalloc(_: voidpf, items: uInt, size: uInt) -> voidpf {
+    libc::calloc(items as usize, size as usize)
+}
+
+unsafe extern "C" fn zfree(_: voidpf, address: voidpf) {
+    libc::free(address)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use libz_sys::uLong;
+
+    /// `data` as a gzip member compressed at `level`, level 0 giving
+    /// stored blocks.
+    pub fn gzip(data: &[u8], level: c_int) -> Vec<u8> {
+        let mut stream = z_stream {
+            next_in: data.as_ptr().cast_mut(),
+            avail_in: data.len() as uInt,
+            total_in: 0,
+            next_out: ptr::null_mut(),
+            avail_out: 0,
+            total_out: 0,
+            msg: ptr::null_mut(),
+            state: ptr::null_mut(),
+            zalloc,
+            zfree,
+            opaque: ptr::null_mut(),
+            data_type: 0,
+            adler: 0,
+            reserved: 0,
+        };
+        unsafe {
+            let code = libz_sys::deflateInit2_(
+                &mut stream,
+                level,
+                libz_sys::Z_DEFLATED,
+                GZIP_WINDOW_BITS,
+                8,
+                libz_sys::Z_DEFAULT_STRATEGY,
+                libz_sys::zlibVersion(),
+                size_of::<z_stream>() as c_int,
+            );
+            assert_eq!(code, libz_sys::Z_OK);
+            let mut out = vec![0; libz_sys::deflateBound(&mut stream, data.len() as uLong) as usize];
+            stream.next_out = out.as_mut_ptr();
+            stream.avail_out = out.len() as uInt;
+            assert_eq!(
+                libz_sys::deflate(&mut stream, libz_sys::Z_FINISH),
+                libz_sys::Z_STREAM_END
+            );
+            out.truncate(stream.total_out as usize);
+            libz_sys::deflateEnd(&mut stream);
+            out
+        }
+    }
+
+    fn sample() -> Vec<u8> {
+        let mut state = 1u32;
+        (0..100_000)
+            .map(|index| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                if index % 3 == 0 {
+                    (state >> 16) as u8
+                } else {
+                    b"<url><loc>https://example.com/</loc></url>"[index % 42]
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn levels() {
+        let data = sample();
+        for level in [0, 1, 6, 9] {
+            let compressed = gzip(&data, level);
+            assert!(is_gzip(&compressed));
+            assert_eq!(decompress(&compressed, data.len()).unwrap(), data, "level {level}");
+        }
+        assert_eq!(decompress(&gzip(b"", 6), 0).unwrap(), b"");
+    }
+
+    #[test]
+    fn members() {
+        let mut compressed = gzip(b"first, ", 1);
+        compressed.extend(gzip(b"second", 0));
+        compressed.extend(gzip(b", third", 9));
+        assert_eq!(decompress(&compressed, 100).unwrap(), b"first, second, third");
+        // Not a member: ignored, like the padding some servers add.
+        compressed.extend(b"\0\0\0\0");
+        assert_eq!(decompress(&compressed, 100).unwrap(), b"first, second, third");
+    }
+
+    #[test]
+    fn max_size() {
+        let data = sample();
+        let compressed = gzip(&data, 6);
+        assert_eq!(decompress(&compressed, data.len()).unwrap().len(), data.len());
+        let error = decompress(&compressed, data.len() - 1).unwrap_err();
+        assert_eq!(error, format!("decompressed body is over {} bytes", data.len() - 1));
+        let mut two = gzip(b"abc", 6);
+        two.extend(gzip(b"def", 6));
+        assert!(decompress(&two, 5).is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        let compressed = gzip(&sample(), 6);
+        assert_eq!(
+            decompress(&compressed[..compressed.len() / 2], usize::MAX).unwrap_err(),
+            "truncated gzip file"
+        );
+        // Without the last byte of the size in the trailer.
+        assert_eq!(
+            decompress(&compressed[..compressed.len() - 1], usize::MAX).unwrap_err(),
+            "truncated gzip file"
+        );
+        let mut corrupted = compressed.clone();
+        let length = corrupted.len();
+        corrupted[length - 8] ^= 1;
+        assert_eq!(
+            decompress(&corrupted, usize::MAX).unwrap_err(),
+            "invalid gzip file: incorrect data check"
+        );
+        let mut method = compressed;
+        method[2] = 7;
+        assert!(decompress(&method, usize::MAX)
+            .unwrap_err()
+            .starts_with("invalid gzip file"));
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414f_a339);
+    }
+}
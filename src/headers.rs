@@ -0,0 +1,68 @@
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// Read the `headers` argument: a dict or an iterable of `(name, value)`
+/// pairs, in the `Name: value` form given to curl.
+pub fn from_python(headers: &Bound<'_, PyAny>) -> PyResult<Vec<String>> {
+    let pairs: Vec<(String, String)> = if let Ok(dict) = headers.downcast::<PyDict>() {
+        dict.iter()
+            .map(|(name, value)| Ok((name.extract()?, value.extract()?)))
+            .collect::<PyResult<_>>()
+            .map_err(|_: PyErr| PyTypeError::new_err("header names and values must be str"))?
+    } else {
+        headers
+            .extract()
+            .map_err(|_| PyTypeError::new_err("headers must be a dict or a list of (name, value) pairs"))?
+    };
+    pairs.iter().map(|(name, value)| format(name, value)).collect()
+}
+
+/// Whether `headers` has a header called `name`.
+pub fn contains(headers: &[String], name: &str) -> bool {
+    headers.iter().any(|header| is_named(header, name))
+}
+
+/// The value of the first header called `name`, empty for a header
+/// removed with `Name:` or sent empty with `Name;`.
+pub fn value<'a>(headers: &'a [String], name: &str) -> Option<&'a str> {
+    let header = headers.iter().find(|header| is_named(header, name))?;
+    Some(header[name.len()..].trim_start_matches([':', ';']).trim())
+}
+
+/// Remove the headers called `name`.
+pub fn remove(headers: &mut Vec<String>, name: &str) {
+    headers.retain(|header| !is_named(header, name));
+}
+
+fn is_named(header: &str, name: &str) -> bool {
+    header
+        .split([':', ';'])
+        .next()
+        .is_some_and(|header_name| header_name.eq_ignore_ascii_case(name))
+}
+
+/// Validate a header and format it for curl.
+///
+/// The name must be an RFC 9110 token, and the value must not contain
+/// control characters other than tabs: a CR or LF would let the value
+/// smuggle other headers into the request.
+pub fn format(name: &str, value: &str) -> PyResult<String> {
+    if name.is_empty() || !name.bytes().all(is_token_char) {
+        return Err(PyValueError::new_err(format!("invalid header name: {:?}", name)));
+    }
+    if value.chars().any(|c| c.is_control() && c != '\t') {
+        return Err(PyValueError::new_err(format!("invalid value for header {}: {:?}", name, value)));
+    }
+    let value = value.trim_matches([' ', '\t']);
+    if value.is_empty() {
+        // curl removes a header given as `Name:`, `Name;` sends it empty.
+        Ok(format!("{};", name))
+    } else {
+        Ok(format!("{}: {}", name, value))
+    }
+}
+
+fn is_token_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c)
+}
@@ -0,0 +1,67 @@
+/// The hosts requests may go to.
+///
+/// A pattern is a glob like `*.example.com`, where `*` matches any
+/// characters and `?` one, or a suffix rule like `.example.com`, matching
+/// `example.com` and its subdomains. Hosts are compared lowercase.
+pub struct HostPolicy {
+    /// Empty allows every host not denied.
+    allowed: Vec<String>,
+    denied: Vec<String>,
+}
+
+impl HostPolicy {
+    pub fn new(allowed: Vec<String>, denied: Vec<String>) -> Self {
+        let lowercase = |patterns: Vec<String>| patterns.into_iter().map(|pattern| pattern.to_ascii_lowercase());
+        HostPolicy {
+            allowed: lowercase(allowed).collect(),
+            denied: lowercase(denied).collect(),
+        }
+    }
+
+    /// Whether `host` is allowed: a denied pattern wins over an allowed
+    /// one.
+    pub fn allows(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        let host = host.trim_end_matches('.');
+        if self.denied.iter().any(|pattern| pattern_matches(pattern, host)) {
+            return false;
+        }
+        self.allowed.is_empty() || self.allowed.iter().any(|pattern| pattern_matches(pattern, host))
+    }
+}
+
+pub fn pattern_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix('.') {
+        Some(domain) => host == domain || host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+        None => glob_matches(pattern.as_bytes(), host.as_bytes()),
+    }
+}
+
+/// Match a glob against the whole of `text`, going back to the last `*`
+/// on a mismatch.
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` is in the pattern, and the text it matched up to.
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
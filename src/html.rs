@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+
+use url::Url;
+
+/// The elements whose content is text, not markup, up to their end tag.
+const RAW_TEXT_ELEMENTS: [&[u8]; 5] = [b"script", b"style", b"textarea", b"title", b"xmp"];
+
+/// The `href` and `src` links of an HTML document received from `url`.
+///
+/// They are resolved against the first `<base href>`, or `url`, without
+/// their fragment, each once in document order. Only the `http` and
+/// `https` links are kept, not the `mailto:` or `javascript:` ones.
+/// Attribute values are decoded as UTF-8.
+pub fn links(html: &[u8], url: &str) -> Vec<String> {
+    let Ok(url) = Url::parse(url) else {
+        return Vec::new();
+    };
+    let mut base = None;
+    let mut values = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = find(&html[pos..], b"<") {
+        pos += start + 1;
+        let rest = &html[pos..];
+        if rest.starts_with(b"!--") {
+            pos += find(&rest[3..], b"-->").map_or(rest.len(), |end| end + 6);
+            continue;
+        }
+        let name_length = rest.iter().take_while(|c| c.is_ascii_alphanumeric()).count();
+        if name_length == 0 {
+            // An end tag, a doctype or a lone `<` in text.
+            continue;
+        }
+        let name = rest[..name_length].to_ascii_lowercase();
+        pos += name_length;
+        pos += parse_attributes(&html[pos..], |attribute, value| {
+            if attribute == b"href" || attribute == b"src" {
+                if name == b"base" && attribute == b"href" {
+                    base.get_or_insert_with(|| value.clone());
+                } else {
+                    values.push(value);
+                }
+            }
+        });
+        if RAW_TEXT_ELEMENTS.contains(&name.as_slice()) {
+            let end_tag = [b"</", name.as_slice()].concat();
+            let rest = &html[pos..];
+            pos += rest
+                .windows(end_tag.len())
+                .position(|window| window.eq_ignore_ascii_case(&end_tag))
+                .unwrap_or(rest.len());
+        }
+    }
+
+    let base = base.and_then(|base| url.join(&base).ok()).unwrap_or(url);
+    let mut seen = HashSet::new();
+    values
+        .iter()
+        .filter_map(|value| base.join(value).ok())
+        .filter(|link| matches!(link.scheme(), "http" | "https"))
+        .map(|mut link| {
+            link.set_fragment(None);
+            String::from(link)
+        })
+        .filter(|link| seen.insert(link.clone()))
+        .collect()
+}
+
+/// Call `attribute` with the lowercase name and the decoded value of each
+/// attribute of a start tag, from after its name, and return the length
+/// of the tag left.
+fn parse_attributes(tag: &[u8], mut attribute: impl FnMut(&[u8], String)) -> usize {
+    let mut pos = 0;
+    loop {
+        while tag.get(pos).is_some_and(|c| c.is_ascii_whitespace() || *c == b'/') {
+            pos += 1;
+        }
+        match tag.get(pos) {
+            None => return pos,
+            Some(b'>') => return pos + 1,
+            _ => {}
+        }
+        let name_start = pos;
+        while tag.get(pos).is_some_and(|c| !c.is_ascii_whitespace() && !matches!(c, b'=' | b'>' | b'/')) {
+            pos += 1;
+        }
+        let name = tag[name_start..pos].to_ascii_lowercase();
+        while tag.get(pos).is_some_and(u8::is_ascii_whitespace) {
+            pos += 1;
+        }
+        if tag.get(pos) != Some(&b'=') {
+            continue;
+        }
+        pos += 1;
+        while tag.get(pos).is_some_and(u8::is_ascii_whitespace) {
+            pos += 1;
+        }
+        let value = match tag.get(pos) {
+            Some(&quote @ (b'"' | b'\'')) => {
+                pos += 1;
+                let length = tag[pos..].iter().position(|c| *c == quote).unwrap_or(tag.len() - pos);
+                let value = &tag[pos..pos + length];
+                pos = (pos + length + 1).min(tag.len());
+                value
+            }
+            _ => {
+                let start = pos;
+                while tag.get(pos).is_some_and(|c| !c.is_ascii_whitespace() && *c != b'>') {
+                    pos += 1;
+                }
+                &tag[start..pos]
+            }
+        };
+        if !name.is_empty() {
+            attribute(&name, decode_references(&String::from_utf8_lossy(value)));
+        }
+    }
+}
+
+/// Decode the numeric character references and the named ones found in
+/// URLs, others are left as they are.
+fn decode_references(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_owned();
+    }
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let character = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => '\u{a0}',
+                reference => {
+                    let number = reference.strip_prefix('#')?;
+                    let code = match number.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => number.parse().ok()?,
+                    };
+                    char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                }
+            };
+            Some((character, end + 1))
+        });
+        match decoded {
+            Some((character, length)) => {
+                out.push(character);
+                rest = &rest[length..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
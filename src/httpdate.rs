@@ -0,0 +1,100 @@
+/// Parse an HTTP date into seconds since the epoch.
+///
+/// Accepts the three formats of RFC 9110: the IMF-fixdate
+/// `Sun, 06 Nov 1994 08:49:37 GMT`, the obsolete RFC 850
+/// `Sunday, 06-Nov-94 08:49:37 GMT` and asctime `Sun Nov  6 08:49:37 1994`,
+/// and the variants servers send, like a missing weekday or `UTC`.
+pub fn parse(value: &str) -> Option<i64> {
+    let mut month = None;
+    let mut time = None;
+    let mut numbers = Vec::new();
+    for token in value.split([' ', ',', '-', '\t']).filter(|token| !token.is_empty()) {
+        if token.contains(':') {
+            time = Some(parse_time(token)?);
+        } else if token.bytes().all(|c| c.is_ascii_digit()) {
+            numbers.push((token.parse::<i64>().ok()?, token.len()));
+        } else if let Some(index) = parse_month(token) {
+            month = Some(index);
+        } else if !is_weekday(token) && !matches!(token.to_ascii_uppercase().as_str(), "GMT" | "UTC" | "UT" | "Z") {
+            // Another time zone, or garbage.
+            return None;
+        }
+    }
+    let (month, (hour, minute, second)) = (month?, time?);
+    // The day comes before the year in all the formats.
+    let [(day, _), (year, digits)] = numbers[..] else {
+        return None;
+    };
+    let year = match (year, digits) {
+        // RFC 850 years: the closest to now, roughly.
+        (0..=69, 2) => 2000 + year,
+        (70..=99, 2) => 1900 + year,
+        _ => year,
+    };
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Format seconds since the epoch as an ISO 8601 UTC date, like
+/// `2024-05-01T12:30:00Z`.
+pub fn format_iso8601(seconds: i64) -> String {
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn parse_time(token: &str) -> Option<(i64, i64, i64)> {
+    let mut parts = token.split(':').map(|part| part.parse::<i64>().ok());
+    let time = (parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(time)
+}
+
+/// The month from 1 to 12.
+fn parse_month(token: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+    let token = token.to_ascii_lowercase();
+    MONTHS.iter().position(|month| *month == token).map(|index| index as i64 + 1)
+}
+
+fn is_weekday(token: &str) -> bool {
+    const WEEKDAYS: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+    let token = token.to_ascii_lowercase();
+    WEEKDAYS.iter().any(|weekday| token == *weekday || token == weekday[..3])
+}
+
+/// The number of days from 1970-01-01 to a date of the proleptic
+/// Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of a number of days from 1970-01-01, the inverse of
+/// `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
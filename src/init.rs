@@ -0,0 +1,118 @@
+//! The global initialization of libcurl, done once per process before the
+//! first handle is created.
+//!
+//! The curl crate initializes libcurl with `CURL_GLOBAL_ALL` as the
+//! extension is loaded, on the platforms with constructor functions, and
+//! before its first handle otherwise. The initializations are counted by
+//! libcurl: flags chosen before any handle exists undo that one and
+//! initialize libcurl again, the next initializations only count.
+
+use std::ffi::c_long;
+use std::sync::Mutex;
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+
+/// `CURL_GLOBAL_ACK_EINTR`, which curl-sys does not define: abort the
+/// waits of libcurl interrupted by a signal instead of resuming them.
+const GLOBAL_ACK_EINTR: c_long = 1 << 2;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct InitFlags {
+    /// Initialize the TLS library; libcurl 7.57 and above always do.
+    pub ssl: bool,
+    /// Initialize the Winsock sockets on Windows, unused elsewhere.
+    pub win32: bool,
+    pub ack_eintr: bool,
+}
+
+impl Default for InitFlags {
+    /// `CURL_GLOBAL_ALL`, the flags of the curl crate.
+    fn default() -> Self {
+        InitFlags {
+            ssl: true,
+            win32: true,
+            ack_eintr: false,
+        }
+    }
+}
+
+impl InitFlags {
+    fn bits(self) -> c_long {
+        let mut bits = curl_sys::CURL_GLOBAL_NOTHING;
+        if self.ssl {
+            bits |= curl_sys::CURL_GLOBAL_SSL;
+        }
+        if self.win32 {
+            bits |= curl_sys::CURL_GLOBAL_WIN32;
+        }
+        if self.ack_eintr {
+            bits |= GLOBAL_ACK_EINTR;
+        }
+        bits
+    }
+
+    /// The names of the flags set.
+    pub fn names(self) -> Vec<&'static str> {
+        [(self.ssl, "ssl"), (self.win32, "win32"), (self.ack_eintr, "ack_eintr")]
+            .into_iter()
+            .filter_map(|(set, name)| set.then_some(name))
+            .collect()
+    }
+}
+
+/// How libcurl was initialized, once it was for pycurse.
+#[derive(Clone, Copy)]
+pub struct InitState {
+    pub flags: InitFlags,
+    /// Whether the flags were chosen with `global_init()`.
+    pub explicit: bool,
+}
+
+static STATE: Mutex<Option<InitState>> = Mutex::new(None);
+
+/// Initialize libcurl with `flags` if no handle was created yet, the
+/// default flags when None.
+///
+/// Fails when libcurl was already initialized with other flags.
+pub fn init(flags: Option<InitFlags>) -> PyResult<InitState> {
+    let mut state = STATE.lock().unwrap();
+    if let Some(current) = *state {
+        return match flags {
+            Some(flags) if flags != current.flags => Err(PyRuntimeError::new_err(format!(
+                "libcurl is already initialized with the flags {:?}, global_init() only applies \
+                 before the first downloader",
+                current.flags.names()
+            ))),
+            _ => Ok(current),
+        };
+    }
+    if let Some(flags) = flags.filter(|flags| *flags != InitFlags::default()) {
+        // SAFETY: no handle exists yet, and the cleanup only undoes the
+        // initialization of the curl crate, if it happened.
+        let code = unsafe {
+            curl_sys::curl_global_cleanup();
+            curl_sys::curl_global_init(flags.bits())
+        };
+        if code != curl_sys::CURLE_OK {
+            return Err(PyRuntimeError::new_err(format!(
+                "curl_global_init failed: {}",
+                curl::Error::new(code)
+            )));
+        }
+    }
+    // Only counts one more initialization when libcurl already is.
+    curl::init();
+    let current = InitState {
+        flags: flags.unwrap_or_default(),
+        explicit: flags.is_some(),
+    };
+    *state = Some(current);
+    Ok(current)
+}
+
+/// How libcurl was initialized, None before the first downloader or
+/// `global_init()`.
+pub fn state() -> Option<InitState> {
+    *STATE.lock().unwrap()
+}
@@ -0,0 +1,534 @@
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyBytes, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple};
+use std::fmt::Write;
+use std::str;
+
+/// Nesting deeper than this is almost certainly a reference cycle.
+const MAX_DEPTH: usize = 512;
+
+/// Serialize a Python object to a JSON string.
+///
+/// Supports the same types as the `json` module with its default settings:
+/// `dict`, `list`, `tuple`, `str`, `int`, `float`, `bool` and `None`.
+pub fn dumps(obj: &Bound<'_, PyAny>) -> PyResult<String> {
+    let mut out = String::new();
+    write_value(obj, &mut out, 0)?;
+    Ok(out)
+}
+
+fn write_value(obj: &Bound<'_, PyAny>, out: &mut String, depth: usize) -> PyResult<()> {
+    if depth > MAX_DEPTH {
+        return Err(PyValueError::new_err("JSON object is too deeply nested"));
+    }
+    if obj.is_none() {
+        out.push_str("null");
+    } else if let Ok(value) = obj.downcast::<PyBool>() {
+        out.push_str(if value.is_true() { "true" } else { "false" });
+    } else if obj.is_instance_of::<PyLong>() {
+        // Python integers are unbounded, so use their own representation.
+        out.push_str(obj.str()?.to_str()?);
+    } else if let Ok(value) = obj.downcast::<PyFloat>() {
+        write_float(value.value(), out)?;
+    } else if let Ok(value) = obj.downcast::<PyString>() {
+        write_py_string(value, out)?;
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        out.push('{');
+        for (i, (key, value)) in dict.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write_key(&key, out)?;
+            out.push(':');
+            write_value(&value, out, depth + 1)?;
+        }
+        out.push('}');
+    } else if let Ok(list) = obj.downcast::<PyList>() {
+        write_array(list.iter(), out, depth)?;
+    } else if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        write_array(tuple.iter(), out, depth)?;
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "Object of type {} is not JSON serializable",
+            obj.get_type().name()?
+        )));
+    }
+    Ok(())
+}
+
+fn write_array<'py>(
+    items: impl Iterator<Item = Bound<'py, PyAny>>,
+    out: &mut String,
+    depth: usize,
+) -> PyResult<()> {
+    out.push('[');
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_value(&item, out, depth + 1)?;
+    }
+    out.push(']');
+    Ok(())
+}
+
+/// Object keys must be strings; scalars are converted like `json.dumps` does.
+fn write_key(key: &Bound<'_, PyAny>, out: &mut String) -> PyResult<()> {
+    if let Ok(key) = key.downcast::<PyString>() {
+        return write_py_string(key, out);
+    }
+    let mut scalar = String::new();
+    if key.is_none() || key.is_instance_of::<PyBool>() || key.is_instance_of::<PyLong>() {
+        write_value(key, &mut scalar, 0)?;
+    } else if let Ok(value) = key.downcast::<PyFloat>() {
+        write_float(value.value(), &mut scalar)?;
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "keys must be str, int, float, bool or None, not {}",
+            key.get_type().name()?
+        )));
+    }
+    write_string(&scalar, out);
+    Ok(())
+}
+
+fn write_float(value: f64, out: &mut String) -> PyResult<()> {
+    if !value.is_finite() {
+        return Err(PyValueError::new_err(format!(
+            "Out of range float values are not JSON compliant: {}",
+            value
+        )));
+    }
+    // `Debug` keeps the fractional part, so `1.0` stays a float.
+    write!(out, "{:?}", value).unwrap();
+    Ok(())
+}
+
+/// A Python string, with its lone surrogates, which UTF-8 cannot hold,
+/// escaped as `\uXXXX` like `json.dumps` does.
+fn write_py_string(value: &Bound<'_, PyString>, out: &mut String) -> PyResult<()> {
+    if let Ok(value) = value.to_str() {
+        write_string(value, out);
+        return Ok(());
+    }
+    let encoded = value.call_method1("encode", ("utf-16-le", "surrogatepass"))?;
+    let units = encoded.downcast::<PyBytes>()?.as_bytes().chunks_exact(2);
+    out.push('"');
+    for c in char::decode_utf16(units.map(|unit| u16::from_le_bytes([unit[0], unit[1]]))) {
+        match c {
+            Ok(c) => write_char(c, out),
+            Err(error) => write!(out, "\\u{:04x}", error.unpaired_surrogate()).unwrap(),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
+
+fn write_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        write_char(c, out);
+    }
+    out.push('"');
+}
+
+fn write_char(c: char, out: &mut String) {
+    match c {
+        '"' => out.push_str("\\\""),
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+        c => out.push(c),
+    }
+}
+
+/// A JSON value parsed without the GIL.
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    /// An integer too large for an `i64`, as written.
+    BigInt(String),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl From<Option<&str>> for Value {
+    fn from(text: Option<&str>) -> Self {
+        text.map_or(Value::Null, |text| Value::String(text.to_owned()))
+    }
+}
+
+impl Value {
+    /// An object with the members `members`.
+    pub fn object<const N: usize>(members: [(&str, Value); N]) -> Self {
+        Value::Object(members.into_iter().map(|(key, value)| (key.to_owned(), value)).collect())
+    }
+
+    /// Serialize the value, with non-finite floats as `null`.
+    pub fn write(&self, out: &mut String) {
+        match self {
+            Value::Null => out.push_str("null"),
+            Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Value::Int(value) => write!(out, "{}", value).unwrap(),
+            Value::BigInt(digits) => out.push_str(digits),
+            Value::Float(value) if !value.is_finite() => out.push_str("null"),
+            Value::Float(value) => write!(out, "{:?}", value).unwrap(),
+            Value::String(value) => write_string(value, out),
+            Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    item.write(out);
+                }
+                out.push(']');
+            }
+            Value::Object(members) => {
+                out.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_string(key, out);
+                    out.push(':');
+                    value.write(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+
+    /// The value as the objects of `json.loads`.
+    pub fn to_python(&self, py: Python<'_>) -> PyResult<PyObject> {
+        Ok(match self {
+            Value::Null => py.None(),
+            Value::Bool(value) => value.into_py(py),
+            Value::Int(value) => value.into_py(py),
+            Value::BigInt(digits) => py.get_type_bound::<PyLong>().call1((digits,))?.unbind(),
+            Value::Float(value) => value.into_py(py),
+            Value::String(value) => value.into_py(py),
+            Value::Array(items) => {
+                let list = PyList::empty_bound(py);
+                for item in items {
+                    list.append(item.to_python(py)?)?;
+                }
+                list.into_any().unbind()
+            }
+            Value::Object(members) => {
+                // Like `json.loads`, the last of duplicate keys wins.
+                let dict = PyDict::new_bound(py);
+                for (key, value) in members {
+                    dict.set_item(key, value.to_python(py)?)?;
+                }
+                dict.into_any().unbind()
+            }
+        })
+    }
+}
+
+/// Parse a UTF-8 JSON document, accepting what `json.loads` accepts with
+/// its default settings, `NaN` and `Infinity` included.
+pub fn parse(data: &[u8]) -> Result<Value, String> {
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    if let Err(error) = str::from_utf8(data) {
+        return Err(format!("invalid UTF-8 at byte {}", error.valid_up_to()));
+    }
+    let mut parser = Parser { data, pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos < data.len() {
+        return Err(parser.error("extra data"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at byte {}", message, self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.data.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, literal: &[u8]) -> bool {
+        let matched = self.data[self.pos..].starts_with(literal);
+        if matched {
+            self.pos += literal.len();
+        }
+        matched
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("JSON document is too deeply nested"));
+        }
+        self.skip_whitespace();
+        let value = match self.data.get(self.pos) {
+            None => return Err(self.error("expecting value")),
+            Some(b'{') => self.object(depth)?,
+            Some(b'[') => self.array(depth)?,
+            Some(b'"') => Value::String(self.string()?),
+            Some(b'-' | b'0'..=b'9') if !self.data[self.pos..].starts_with(b"-Infinity") => self.number()?,
+            Some(_) if self.eat(b"null") => Value::Null,
+            Some(_) if self.eat(b"true") => Value::Bool(true),
+            Some(_) if self.eat(b"false") => Value::Bool(false),
+            Some(_) if self.eat(b"NaN") => Value::Float(f64::NAN),
+            Some(_) if self.eat(b"Infinity") => Value::Float(f64::INFINITY),
+            Some(_) if self.eat(b"-Infinity") => Value::Float(f64::NEG_INFINITY),
+            Some(_) => return Err(self.error("expecting value")),
+        };
+        Ok(value)
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, String> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b"}") {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.data.get(self.pos) != Some(&b'"') {
+                return Err(self.error("expecting property name enclosed in double quotes"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(b":") {
+                return Err(self.error("expecting ':' delimiter"));
+            }
+            members.push((key, self.value(depth + 1)?));
+            self.skip_whitespace();
+            if self.eat(b"}") {
+                return Ok(Value::Object(members));
+            }
+            if !self.eat(b",") {
+                return Err(self.error("expecting ',' delimiter"));
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b"]") {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            if self.eat(b"]") {
+                return Ok(Value::Array(items));
+            }
+            if !self.eat(b",") {
+                return Err(self.error("expecting ',' delimiter"));
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        let digits = |parser: &mut Self| {
+            let from = parser.pos;
+            while parser.data.get(parser.pos).is_some_and(u8::is_ascii_digit) {
+                parser.pos += 1;
+            }
+            parser.pos - from
+        };
+        self.eat(b"-");
+        // No leading zeros: `012` is `0` followed by extra data.
+        if !self.eat(b"0") && digits(self) == 0 {
+            return Err(self.error("expecting value"));
+        }
+        let mut float = false;
+        if self.data.get(self.pos) == Some(&b'.') && self.data.get(self.pos + 1).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+            digits(self);
+            float = true;
+        }
+        if matches!(self.data.get(self.pos), Some(b'e' | b'E')) {
+            let mark = self.pos;
+            self.pos += 1;
+            if !self.eat(b"+") {
+                self.eat(b"-");
+            }
+            if digits(self) == 0 {
+                // Not an exponent, left as extra data.
+                self.pos = mark;
+            } else {
+                float = true;
+            }
+        }
+        let text = str::from_utf8(&self.data[start..self.pos]).unwrap();
+        Ok(match float {
+            true => Value::Float(text.parse().unwrap()),
+            false => text.parse().map(Value::Int).unwrap_or_else(|_| Value::BigInt(text.to_owned())),
+        })
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let Some(&byte) = self.data.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            match byte {
+                b'"' => {
+                    self.pos += 1;
+                    break;
+                }
+                b'\\' => {
+                    self.pos += 1;
+                    let escaped = match self.data.get(self.pos) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid \\escape")),
+                    };
+                    self.pos += 1;
+                    out.extend_from_slice(escaped.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                0..=0x1f => return Err(self.error("invalid control character")),
+                _ => {
+                    out.push(byte);
+                    self.pos += 1;
+                }
+            }
+        }
+        // Only whole characters were copied from the validated document.
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    /// The character of a `\uXXXX` escape, with the `u` at `pos`, left on
+    /// its last digit. Lone surrogates, which a `String` cannot hold, are
+    /// replaced by U+FFFD.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let first = self.hex4(self.pos + 1)?;
+        self.pos += 4;
+        if (0xd800..0xdc00).contains(&first) && self.data[self.pos + 1..].starts_with(b"\\u") {
+            if let Ok(second @ 0xdc00..=0xdfff) = self.hex4(self.pos + 3) {
+                self.pos += 6;
+                return Ok(char::from_u32(0x10000 + ((first - 0xd800) << 10) + (second - 0xdc00)).unwrap());
+            }
+        }
+        Ok(char::from_u32(first).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn hex4(&self, at: usize) -> Result<u32, String> {
+        self.data
+            .get(at..at + 4)
+            .and_then(|digits| str::from_utf8(digits).ok())
+            .filter(|digits| digits.bytes().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid \\uXXXX escape"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The JSON of the Python expression `expression`.
+    fn dumps_of(expression: &str) -> PyResult<String> {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| dumps(&py.eval_bound(expression, None, None)?))
+    }
+
+    /// `data` parsed and serialized again.
+    fn reparse(data: &str) -> Result<String, String> {
+        let mut out = String::new();
+        parse(data.as_bytes())?.write(&mut out);
+        Ok(out)
+    }
+
+    #[test]
+    fn dumps_values() {
+        assert_eq!(dumps_of("None").unwrap(), "null");
+        let expected = "[true,false,0,-7,1180591620717411303424,1.0,0.1,1e300]";
+        assert_eq!(dumps_of("[True, False, 0, -7, 2**70, 1.0, 0.1, 1e300]").unwrap(), expected);
+        assert_eq!(dumps_of("{'a': (1, [2]), 'b': {}}").unwrap(), r#"{"a":[1,[2]],"b":{}}"#);
+        assert_eq!(dumps_of("{1: 0, 2.5: 0, None: 0, False: 0}").unwrap(), r#"{"1":0,"2.5":0,"null":0,"false":0}"#);
+        assert_eq!(dumps_of("'caf\u{e9} \\U0001f600'").unwrap(), "\"caf\u{e9} \u{1f600}\"");
+    }
+
+    #[test]
+    fn dumps_escapes() {
+        assert_eq!(dumps_of(r#"'"\\\n\r\t\x00\x1f/'"#).unwrap(), r#""\"\\\n\r\t\u0000\u001f/""#);
+    }
+
+    #[test]
+    fn dumps_lone_surrogates() {
+        assert_eq!(dumps_of(r"'\ud800'").unwrap(), r#""\ud800""#);
+        assert_eq!(dumps_of(r"'a\udfff\n\ud83d'").unwrap(), r#""a\udfff\n\ud83d""#);
+        assert_eq!(dumps_of(r"{'\udc00': ['\ud800x']}").unwrap(), r#"{"\udc00":["\ud800x"]}"#);
+        // A pair of surrogates is the character they encode.
+        assert_eq!(dumps_of(r"'\ud83d\ude00'").unwrap(), "\"\u{1f600}\"");
+    }
+
+    #[test]
+    fn dumps_errors() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let message = |expression: &str| dumps_of(expression).unwrap_err().value_bound(py).to_string();
+            assert_eq!(message("float('nan')"), "Out of range float values are not JSON compliant: NaN");
+            assert_eq!(message("[b'']"), "Object of type bytes is not JSON serializable");
+            assert_eq!(message("{(1,): 0}"), "keys must be str, int, float, bool or None, not tuple");
+            let nested = "__import__('functools').reduce(lambda inner, _: [inner], range(600), [])";
+            assert_eq!(message(nested), "JSON object is too deeply nested");
+        });
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(reparse(" null ").unwrap(), "null");
+        assert_eq!(reparse("[true,false,0,-0,-7,1.5,1E3,2e-1]").unwrap(), "[true,false,0,0,-7,1.5,1000.0,0.2]");
+        assert_eq!(reparse("123456789012345678901234567890").unwrap(), "123456789012345678901234567890");
+        assert_eq!(reparse("[NaN,Infinity,-Infinity]").unwrap(), "[null,null,null]");
+        assert_eq!(reparse("\u{feff}{\"a\" : {\"b\": []}, \"a\": 1}").unwrap(), r#"{"a":{"b":[]},"a":1}"#);
+    }
+
+    #[test]
+    fn parse_strings() {
+        assert_eq!(reparse(r#""\"\\\/\b\f\n\r\t""#).unwrap(), r#""\"\\/\u0008\u000c\n\r\t""#);
+        assert_eq!(reparse(r#""\u00e9\u20AC caf\u00e9""#).unwrap(), "\"\u{e9}\u{20ac} caf\u{e9}\"");
+        assert_eq!(reparse(r#""\ud83d\ude00""#).unwrap(), "\"\u{1f600}\"");
+        // Lone surrogates cannot be held: replaced.
+        assert_eq!(reparse(r#""\ud800 \udc00 \ud83dx""#).unwrap(), "\"\u{fffd} \u{fffd} \u{fffd}x\"");
+        assert_eq!(reparse(r#""\ud83d\u0041""#).unwrap(), "\"\u{fffd}A\"");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(reparse("").unwrap_err(), "expecting value at byte 0");
+        assert_eq!(reparse("[1,]").unwrap_err(), "expecting value at byte 3");
+        assert_eq!(reparse("012").unwrap_err(), "extra data at byte 1");
+        assert_eq!(reparse("\"abc").unwrap_err(), "unterminated string at byte 4");
+        assert_eq!(reparse("\"\\x\"").unwrap_err(), "invalid \\escape at byte 2");
+        assert_eq!(reparse("\"\\u12\"").unwrap_err(), "invalid \\uXXXX escape at byte 2");
+        assert_eq!(reparse("\"a\nb\"").unwrap_err(), "invalid control character at byte 2");
+        assert_eq!(parse(b"\"\xff\"").err().unwrap(), "invalid UTF-8 at byte 1");
+        assert!(reparse(&"[".repeat(600)).unwrap_err().starts_with("JSON document is too deeply nested"));
+    }
+}
@@ -1,257 +1,5892 @@
+use pyo3::exceptions::{
+    PyBufferError, PyConnectionError, PyLookupError, PyRuntimeError, PyTimeoutError, PyTypeError, PyUnicodeDecodeError,
+    PyValueError,
+};
+use pyo3::buffer::PyBuffer;
+use pyo3::ffi;
 use pyo3::prelude::*;
-use std::collections::HashMap;
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{timezone_utc_bound, IntoPyDict, PyBytes, PyDateTime, PyDict, PyList, PyMemoryView, PyString, PyType};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ffi::{c_char, c_int, c_long, c_void, CStr, CString};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
 use std::str;
 use std::thread;
-use std::time::Duration;
-use curl::easy::{Easy2, Handler, WriteError};
+use std::time::{Duration, Instant};
+use curl::easy::{Auth, Easy2, Handler, HttpVersion, List, ReadError, SeekResult, TimeCondition, WriteError};
 use curl::multi::{Easy2Handle, Multi};
+use pyo3::create_exception;
 use std::result::Result;
-use std::sync::Mutex;
-use crossbeam::channel::{unbounded, Sender, Receiver};
-use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use crossbeam::channel::{unbounded, Sender, Receiver, RecvTimeoutError, TryRecvError};
+use polling::Poller;
+use url::Url;
 
+mod archive;
+mod byteranges;
+mod cache;
+mod charset;
+mod clock;
+mod compress;
+mod curl_ext;
+mod digest;
+mod download;
+mod failure;
+mod feed;
+mod form;
+mod gzip;
+mod headers;
+mod hosts;
+mod html;
+mod httpdate;
+mod init;
+mod json;
+mod link;
+mod options;
+mod pool;
+mod process;
+mod profile;
+mod quota;
+mod reactor;
+mod retry;
+mod rewrite;
+mod robots;
+mod sitemap;
+mod stats;
+mod stop;
+mod stream;
+mod throttle;
+mod tls;
+mod toml;
+mod trace;
+mod xml;
+
+use archive::Archive;
+use clock::ManualClock;
+use digest::{Algorithm, Digests};
+use download::PartFile;
+use failure::{Category, Failure};
+use hosts::HostPolicy;
+use rewrite::UrlRewrites;
+use stop::StopConditions;
+use options::RequestOptions;
+use pool::BufferPool;
+use process::{Output, Processor};
+use profile::Profile;
+use quota::HostQuotas;
+use reactor::Reactor;
+use retry::{Backoff, RetryBudget};
+use robots::Robots;
+use stats::{format_labels, Labels, Stats};
+use stream::{Next, Push, Stream};
+use throttle::HostThrottle;
+use tls::TlsOptions;
+use trace::{Span, SpanContext, Timestamp, Timings};
+
+/// HTTP authentication mechanisms selectable with `auth_scheme`.
+#[derive(Clone, Copy)]
+enum AuthScheme {
+    Basic,
+    Digest,
+    Ntlm,
+    Negotiate,
+    Any,
+}
+
+impl AuthScheme {
+    fn parse(name: &str) -> PyResult<Self> {
+        let version = curl::Version::get();
+        let scheme = match name.to_ascii_lowercase().as_str() {
+            "basic" => AuthScheme::Basic,
+            "digest" => AuthScheme::Digest,
+            "ntlm" => AuthScheme::Ntlm,
+            "negotiate" | "kerberos" => AuthScheme::Negotiate,
+            "any" => AuthScheme::Any,
+            _ => return Err(PyValueError::new_err(format!("unknown auth_scheme: {}", name))),
+        };
+        let supported = match scheme {
+            AuthScheme::Ntlm => version.feature_ntlm(),
+            AuthScheme::Negotiate => version.feature_spnego() || version.feature_gss_negotiate(),
+            _ => true,
+        };
+        if !supported {
+            return Err(PyValueError::new_err(format!(
+                "auth_scheme {} is not supported by this libcurl build",
+                name
+            )));
+        }
+        Ok(scheme)
+    }
+
+    fn to_auth(self) -> Auth {
+        let mut auth = Auth::new();
+        match self {
+            AuthScheme::Basic => auth.basic(true),
+            AuthScheme::Digest => auth.digest(true),
+            AuthScheme::Ntlm => auth.ntlm(true),
+            AuthScheme::Negotiate => auth.gssnegotiate(true),
+            AuthScheme::Any => auth.auto(true),
+        };
+        auth
+    }
+}
+
+/// Parse the `http_version` option.
+fn parse_http_version(name: &str) -> PyResult<HttpVersion> {
+    let version = match name {
+        "1.0" => HttpVersion::V10,
+        "1.1" => HttpVersion::V11,
+        "2" => HttpVersion::V2,
+        "2-prior-knowledge" => HttpVersion::V2PriorKnowledge,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "unknown http_version: {}, expected 1.0, 1.1, 2 or 2-prior-knowledge",
+                name
+            )))
+        }
+    };
+    if matches!(version, HttpVersion::V2 | HttpVersion::V2PriorKnowledge) && !curl::Version::get().feature_http2() {
+        return Err(PyValueError::new_err("HTTP/2 is not supported by this libcurl build"));
+    }
+    Ok(version)
+}
+
+/// The HTTP version a response was received with.
+fn http_version_name(version: c_long) -> Option<&'static str> {
+    match version as c_int {
+        curl_sys::CURL_HTTP_VERSION_1_0 => Some("1.0"),
+        curl_sys::CURL_HTTP_VERSION_1_1 => Some("1.1"),
+        curl_sys::CURL_HTTP_VERSION_2_0 => Some("2"),
+        curl_sys::CURL_HTTP_VERSION_3 => Some("3"),
+        _ => None,
+    }
+}
+
+/// A request waiting to be picked up by the downloader thread.
+#[derive(Clone, Default)]
+struct Request {
+    /// The position of the request in the order of submission.
+    sequence: u64,
+    url: String,
+    headers: Vec<String>,
+    body: Option<Vec<u8>>,
+    credentials: Option<(String, String)>,
+    auth_scheme: Option<AuthScheme>,
+    proxy: ProxyOptions,
+    tls: TlsOptions,
+    http_version: Option<HttpVersion>,
+    /// `HOST:PORT:CONNECT-TO-HOST:CONNECT-TO-PORT` entries.
+    connect_to: Vec<String>,
+    dns: DnsOptions,
+    connection: ConnectionOptions,
+    max_retries: u32,
+    fail_on_status: bool,
+    /// Search HTML bodies for a `<meta>` charset.
+    sniff_charset: bool,
+    /// Request compressed bodies and decode them while they are received.
+    decompress: bool,
+    /// Keep the body as received, its `Content-Encoding` not decoded.
+    raw_content: bool,
+    /// Accept HTTP/0.9 responses, without status line nor headers.
+    allow_http09: bool,
+    /// Read the body until the connection closes.
+    ignore_content_length: bool,
+    attempts: u32,
+    path: Option<String>,
+    preserve_mtime: bool,
+    resume: bool,
+    if_modified_since: Option<i64>,
+    /// The file sent as the body, with `PUT` for HTTP.
+    upload: Option<String>,
+    /// Create the missing directories of an FTP or SFTP upload.
+    create_dirs: bool,
+    /// Abort the transfer once the headers are received.
+    headers_only: bool,
+    limits: ResponseLimits,
+    /// The Python buffer the body is written to.
+    into: Option<Arc<PyBuffer<u8>>>,
+    /// Send the response once the body starts, and the body in chunks,
+    /// with at most this many chunks waiting to be read.
+    stream: Option<usize>,
+    /// The user agent whose robots.txt rules the request must follow.
+    robots_agent: Option<String>,
+    /// Set on the internal requests downloading a robots.txt file.
+    robots_for: Option<RobotsKey>,
+    /// Set on the internal HEAD requests of `preconnect()`.
+    preconnect: bool,
+    labels: Labels,
+    queued: Option<Timestamp>,
+    started: Option<Timestamp>,
+    span: Option<SpanContext>,
+    inject_traceparent: bool,
+    /// Bounds all the attempts of the request, with the time spent waiting
+    /// in the queues.
+    deadline: Option<Instant>,
+    /// Abort the transfer once it receives nothing for this long, the
+    /// time it is paused excluded.
+    inactivity_timeout: Option<Duration>,
+    /// Called with the headers of each attempt, once they are received.
+    on_headers: Option<Arc<PyObject>>,
+    /// Follow the redirects, up to this many.
+    max_redirects: Option<u32>,
+    /// Called with each redirect, to allow, deny or rewrite it.
+    on_redirect: Option<Arc<PyObject>>,
+    /// The URLs redirected from, in order.
+    redirects: Vec<String>,
+    /// The URL as given, when the rewrite rules changed it.
+    original_url: Option<String>,
+    /// Run on the body of the response once it is received.
+    process: Vec<Processor>,
+    /// Computed on the body as it is received.
+    digests: Vec<Algorithm>,
+    /// The ranges requested, like `0-99,200-299`.
+    ranges: Option<String>,
+}
+
+/// The responses a request accepts, checked as they are received.
+#[derive(Clone, Default)]
+struct ResponseLimits {
+    /// Lowercase media types, like `text/html` or `image/*`, of the
+    /// successful responses.
+    content_types: Option<Vec<String>>,
+    max_length: Option<u64>,
+    /// The size of the header block of a response, its status line
+    /// included, and its number of headers.
+    max_header_bytes: Option<usize>,
+    max_headers: Option<usize>,
+}
+
+impl ResponseLimits {
+    fn accepts(&self, content_type: &str) -> bool {
+        let Some(types) = &self.content_types else {
+            return true;
+        };
+        let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        let major = mime.split('/').next().unwrap_or_default();
+        types.iter().any(|accepted| match accepted.strip_suffix("/*") {
+            Some("*") => true,
+            Some(accepted_major) => accepted_major == major,
+            None => *accepted == mime,
+        })
+    }
+}
+
+/// Proxy settings of a request.
+#[derive(Clone, Default)]
+struct ProxyOptions {
+    url: Option<String>,
+    tunnel: bool,
+    cainfo: Option<String>,
+    cert: Option<String>,
+    key: Option<String>,
+    tls: TlsOptions,
+}
+
+/// How the connections of a request are reused.
+#[derive(Clone, Copy, Default)]
+struct ConnectionOptions {
+    /// Connections idle for longer are not reused.
+    max_age: Option<Duration>,
+    /// Connections opened for longer are not reused.
+    max_lifetime: Option<Duration>,
+    /// Open a new connection rather than reusing one.
+    fresh_connect: bool,
+    /// Close the connection after the transfer.
+    forbid_reuse: bool,
+}
+
+impl ConnectionOptions {
+    fn apply<H: Handler>(&self, easy: &mut Easy2<H>) -> Result<(), curl::Error> {
+        if let Some(max_age) = self.max_age {
+            easy.maxage_conn(max_age)?;
+        }
+        if let Some(max_lifetime) = self.max_lifetime {
+            curl_ext::setopt_long(easy, curl_ext::MAXLIFETIME_CONN, max_lifetime.as_secs() as c_long)?;
+        }
+        if self.fresh_connect {
+            easy.fresh_connect(true)?;
+        }
+        if self.forbid_reuse {
+            easy.forbid_reuse(true)?;
+        }
+        Ok(())
+    }
+}
+
+/// The resolvers used for the DNS resolution, with c-ares, and how long
+/// their answers are cached.
+#[derive(Clone, Default)]
+struct DnsOptions {
+    /// `host[:port]` entries separated by commas.
+    servers: Option<String>,
+    interface: Option<String>,
+    local_ip4: Option<String>,
+    local_ip6: Option<String>,
+    /// 60 seconds by default, zero to resolve the host of every request.
+    cache_timeout: Option<Duration>,
+}
+
+impl DnsOptions {
+    fn new(
+        servers: Option<Vec<String>>,
+        interface: Option<String>,
+        local_ip4: Option<String>,
+        local_ip6: Option<String>,
+    ) -> PyResult<Self> {
+        let options = DnsOptions {
+            servers: servers.filter(|servers| !servers.is_empty()).map(|servers| servers.join(",")),
+            interface,
+            local_ip4,
+            local_ip6,
+            cache_timeout: None,
+        };
+        let is_set = options.servers.is_some()
+            || options.interface.is_some()
+            || options.local_ip4.is_some()
+            || options.local_ip6.is_some();
+        if is_set && curl::Version::get().ares_version().is_none() {
+            return Err(PyValueError::new_err("the DNS options require a libcurl built with c-ares"));
+        }
+        Ok(options)
+    }
+
+    fn apply<H: Handler>(&self, easy: &mut Easy2<H>) -> Result<(), curl::Error> {
+        if let Some(servers) = &self.servers {
+            easy.dns_servers(servers)?;
+        }
+        if let Some(interface) = &self.interface {
+            curl_ext::setopt_str(easy, curl_ext::DNS_INTERFACE, interface)?;
+        }
+        if let Some(ip) = &self.local_ip4 {
+            curl_ext::setopt_str(easy, curl_ext::DNS_LOCAL_IP4, ip)?;
+        }
+        if let Some(ip) = &self.local_ip6 {
+            curl_ext::setopt_str(easy, curl_ext::DNS_LOCAL_IP6, ip)?;
+        }
+        if let Some(timeout) = self.cache_timeout {
+            easy.dns_cache_timeout(timeout)?;
+        }
+        Ok(())
+    }
+}
+
+impl Request {
+    /// Whether the response should be retried.
+    fn is_failure(&self, response: &Response) -> bool {
+        response.status_code < 0 || (self.fail_on_status && response.status_code >= 400)
+    }
+
+    /// The request following a redirect of a response with `status_code`
+    /// to `location`, None if `on_redirect` denies it.
+    ///
+    /// Only HTTP(S) targets are followed, as curl does by default: a
+    /// redirect to `file://` would read a local file.
+    fn follow(&self, status_code: u32, location: &str) -> Result<Option<Request>, String> {
+        let max_redirects = self.max_redirects.unwrap_or_default();
+        if self.redirects.len() as u32 >= max_redirects {
+            return Err(format!("too many redirects ({})", max_redirects));
+        }
+        let invalid = |_| format!("invalid redirect location: {:?}", location);
+        let mut target = Url::parse(&self.url).and_then(|url| url.join(location)).map_err(invalid)?;
+        if let Some(callback) = &self.on_redirect {
+            let verdict =
+                Python::with_gil(|py| redirect_verdict(py, callback, &self.url, target.as_str(), status_code));
+            let Some(url) = verdict else {
+                return Ok(None);
+            };
+            target = Url::parse(&url).map_err(|_| format!("invalid redirect URL: {:?}", url))?;
+        }
+        if !matches!(target.scheme(), "http" | "https") {
+            return Err(format!("redirect to a disallowed scheme: {}", target));
+        }
+        let mut request = self.clone();
+        request.redirects.push(self.url.clone());
+        if http_origin(target.as_str()) != http_origin(&self.url) {
+            // The credentials are for the origin they were given for: not
+            // another host or port, nor plain HTTP after HTTPS.
+            request.credentials = None;
+            for name in ["Authorization", "Cookie", "Proxy-Authorization"] {
+                headers::remove(&mut request.headers, name);
+            }
+        }
+        if status_code == 303 || (matches!(status_code, 301 | 302) && request.body.is_some()) {
+            // As browsers do, the redirect is followed with a GET.
+            request.body = None;
+            request.upload = None;
+            headers::remove(&mut request.headers, "Content-Type");
+        }
+        request.url = target.into();
+        Ok(Some(request))
+    }
+
+    /// What `Transfers::fail()` needs to fail the request once it is gone,
+    /// after a panic.
+    fn origin(&self) -> Request {
+        Request {
+            sequence: self.sequence,
+            url: self.url.clone(),
+            robots_for: self.robots_for.clone(),
+            preconnect: self.preconnect,
+            labels: self.labels.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the downloader made the request for itself, without a
+    /// response for Python.
+    fn is_internal(&self) -> bool {
+        self.robots_for.is_some() || self.preconnect
+    }
+
+    /// Whether the deadline passed at `now`, the instant of the clock of
+    /// the downloader.
+    fn expired(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| now >= deadline)
+    }
+
+    /// The time left before the deadline, at least a millisecond as a zero
+    /// timeout means none for curl.
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        let deadline = self.deadline?;
+        Some(deadline.saturating_duration_since(now).max(Duration::from_millis(1)))
+    }
+
+    /// The collector receiving the body of the transfer with this token,
+    /// and reading the file to upload.
+    fn collector(&self, token: usize, buffers: &BufferPool) -> std::io::Result<Collector> {
+        let mut collector = Collector {
+            headers_only: self.headers_only,
+            follow_redirects: self.max_redirects.is_some(),
+            limits: self.limits.clone(),
+            digests: Digests::new(&self.digests),
+            inactivity_timeout: self.inactivity_timeout,
+            ..Default::default()
+        };
+        if let Some(buffer) = &self.into {
+            collector.into = Some(Target { buffer: buffer.clone(), written: 0 });
+        } else if let Some(path) = &self.path {
+            collector.file = Some(if self.resume { PartFile::resume(path)? } else { PartFile::create(path, token)? });
+        } else if let Some(max_chunks) = self.stream {
+            collector.stream = Some(Arc::new(Stream::new(token, max_chunks)));
+        } else {
+            collector.data = buffers.get();
+        }
+        if let Some(path) = &self.upload {
+            // Opened for each attempt, so a retry sends the file again.
+            collector.upload = Some(File::open(path)?);
+        }
+        Ok(collector)
+    }
+
+    /// Create the curl easy handle performing this request, at `now`.
+    fn to_easy(&self, collector: Collector, now: Instant) -> Result<Easy2<Collector>, curl::Error> {
+        let mut request = Easy2::new(collector);
+        self.configure(&mut request, now)?;
+        Ok(request)
+    }
+
+    /// Set the options of an easy handle, with the collector of this
+    /// request already in place, its timeout the time left at `now`.
+    fn configure(&self, request: &mut Easy2<Collector>, now: Instant) -> Result<(), curl::Error> {
+        let version = curl::Version::get();
+        let mut headers = self.headers.clone();
+        if let (Some(span), true) = (&self.span, self.inject_traceparent) {
+            headers.push(span.traceparent());
+        }
+        let collector = request.get_ref();
+        let mut resume_from = 0;
+        if let Some(file) = &collector.file {
+            if let (offset @ 1.., Some(etag)) = (file.offset(), file.etag()) {
+                resume_from = offset;
+                headers.push(format!("If-Range: {}", etag));
+            }
+        }
+
+        let uploading = collector.upload.is_some();
+        let upload_size = collector.upload.as_ref().and_then(|file| file.metadata().ok()).map(|metadata| metadata.len());
+
+        request.url(&self.url)?;
+        request.useragent(&format!("curl/{}", version.version()))?;
+        if !headers.is_empty() {
+            let mut list = List::new();
+            for header in &headers {
+                list.append(header)?;
+            }
+            request.http_headers(list)?;
+        }
+        if resume_from > 0 {
+            request.resume_from(resume_from)?;
+        }
+        if let Some(ranges) = &self.ranges {
+            request.range(ranges)?;
+        }
+        if self.decompress {
+            // An empty value lists all the encodings libcurl supports.
+            request.accept_encoding("")?;
+        }
+        if self.raw_content {
+            request.http_content_decoding(false)?;
+        }
+        if self.allow_http09 {
+            request.http_09_allowed(true)?;
+        }
+        if self.ignore_content_length {
+            request.ignore_content_length(true)?;
+        }
+        if let Some(body) = &self.body {
+            request.post_fields_copy(body)?;
+        }
+        if uploading {
+            request.upload(true)?;
+            if let Some(size) = upload_size {
+                request.in_filesize(size)?;
+            }
+        }
+        if self.create_dirs {
+            curl_ext::setopt_long(
+                request,
+                curl_sys::CURLOPT_FTP_CREATE_MISSING_DIRS,
+                curl_ext::FTP_CREATE_DIR_RETRY,
+            )?;
+        }
+        if let Some((username, password)) = &self.credentials {
+            request.username(username)?;
+            request.password(password)?;
+        }
+        if let Some(scheme) = self.auth_scheme {
+            request.http_auth(&scheme.to_auth())?;
+        }
+        if let Some(proxy) = &self.proxy.url {
+            request.proxy(proxy)?;
+        }
+        request.http_proxy_tunnel(self.proxy.tunnel)?;
+        if let Some(cainfo) = &self.proxy.cainfo {
+            request.proxy_cainfo(cainfo)?;
+        }
+        if let Some(cert) = &self.proxy.cert {
+            request.proxy_sslcert(cert)?;
+        }
+        if let Some(key) = &self.proxy.key {
+            request.proxy_sslkey(key)?;
+        }
+        self.proxy.tls.apply_to_proxy(request)?;
+        self.tls.apply(request)?;
+        if let Some(version) = self.http_version {
+            request.http_version(version)?;
+        }
+        if !self.connect_to.is_empty() {
+            let mut list = List::new();
+            for entry in &self.connect_to {
+                list.append(entry)?;
+            }
+            request.connect_to(list)?;
+        }
+        self.dns.apply(request)?;
+        self.connection.apply(request)?;
+        if self.path.is_some() {
+            request.fetch_filetime(true)?;
+        }
+        if let Some(time) = self.if_modified_since {
+            request.time_condition(TimeCondition::IfModifiedSince)?;
+            request.time_value(time)?;
+        }
+        if self.robots_for.is_some() {
+            request.follow_location(true)?;
+            request.max_redirections(5)?;
+        }
+        if self.preconnect {
+            request.nobody(true)?;
+        }
+        if let Some(remaining) = self.remaining(now) {
+            request.timeout(remaining)?;
+        }
+        if self.inactivity_timeout.is_some() {
+            // A low speed limit makes curl run the transfer every second,
+            // so the progress callback checks its inactivity even when no
+            // socket is ready; the low speed time is never reached, about
+            // 23 days, multiplied by 1000 in a 32-bit long on Windows.
+            request.progress(true)?;
+            request.low_speed_limit(1)?;
+            request.low_speed_time(Duration::from_secs(2_000_000))?;
+        }
+        Ok(())
+    }
+
+    /// The response of a completed transfer of this request.
+    ///
+    /// A successful download replaces the destination file, the temporary
+    /// file is removed otherwise.
+    fn response(&self, easy: &mut Easy2<Collector>, result: Result<(), curl::Error>) -> Response {
+        let result = match result {
+            // Aborted on purpose, once the headers were received.
+            Err(error) if error.is_write_error() && self.headers_only && easy.get_ref().headers_complete => Ok(()),
+            result => result,
+        };
+        let collector = easy.get_ref();
+        let content_length = collector
+            .header_value("Content-Length")
+            .and_then(|length| length.trim().parse::<u64>().ok());
+        let size_downloaded = easy.download_size().unwrap_or(0.0) as u64;
+        let body_expected = !self.headers_only && !matches!(collector.status, 204 | 304);
+        let cut_short = result.is_err() && collector.body_started;
+        let mut response = match result {
+            Ok(()) => {
+                let http_status = easy
+                    .response_code()
+                    .expect("HTTP request finished without status code");
+                let collector = easy.get_mut();
+                let data = std::mem::take(&mut collector.data);
+                let encoding = charset::detect(collector.header_value("Content-Type"), &data, self.sniff_charset);
+                let nbytes = collector.into.as_ref().map(|target| target.written);
+                let headers = std::mem::take(&mut collector.headers);
+                let digests = std::mem::take(&mut collector.digests).finish();
+
+                Response {
+                    sequence: self.sequence,
+                    url: self.url.clone(),
+                    status_code: http_status as i64,
+                    headers,
+                    data,
+                    compressed: None,
+                    encoding,
+                    path: None,
+                    filetime: if self.path.is_some() { easy.filetime().unwrap_or(None) } else { None },
+                    not_modified: http_status == 304 || easy.time_condition_unmet().unwrap_or(false),
+                    http_version: curl_ext::getinfo_long(easy, curl_ext::INFO_HTTP_VERSION)
+                        .ok()
+                        .and_then(http_version_name),
+                    error: None,
+                    category: None,
+                    curl_code: None,
+                    span: None,
+                    queued: None,
+                    started: None,
+                    finished: None,
+                    stream: None,
+                    nbytes,
+                    redirects: self.redirects.clone(),
+                    original_url: self.original_url.clone(),
+                    processed: Vec::new(),
+                    digests,
+                    content_length: None,
+                    size_downloaded: 0,
+                    truncated: false,
+                    parts: None,
+                }
+            }
+            Err(error) => match easy.get_ref().aborted() {
+                Some(reason) => Response::error(self, reason),
+                None => Response::error(self, error),
+            },
+        };
+        if response.status_code == 206 && self.ranges.is_some() && self.path.is_none() && self.into.is_none() {
+            match byteranges::parse(&response.headers, &response.data) {
+                Ok(parts) => response.parts = Some(parts),
+                // The body stays as received.
+                Err(error) => log::warn!("cannot split the 206 response from {}: {}", self.url, error),
+            }
+        }
+        response.queued = self.queued;
+        response.started = self.started;
+        response.finished = Some(Timestamp::now());
+        response.content_length = content_length;
+        response.size_downloaded = size_downloaded;
+        response.truncated =
+            body_expected && (cut_short || content_length.is_some_and(|length| size_downloaded < length));
+
+        if let Some(mut file) = easy.get_mut().file.take() {
+            if response.not_modified {
+                // The file already there is up to date.
+                response.path = self.path.clone();
+            } else if response.status_code == 416 {
+                // The partial download does not match the resource anymore.
+                let _ = file.restart(None);
+            } else if response.status_code >= 0 && response.status_code < 400 {
+                let mtime = if self.preserve_mtime { response.filetime } else { None };
+                match file.commit(mtime) {
+                    Ok(()) => response.path = self.path.clone(),
+                    Err(error) => response = Response::error(self, error),
+                }
+            }
+        }
+        response
+    }
+
+    /// The span of the request, finished with the last transfer `easy`
+    /// made, when it is traced.
+    fn span(&self, easy: &Easy2<Collector>) -> Option<Span> {
+        let (Some(context), Some(queued), Some(started)) = (self.span, self.queued, self.started) else {
+            return None;
+        };
+        Some(Span {
+            context,
+            queued,
+            started,
+            finished: Timestamp::now(),
+            timings: Timings {
+                namelookup: easy.namelookup_time().unwrap_or_default(),
+                connect: easy.connect_time().unwrap_or_default(),
+                appconnect: easy.appconnect_time().unwrap_or_default(),
+                starttransfer: easy.starttransfer_time().unwrap_or_default(),
+                total: easy.total_time().unwrap_or_default(),
+            },
+            attempts: self.attempts + 1,
+            labels: self.labels.clone(),
+        })
+    }
+
+    /// Run the processors of the request on the body of its response.
+    fn process(&self, response: &mut Response) {
+        if response.status_code >= 0 {
+            response.processed = self
+                .process
+                .iter()
+                .map(|processor| (*processor, processor.run(&response.data, &response.url)))
+                .collect();
+        }
+    }
+}
 
 struct Response {
+    /// The `sequence` of the request.
+    sequence: u64,
     url: String,
     status_code: i64,
+    headers: Vec<(String, String)>,
+    data: Vec<u8>,
+    /// The size of the body `data` holds compressed, while the response
+    /// waits to be fetched with `compress_buffered`.
+    compressed: Option<usize>,
+    /// The Python codec decoding `data`, when known.
+    encoding: Option<String>,
+    path: Option<String>,
+    /// The modification time of the downloaded file, in seconds since the
+    /// epoch.
+    filetime: Option<i64>,
+    not_modified: bool,
+    http_version: Option<&'static str>,
+    error: Option<String>,
+    /// What kind of failure `error` is, and the curl error code.
+    category: Option<Category>,
+    curl_code: Option<u32>,
+    span: Option<Span>,
+    /// When the request was added, when its last transfer started and when
+    /// the response was received.
+    queued: Option<Timestamp>,
+    started: Option<Timestamp>,
+    finished: Option<Timestamp>,
+    /// The body of a streamed response.
+    stream: Option<Arc<Stream>>,
+    /// The size of the body written to the buffer of `into`.
+    nbytes: Option<usize>,
+    /// The URLs redirected from, in order.
+    redirects: Vec<String>,
+    original_url: Option<String>,
+    /// The results of the processors of the request, or why they failed.
+    processed: Vec<(Processor, Result<Output, String>)>,
+    /// The hex digests of the body.
+    digests: Vec<(Algorithm, String)>,
+    /// The size of the body announced by `Content-Length`, and the one
+    /// received, both before decoding.
+    content_length: Option<u64>,
+    size_downloaded: u64,
+    /// Whether the body received is shorter than announced, or the
+    /// transfer failed in its middle.
+    truncated: bool,
+    /// The ranges of a 206 response.
+    parts: Option<Vec<byteranges::Part>>,
+}
+
+impl Response {
+    fn error(task: &Request, error: impl Into<Failure>) -> Self {
+        Response {
+            redirects: task.redirects.clone(),
+            original_url: task.original_url.clone(),
+            queued: task.queued,
+            started: task.started,
+            finished: Some(Timestamp::now()),
+            ..Response::failure(&task.url, task.sequence, error)
+        }
+    }
+
+    fn failure(url: &str, sequence: u64, error: impl Into<Failure>) -> Self {
+        let failure = error.into();
+        Response {
+            sequence,
+            url: url.to_owned(),
+            status_code: -1,
+            headers: Vec::new(),
+            data: Vec::new(),
+            compressed: None,
+            encoding: None,
+            path: None,
+            filetime: None,
+            not_modified: false,
+            http_version: None,
+            error: Some(failure.message),
+            category: Some(failure.category),
+            curl_code: failure.curl_code,
+            span: None,
+            queued: None,
+            started: None,
+            finished: None,
+            stream: None,
+            nbytes: None,
+            redirects: Vec::new(),
+            original_url: None,
+            processed: Vec::new(),
+            digests: Vec::new(),
+            content_length: None,
+            size_downloaded: 0,
+            truncated: false,
+            parts: None,
+        }
+    }
+}
+
+
+/// The most memory reserved for a body from its `Content-Length`.
+const MAX_PREALLOCATED_BYTES: usize = 64 * 1024 * 1024;
+
+/// Receives the body of a transfer, in memory or in a file, and reads the
+/// file to upload.
+#[derive(Default)]
+struct Collector {
     data: Vec<u8>,
+    file: Option<PartFile>,
+    upload: Option<File>,
+    /// Where the body goes instead of `data` when it is streamed.
+    stream: Option<Arc<Stream>>,
+    status: u32,
+    /// The HTTP version of the status line.
+    version: Option<&'static str>,
+    headers: Vec<(String, String)>,
+    /// Whether the headers of the final response were all received.
+    headers_complete: bool,
+    body_started: bool,
+    /// Set to abort the transfer once its body starts.
+    abort: Arc<AtomicBool>,
+    /// Abort the transfer once the headers are received.
+    headers_only: bool,
+    /// Whether the redirects are followed, so their body is discarded.
+    follow_redirects: bool,
+    limits: ResponseLimits,
+    /// Why the response was refused by `limits`.
+    rejected: Option<(Category, String)>,
+    /// The number of bytes of the body received.
+    received: u64,
+    /// The size of the header block received, and its number of headers.
+    header_bytes: usize,
+    header_count: usize,
+    /// Where the body goes instead of `data` for `into`.
+    into: Option<Target>,
+    digests: Digests,
+    /// When the Python signals were last checked, for a transfer run in a
+    /// Python thread, and the exception a signal handler raised.
+    signals_checked: Option<Instant>,
+    interrupted: Option<PyErr>,
+    inactivity_timeout: Option<Duration>,
+    /// When bytes were last received or sent, and whether the transfer is
+    /// paused, its stream full.
+    last_activity: Option<Instant>,
+    paused: bool,
+}
+
+/// A writable Python buffer receiving a body.
+struct Target {
+    buffer: Arc<PyBuffer<u8>>,
+    /// The number of bytes written from its start.
+    written: usize,
+}
+
+impl Collector {
+    fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .rev()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The `Location` of a redirect to follow.
+    fn redirect_location(&self) -> Option<&str> {
+        if !self.follow_redirects || !matches!(self.status, 301 | 302 | 303 | 307 | 308) {
+            return None;
+        }
+        self.header_value("Location")
+    }
+
+    /// Why the transfer was aborted on purpose, if it was.
+    fn aborted(&self) -> Option<(Category, &str)> {
+        if self.abort.load(Ordering::Relaxed) {
+            Some((Category::Cancelled, ABORTED))
+        } else {
+            self.rejected.as_ref().map(|(category, reason)| (*category, reason.as_str()))
+        }
+    }
+
+    /// Why the headers of the final response are refused by `limits`.
+    ///
+    /// Only successful responses must have an accepted media type.
+    fn check_headers(&self) -> Option<(Category, String)> {
+        let length = self.header_value("Content-Length").and_then(|length| length.trim().parse::<u64>().ok());
+        if let (Some(length), Some(max_length)) = (length, self.limits.max_length) {
+            if length > max_length {
+                let reason = format!("Content-Length {} is over max_content_length ({} bytes)", length, max_length);
+                return Some((Category::TooLarge, reason));
+            }
+        }
+        let content_type = self.header_value("Content-Type").unwrap_or_default();
+        if (200..300).contains(&self.status) && !self.limits.accepts(content_type) {
+            return Some((Category::Policy, format!("unexpected Content-Type: {:?}", content_type)));
+        }
+        None
+    }
+
+    /// Called before the first byte of the body is written.
+    ///
+    /// A body kept in memory gets the room its `Content-Length` announces
+    /// up front, at most `max_content_length` or `MAX_PREALLOCATED_BYTES`
+    /// as the server may lie, instead of growing as it is received.
+    fn start_body(&mut self) -> std::io::Result<()> {
+        let etag = self.header_value("ETag").map(str::to_owned);
+        if let Some(file) = &mut self.file {
+            // Anything but a partial response replaces what was downloaded.
+            if self.status != 206 || file.offset() == 0 {
+                file.restart(etag.as_deref())?;
+            } else {
+                // The digests cover the whole file.
+                file.read_downloaded(|chunk| self.digests.update(chunk))?;
+            }
+        } else if self.stream.is_none() {
+            let length = self.header_value("Content-Length").and_then(|length| length.trim().parse::<u64>().ok());
+            if let Some(length) = length {
+                let length = length.min(self.limits.max_length.unwrap_or(u64::MAX));
+                self.data.reserve_exact(length.min(MAX_PREALLOCATED_BYTES as u64) as usize);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Handler for Collector {
+    fn header(&mut self, data: &[u8]) -> bool {
+        self.last_activity = Some(Instant::now());
+        let line = String::from_utf8_lossy(data);
+        let line = line.trim_end();
+        if line.starts_with("HTTP/") {
+            self.header_bytes = 0;
+            self.header_count = 0;
+        }
+        self.header_bytes += data.len();
+        if let Some(max_bytes) = self.limits.max_header_bytes.filter(|max_bytes| self.header_bytes > *max_bytes) {
+            let reason = format!("the headers are over max_header_bytes ({} bytes)", max_bytes);
+            self.rejected = Some((Category::TooLarge, reason));
+            return false;
+        }
+        if line.starts_with("HTTP/") {
+            // A new response begins: an interim one, or one after a redirect.
+            self.status = line
+                .split_whitespace()
+                .nth(1)
+                .and_then(|code| code.parse().ok())
+                .unwrap_or(0);
+            self.version = match line.split_whitespace().next() {
+                Some("HTTP/1.0") => Some("1.0"),
+                Some("HTTP/1.1") => Some("1.1"),
+                Some("HTTP/2") => Some("2"),
+                Some("HTTP/3") => Some("3"),
+                _ => None,
+            };
+            self.headers.clear();
+            self.headers_complete = false;
+            self.body_started = false;
+        } else if line.is_empty() {
+            // Interim responses and followed redirects are followed by the
+            // final one.
+            self.headers_complete = self.status >= 200 && self.redirect_location().is_none();
+            if self.headers_complete {
+                self.rejected = self.check_headers();
+                if self.rejected.is_some() || self.headers_only {
+                    return false;
+                }
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            self.header_count += 1;
+            if let Some(max_headers) = self.limits.max_headers.filter(|max_headers| self.header_count > *max_headers) {
+                let reason = format!("the response has more than max_headers ({}) headers", max_headers);
+                self.rejected = Some((Category::TooLarge, reason));
+                return false;
+            }
+            self.headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+        true
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, WriteError> {
+        self.last_activity = Some(Instant::now());
+        self.paused = false;
+        if self.abort.load(Ordering::Relaxed) {
+            return Ok(0);
+        }
+        if self.redirect_location().is_some() {
+            return Ok(data.len());
+        }
+        self.received += data.len() as u64;
+        if let Some(max_length) = self.limits.max_length.filter(|max_length| self.received > *max_length) {
+            let reason = format!("the body is over max_content_length ({} bytes)", max_length);
+            self.rejected = Some((Category::TooLarge, reason));
+            return Ok(0);
+        }
+        if !self.body_started {
+            self.body_started = true;
+            if self.start_body().is_err() {
+                return Ok(0);
+            }
+        }
+        if let Some(target) = &mut self.into {
+            let end = target.written + data.len();
+            if end > target.buffer.len_bytes() {
+                let size = target.buffer.len_bytes();
+                let reason = format!("the body is larger than the buffer ({} bytes)", size);
+                self.rejected = Some((Category::TooLarge, reason));
+                return Ok(0);
+            }
+            // SAFETY: the buffer is writable, contiguous and held until the
+            // target is dropped.
+            unsafe {
+                let start = (target.buffer.buf_ptr() as *mut u8).add(target.written);
+                ptr::copy_nonoverlapping(data.as_ptr(), start, data.len());
+            }
+            target.written = end;
+            self.digests.update(data);
+            return Ok(data.len());
+        }
+        if let Some(stream) = &self.stream {
+            return match stream.push(data) {
+                Push::Queued => {
+                    self.digests.update(data);
+                    Ok(data.len())
+                }
+                // curl gives the same data again once unpaused.
+                Push::Full => {
+                    self.paused = true;
+                    Err(WriteError::Pause)
+                }
+                Push::Closed => Ok(0),
+            };
+        }
+        match &mut self.file {
+            Some(file) => {
+                if file.write(data).is_err() {
+                    // A short count makes curl abort with CURLE_WRITE_ERROR.
+                    return Ok(0);
+                }
+            }
+            None => self.data.extend_from_slice(data),
+        }
+        self.digests.update(data);
+        Ok(data.len())
+    }
+
+    fn read(&mut self, data: &mut [u8]) -> Result<usize, ReadError> {
+        self.last_activity = Some(Instant::now());
+        match &mut self.upload {
+            Some(file) => file.read(data).map_err(|_| ReadError::Abort),
+            None => Ok(0),
+        }
+    }
+
+    /// Abort a transfer inactive for too long, and a transfer run in a
+    /// Python thread when a signal handler raises, like `KeyboardInterrupt`
+    /// for Ctrl-C.
+    fn progress(&mut self, _dltotal: f64, _dlnow: f64, _ultotal: f64, _ulnow: f64) -> bool {
+        if let Some(timeout) = self.inactivity_timeout {
+            let last_activity = *self.last_activity.get_or_insert_with(Instant::now);
+            if !self.paused && last_activity.elapsed() >= timeout {
+                self.rejected = Some((Category::Timeout, INACTIVITY_TIMEOUT.to_owned()));
+                return false;
+            }
+        }
+        let Some(checked) = self.signals_checked else {
+            return true;
+        };
+        if checked.elapsed() < Duration::from_millis(100) {
+            return true;
+        }
+        self.signals_checked = Some(Instant::now());
+        match Python::with_gil(|py| py.check_signals()) {
+            Ok(()) => true,
+            Err(error) => {
+                self.interrupted = Some(error);
+                false
+            }
+        }
+    }
+
+    /// Rewind the upload, to send it again after a redirect or an
+    /// authentication challenge.
+    fn seek(&mut self, whence: SeekFrom) -> SeekResult {
+        match &mut self.upload {
+            Some(file) => match file.seek(whence) {
+                Ok(_) => SeekResult::Ok,
+                Err(_) => SeekResult::Fail,
+            },
+            None => SeekResult::CantSeek,
+        }
+    }
+}
+
+/// How the downloader thread loop schedules requests.
+#[derive(Clone, Copy)]
+struct SchedulerConfig {
+    /// How long the thread waits for socket activity while transfers are
+    /// running, at most.
+    poll_interval: Duration,
+    /// How long to block for a new request when there is nothing to do.
+    idle_timeout: Duration,
+    /// The maximum number of requests taken from the queue per iteration.
+    batch_size: usize,
+    /// No new transfer starts while the responses not fetched yet hold
+    /// this many bytes.
+    max_buffered_bytes: Option<usize>,
+    /// Whether the bodies of those responses are kept compressed.
+    compress_buffered: bool,
+    /// The maximum number of transfers running at a time, in all and to
+    /// the same host.
+    max_transfers: Option<usize>,
+    max_host_transfers: Option<usize>,
+    retry_backoff: Backoff,
+}
+
+/// Settings of the curl multi handle.
+#[derive(Clone, Copy, Default)]
+struct MultiConfig {
+    /// The size of the connection cache.
+    max_connects: Option<usize>,
+    /// The connections open at a time, in total and to a host.
+    max_total_connections: Option<usize>,
+    max_host_connections: Option<usize>,
+    /// The HTTP/2 streams of a connection.
+    max_concurrent_streams: Option<usize>,
+    /// Whether transfers share HTTP/2 connections, on by default.
+    multiplex: Option<bool>,
+}
+
+impl MultiConfig {
+    fn apply(&self, multi: &mut Multi) -> Result<(), curl::MultiError> {
+        if let Some(max_connects) = self.max_connects {
+            multi.set_max_connects(max_connects)?;
+        }
+        if let Some(max_connections) = self.max_total_connections {
+            multi.set_max_total_connections(max_connections)?;
+        }
+        if let Some(max_connections) = self.max_host_connections {
+            multi.set_max_host_connections(max_connections)?;
+        }
+        if let Some(max_streams) = self.max_concurrent_streams {
+            multi.set_max_concurrent_streams(max_streams)?;
+        }
+        if let Some(multiplex) = self.multiplex {
+            multi.pipelining(false, multiplex)?;
+        }
+        Ok(())
+    }
+}
+
+/// How far a request added by Python got.
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    /// Queued, or waiting for a robots.txt file or for its start time.
+    Pending,
+    InFlight,
+    /// Its response waits to be fetched.
+    Completed,
+}
+
+struct Downloader {
+    scheduler: SchedulerConfig,
+    multi_config: MultiConfig,
+    task_sender: Sender<Request>,
+    task_receiver: Receiver<Request>,
+    response_sender: Sender<Response>,
+    response_receiver: Receiver<Response>,
+    /// The responses going to a callback instead of `fetch()`.
+    callback_sender: Sender<Event>,
+    callback_receiver: Receiver<Event>,
+    /// The callbacks of the requests, by sequence.
+    callbacks: Mutex<HashMap<u64, PyObject>>,
+    /// The requests added by Python whose response was not fetched yet,
+    /// with their URL, by sequence.
+    stages: Mutex<BTreeMap<u64, (Stage, String)>>,
+    running: AtomicBool,
+    stats: Mutex<Stats>,
+    /// Requests accepted by the downloader thread but not started yet.
+    waiting: AtomicUsize,
+    in_flight: AtomicUsize,
+    /// The size of the bodies of the responses not fetched yet.
+    buffered_bytes: AtomicUsize,
+    worker_alive: AtomicBool,
+    /// When the downloader thread last iterated, in milliseconds since
+    /// `created`.
+    heartbeat: AtomicU64,
+    channels_intact: AtomicBool,
+    /// The requests failed for being lost by the downloader thread, and the
+    /// second responses to a request dropped.
+    lost: AtomicUsize,
+    duplicates: AtomicUsize,
+    created: Instant,
+    /// The poller the downloader thread waits on, notified to wake it up.
+    poller: Arc<Poller>,
+    buffers: Arc<BufferPool>,
+    /// Set to close the idle connections once no transfer is running.
+    flush_connections: AtomicBool,
+    /// Set to cancel the requests with a lower sequence.
+    cancel_before: AtomicU64,
+    retry_budget: Option<Mutex<RetryBudget>>,
+    host_quotas: Option<Mutex<HostQuotas>>,
+    host_throttle: Option<Mutex<HostThrottle>>,
+    host_policy: Option<HostPolicy>,
+    stop_conditions: Option<Mutex<StopConditions>>,
+    /// The file the responses are appended to.
+    archive: Option<Mutex<Archive>>,
+    /// Set by `pause()`: no transfer starts, and with `paused_transfers`
+    /// the running ones are paused too.
+    suspended: AtomicBool,
+    paused_transfers: AtomicBool,
+    /// The streamed transfers to resume, now that Python read their
+    /// chunks.
+    unpaused: Mutex<Vec<usize>>,
+    /// The number of transfers performed by `perform()`, whose tokens
+    /// count down from `usize::MAX` to stay apart from the ones of the
+    /// downloader thread.
+    performed: AtomicUsize,
+    /// Set by `_inject_panic()`: the downloader thread panics next time it
+    /// iterates.
+    inject_panic: AtomicBool,
+    /// The clock of the retry backoff and the deadlines, the real one when
+    /// None.
+    clock: Option<Py<ManualClock>>,
+}
+
+impl Downloader {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        scheduler: SchedulerConfig,
+        multi_config: MultiConfig,
+        retry_budget: Option<RetryBudget>,
+        host_quotas: Option<HostQuotas>,
+        host_throttle: Option<HostThrottle>,
+        host_policy: Option<HostPolicy>,
+        stop_conditions: Option<StopConditions>,
+        archive: Option<Archive>,
+        clock: Option<Py<ManualClock>>,
+    ) -> std::io::Result<Self> {
+        let (task_sender, task_receiver) =  unbounded();
+        let (response_sender, response_receiver) = unbounded();
+        let (callback_sender, callback_receiver) = unbounded();
+
+        Ok(Downloader {
+            scheduler,
+            multi_config,
+            task_sender,
+            task_receiver,
+            response_sender,
+            response_receiver,
+            callback_sender,
+            callback_receiver,
+            callbacks: Mutex::new(HashMap::new()),
+            stages: Mutex::new(BTreeMap::new()),
+            running: AtomicBool::new(true),
+            stats: Mutex::new(Stats::default()),
+            waiting: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            buffered_bytes: AtomicUsize::new(0),
+            worker_alive: AtomicBool::new(false),
+            heartbeat: AtomicU64::new(0),
+            channels_intact: AtomicBool::new(true),
+            lost: AtomicUsize::new(0),
+            duplicates: AtomicUsize::new(0),
+            created: Instant::now(),
+            poller: Arc::new(Poller::new()?),
+            buffers: Arc::new(BufferPool::default()),
+            flush_connections: AtomicBool::new(false),
+            cancel_before: AtomicU64::new(0),
+            retry_budget: retry_budget.map(Mutex::new),
+            host_quotas: host_quotas.map(Mutex::new),
+            host_throttle: host_throttle.map(Mutex::new),
+            host_policy,
+            stop_conditions: stop_conditions.map(Mutex::new),
+            archive: archive.map(Mutex::new),
+            suspended: AtomicBool::new(false),
+            paused_transfers: AtomicBool::new(false),
+            unpaused: Mutex::new(Vec::new()),
+            performed: AtomicUsize::new(0),
+            inject_panic: AtomicBool::new(false),
+            clock,
+        })
+    }
+
+    fn add_request(&self, request: Request) -> PyResult<()> {
+        match self.task_sender.send(request) {
+            Err(_) => Err(PyRuntimeError::new_err("Failed to send task")),
+            Ok(_) => {
+                self.wake_up();
+                Ok(())
+            }
+        }
+    }
+
+    /// Perform a request in the calling thread, with its retries, on
+    /// `easy`, created on first use and kept to reuse its connections.
+    ///
+    /// Python signals are handled meanwhile: an exception raised by their
+    /// handler aborts the request.
+    fn perform(
+        &self,
+        easy: &mut Option<Easy2<Collector>>,
+        mut task: Request,
+        timeout: Option<Duration>,
+    ) -> PyResult<Response> {
+        loop {
+            if task.expired(self.now()) {
+                return Ok(Response::error(&task, (Category::Timeout, DEADLINE_EXCEEDED)));
+            }
+            if !self.allows_host(&task.url) {
+                return Ok(Response::error(&task, (Category::Policy, HOST_NOT_ALLOWED)));
+            }
+            if let Some(error) = self.stopped() {
+                return Ok(Response::error(&task, (Category::Policy, error)));
+            }
+            if let Some(until) = self.quota_exceeded(&host_of(&task.url)) {
+                if !self.defers_over_quota() {
+                    return Ok(Response::error(&task, (Category::Policy, QUOTA_EXCEEDED)));
+                }
+                sleep_until(task.deadline.map_or(until, |deadline| deadline.min(until)))?;
+                continue;
+            }
+            if !self.throttle_allows(&host_of(&task.url), 0) {
+                let until = self.throttled_until(&host_of(&task.url)).unwrap_or_else(Instant::now);
+                sleep_until(task.deadline.map_or(until, |deadline| deadline.min(until)))?;
+                continue;
+            }
+            self.count_start();
+            let token = usize::MAX - self.performed.fetch_add(1, Ordering::Relaxed);
+            let collector = match task.collector(token, &self.buffers) {
+                Ok(collector) => collector,
+                Err(error) => return Ok(Response::error(&task, error)),
+            };
+            let request = easy.get_or_insert_with(|| Easy2::new(Collector::default()));
+            request.reset();
+            *request.get_mut() = Collector {
+                signals_checked: Some(Instant::now()),
+                ..collector
+            };
+            task.started = Some(Timestamp::now());
+            let now = self.now();
+            let timeout = timeout.into_iter().chain(task.remaining(now)).min();
+            let result = task
+                .configure(request, now)
+                .and_then(|()| timeout.map_or(Ok(()), |timeout| request.timeout(timeout)))
+                .and_then(|()| request.progress(true))
+                .and_then(|()| request.perform());
+            if let Some(error) = request.get_mut().interrupted.take() {
+                *request.get_mut() = Collector::default();
+                return Err(error);
+            }
+            let location = result.is_ok().then(|| request.get_ref().redirect_location().map(str::to_owned)).flatten();
+            if let Some(location) = location {
+                let status_code = request.get_ref().status;
+                let total_time = request.total_time().unwrap_or_default();
+                let bytes = request.download_size().unwrap_or(0.0) as u64;
+                self.record(&task, status_code as i64, false, total_time, bytes);
+                match task.follow(status_code, &location) {
+                    Ok(Some(next)) => {
+                        *request.get_mut() = Collector::default();
+                        task = next;
+                        continue;
+                    }
+                    Ok(None) => {}
+                    Err(error) => {
+                        *request.get_mut() = Collector::default();
+                        return Ok(Response::error(&task, (Category::Protocol, error)));
+                    }
+                }
+            }
+            let mut response = task.response(request, result);
+            let aborted = response.status_code < 0 && request.get_ref().aborted().is_some();
+            // Close the file to upload.
+            *request.get_mut() = Collector::default();
+
+            let failed = task.is_failure(&response);
+            if failed && !aborted && response.status_code < 0 && task.expired(self.now()) {
+                response.error = Some(DEADLINE_EXCEEDED.to_owned());
+                response.category = Some(Category::Timeout);
+            }
+            let total_time = request.total_time().unwrap_or_default();
+            let bytes = request.download_size().unwrap_or(0.0) as u64;
+            self.record(&task, response.status_code, failed, total_time, bytes);
+            let delay = self.scheduler.retry_backoff.delay(task.attempts);
+            if self.should_retry(&task, failed && !aborted, delay) {
+                self.buffers.put(response.data);
+                self.sleep(delay)?;
+                task.attempts += 1;
+                continue;
+            }
+            response.span = task.span(request);
+            task.process(&mut response);
+            return Ok(response);
+        }
+    }
+
+    /// The instant the deadlines and the retry times are compared to.
+    fn now(&self) -> Instant {
+        self.clock.as_ref().map_or_else(Instant::now, |clock| clock.get().now())
+    }
+
+    /// Wait `delay` before a retry of `perform()`, or advance the clock of
+    /// the downloader by as much.
+    fn sleep(&self, delay: Duration) -> PyResult<()> {
+        match &self.clock {
+            Some(clock) => {
+                clock.get().skip(delay);
+                Ok(())
+            }
+            None => sleep_until(Instant::now() + delay),
+        }
+    }
+
+    /// Account for a response handed to Python, which may make room for
+    /// new transfers.
+    ///
+    /// A body kept compressed is decompressed.
+    fn release(&self, response: &mut Response) {
+        self.stages.lock().unwrap().remove(&response.sequence);
+        let over_budget = self.over_budget();
+        self.buffered_bytes.fetch_sub(response.data.len(), Ordering::Relaxed);
+        if over_budget && !self.over_budget() {
+            self.wake_up();
+        }
+        if let Some(size) = response.compressed.take() {
+            let mut data = self.buffers.get();
+            match compress::decompress(&response.data, size, &mut data) {
+                Ok(()) => response.data = data,
+                Err(error) => {
+                    *response = Response {
+                        span: response.span.take(),
+                        ..Response::failure(&response.url, response.sequence, error)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Call the callbacks with their responses, with `executor`, until the
+    /// downloader is dropped, the spans going to `span_exporter` first.
+    fn run_callbacks(self: Arc<Self>, executor: CallbackExecutor, span_exporter: Option<PyObject>) {
+        while self.running.load(Ordering::Relaxed) {
+            let Ok(event) = self.callback_receiver.recv_timeout(Duration::from_millis(500)) else {
+                continue;
+            };
+            Python::with_gil(|py| {
+                let result = match event {
+                    Event::Response(mut response) => {
+                        self.release(&mut response);
+                        let Some(callback) = self.callbacks.lock().unwrap().remove(&response.sequence) else {
+                            return;
+                        };
+                        if let Some(exporter) = &span_exporter {
+                            export_span(py, exporter, &mut response);
+                        }
+                        let response = ResponsePython::new(*response, &self);
+                        Py::new(py, response).map(|response| (callback, response.into_any()))
+                    }
+                    Event::Headers(callback, headers) => {
+                        Py::new(py, headers).map(|headers| (callback.clone_ref(py), headers.into_any()))
+                    }
+                };
+                let result = result
+                    .and_then(|(callback, argument)| Py::new(py, CallbackCall { callback, argument }))
+                    .and_then(|call| executor.run(py, call));
+                if let Err(error) = result {
+                    error.write_unraisable_bound(py, None);
+                }
+            });
+        }
+    }
+
+    /// Count a finished transfer in the stats and in the quota of its host.
+    fn record(&self, task: &Request, status_code: i64, failed: bool, time: Duration, bytes: u64) {
+        self.stats.lock().unwrap().record(&task.labels, status_code, failed, time, bytes);
+        if let Some(quotas) = &self.host_quotas {
+            quotas.lock().unwrap().record(&host_of(&task.url), bytes, time);
+        }
+        if let Some(conditions) = &self.stop_conditions {
+            conditions.lock().unwrap().record(bytes);
+        }
+        if let Some(throttle) = &self.host_throttle {
+            let started = task.started.map(|started| started.instant);
+            throttle.lock().unwrap().record(&host_of(&task.url), status_code, started);
+        }
+    }
+
+    /// The error of the requests the downloader no longer starts, once a
+    /// stop condition is reached.
+    fn stopped(&self) -> Option<String> {
+        let mut conditions = self.stop_conditions.as_ref()?.lock().unwrap();
+        conditions.reason().map(|reason| format!("{}: {}", DOWNLOADER_STOPPED, reason))
+    }
+
+    /// Count a transfer starting in the stop conditions.
+    fn count_start(&self) {
+        if let Some(conditions) = &self.stop_conditions {
+            conditions.lock().unwrap().start();
+        }
+    }
+
+    fn allows_host(&self, url: &str) -> bool {
+        self.host_policy.as_ref().is_none_or(|policy| policy.allows(&host_of(url)))
+    }
+
+    /// When `host` gets under its quota again, None if it is now.
+    fn quota_exceeded(&self, host: &str) -> Option<Instant> {
+        self.host_quotas.as_ref()?.lock().unwrap().exceeded(host)
+    }
+
+    /// Whether the throttle lets a transfer to `host` start now, with
+    /// `running` ones already; its start is then counted.
+    fn throttle_allows(&self, host: &str, running: usize) -> bool {
+        self.host_throttle
+            .as_ref()
+            .is_none_or(|throttle| throttle.lock().unwrap().try_start(host, running))
+    }
+
+    /// When the throttle lets the next transfer to `host` start, None if
+    /// it is now.
+    fn throttled_until(&self, host: &str) -> Option<Instant> {
+        self.host_throttle.as_ref()?.lock().unwrap().next_start(host)
+    }
+
+    /// Append a response to the archive. The body of a streamed response
+    /// goes to Python only.
+    fn archive(&self, response: &Response) {
+        let Some(archive) = &self.archive else {
+            return;
+        };
+        if response.stream.is_some() {
+            return;
+        }
+        let record = archive::Record {
+            url: &response.url,
+            status_code: (response.status_code >= 0).then_some(response.status_code),
+            http_version: response.http_version,
+            headers: &response.headers,
+            body: &response.data,
+            error: response.error.as_deref(),
+            redirects: &response.redirects,
+            queued: response.queued.map(|queued| queued.wall),
+            started: response.started.map(|started| started.wall),
+            finished: response.finished.unwrap_or_else(Timestamp::now).wall,
+        };
+        if let Err(error) = archive.lock().unwrap().write(&record) {
+            log::warn!("failed to archive {}: {}", response.url, error);
+        }
+    }
+
+    fn defers_over_quota(&self) -> bool {
+        self.host_quotas.as_ref().is_some_and(|quotas| quotas.lock().unwrap().defer)
+    }
+
+    /// Whether a failed transfer of `task` is tried again after `delay`,
+    /// counting its request and its retry in the retry budget.
+    ///
+    /// A retry that would start past the deadline of the request is not.
+    fn should_retry(&self, task: &Request, failed: bool, delay: Duration) -> bool {
+        let retriable = failed
+            && task.attempts < task.max_retries
+            && task.deadline.is_none_or(|deadline| self.now() + delay < deadline);
+        let Some(budget) = &self.retry_budget else {
+            return retriable;
+        };
+        let mut budget = budget.lock().unwrap();
+        if task.attempts == 0 {
+            budget.record_request();
+        }
+        if !retriable {
+            return false;
+        }
+        let allowed = budget.try_retry();
+        if !allowed {
+            log::debug!("retry budget exhausted, not retrying {}", task.url);
+        }
+        allowed
+    }
+
+    /// Hand a response over to `fetch()` or to its callback, unless its
+    /// request already got one: each request added by Python gets exactly
+    /// one response.
+    fn send(&self, mut response: Response) {
+        let first = match self.stages.lock().unwrap().get_mut(&response.sequence) {
+            Some(entry) if entry.0 != Stage::Completed => {
+                entry.0 = Stage::Completed;
+                true
+            }
+            _ => false,
+        };
+        if !first {
+            log::warn!("dropping a second response to {} (request {})", response.url, response.sequence);
+            self.duplicates.fetch_add(1, Ordering::Relaxed);
+            self.buffers.put(response.data);
+            return;
+        }
+        self.archive(&response);
+        if self.scheduler.compress_buffered {
+            if let Some(compressed) = compress::compress(&response.data) {
+                let data = std::mem::replace(&mut response.data, compressed);
+                response.compressed = Some(data.len());
+                self.buffers.put(data);
+            }
+        }
+        self.buffered_bytes.fetch_add(response.data.len(), Ordering::Relaxed);
+        let sent = if self.callbacks.lock().unwrap().contains_key(&response.sequence) {
+            self.callback_sender.send(Event::Response(Box::new(response))).is_ok()
+        } else {
+            self.response_sender.send(response).is_ok()
+        };
+        if !sent {
+            self.channels_intact.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Fail the requests added by Python which the downloader thread lost
+    /// when it panicked: neither queued nor answered.
+    fn fail_lost(&self, error: &str) {
+        // Held while the queue is read and refilled: Python queues its
+        // requests with it held too, a request registered is then queued.
+        let stages = self.stages.lock().unwrap();
+        let queued: Vec<Request> = self.task_receiver.try_iter().collect();
+        let in_queue: HashSet<u64> =
+            queued.iter().filter(|task| !task.is_internal()).map(|task| task.sequence).collect();
+        let lost: Vec<(u64, String)> = stages
+            .iter()
+            .filter(|(sequence, (stage, _))| *stage != Stage::Completed && !in_queue.contains(sequence))
+            .map(|(sequence, (_, url))| (*sequence, url.clone()))
+            .collect();
+        for task in queued {
+            // The receiver lives as long as the sender.
+            let _ = self.task_sender.send(task);
+        }
+        drop(stages);
+        for (sequence, url) in lost {
+            log::error!("request {} to {} lost by the downloader thread", sequence, url);
+            self.lost.fetch_add(1, Ordering::Relaxed);
+            self.send(Response::failure(&url, sequence, error));
+        }
+    }
+
+    fn set_stage(&self, sequence: u64, stage: Stage) {
+        if let Some(entry) = self.stages.lock().unwrap().get_mut(&sequence) {
+            entry.0 = stage;
+        }
+    }
+
+    /// Resume a paused streamed transfer.
+    fn unpause(&self, token: usize) {
+        self.unpaused.lock().unwrap().push(token);
+        self.wake_up();
+    }
+
+    /// Interrupt the wait of the downloader thread for socket activity.
+    fn wake_up(&self) {
+        if let Err(error) = self.poller.notify() {
+            log::warn!("failed to wake up the downloader thread: {}", error);
+        }
+    }
+
+    fn get_task(&self, processing_requests: bool) -> Result<Request, std::sync::mpsc::RecvError> {
+        if !processing_requests {
+            // block if there is no download
+            match self.task_receiver.recv_timeout(self.scheduler.idle_timeout) {
+                Ok(request) => return Ok(request),
+                Err(_) => return Err(std::sync::mpsc::RecvError),
+            }
+        }
+        match self.task_receiver.try_recv() {
+            Ok(request) => Ok(request),
+            Err(TryRecvError::Disconnected) => {
+                self.channels_intact.store(false, Ordering::Relaxed);
+                Err(std::sync::mpsc::RecvError)
+            }
+            Err(_) => Err(std::sync::mpsc::RecvError),
+        }
+    }
+
+    /// A multi handle with its event loop.
+    fn new_multi(&self) -> (Multi, Reactor) {
+        let mut multi = Multi::new();
+        // Checked when the downloader was created.
+        self.multi_config.apply(&mut multi).unwrap();
+        let reactor = Reactor::attach(&mut multi, self.poller.clone()).expect("failed to create the event loop");
+        (multi, reactor)
+    }
+
+    /// Whether the responses not fetched yet use up the memory budget.
+    fn over_budget(&self) -> bool {
+        self.scheduler
+            .max_buffered_bytes
+            .is_some_and(|budget| self.buffered_bytes.load(Ordering::Relaxed) >= budget)
+    }
+
+    /// How long ago the downloader thread last iterated.
+    fn since_heartbeat(&self) -> Duration {
+        let heartbeat = Duration::from_millis(self.heartbeat.load(Ordering::Relaxed));
+        self.created.elapsed().saturating_sub(heartbeat)
+    }
+
+    fn thread_runner(&self) {
+        // Reset the flag even if the thread panics.
+        struct AliveGuard<'a>(&'a AtomicBool);
+        impl Drop for AliveGuard<'_> {
+            fn drop(&mut self) {
+                self.0.store(false, Ordering::Relaxed);
+            }
+        }
+        self.worker_alive.store(true, Ordering::Relaxed);
+        let _alive = AliveGuard(&self.worker_alive);
+
+        // A panic outside of a single transfer fails all current requests
+        // and restarts the loop with new curl handles.
+        while self.running.load(Ordering::Relaxed) {
+            let mut transfers = Transfers::new(self);
+            match panic::catch_unwind(AssertUnwindSafe(|| self.run(&mut transfers))) {
+                Ok(()) => break,
+                Err(payload) => {
+                    let message = panic_message(&payload);
+                    log::error!("downloader thread panicked, restarting: {}", message);
+                    let error = format!("downloader thread panicked: {}", message);
+                    if panic::catch_unwind(AssertUnwindSafe(|| transfers.fail_all(&error))).is_err() {
+                        log::error!("panic while failing the requests of the downloader thread");
+                    }
+                    // The requests it was handling outside of the transfers,
+                    // or could not fail.
+                    self.fail_lost(&error);
+                }
+            }
+        }
+    }
+
+    fn run(&self, transfers: &mut Transfers) {
+        let mut processing_requests = true;
+        while self.running.load(Ordering::Relaxed) {
+            self.heartbeat.store(self.created.elapsed().as_millis() as u64, Ordering::Relaxed);
+            if self.inject_panic.swap(false, Ordering::Relaxed) {
+                panic!("injected panic");
+            }
+            // Requests stay queued until Python fetches enough responses.
+            let over_budget = self.over_budget();
+            let suspended = self.suspended.load(Ordering::Relaxed);
+            let batch_size = if over_budget || suspended { 0 } else { self.scheduler.batch_size };
+            for _ in 0..batch_size {
+                match self.get_task(processing_requests) {
+                    Ok(task) => {
+                        processing_requests = true;
+                        transfers.submit(task);
+                    }
+                    Err(_) => {
+                        // No more tasks to process.
+                        break;
+                    }
+                }
+            }
+            transfers.schedule();
+
+            if processing_requests || over_budget || suspended {
+                // New requests, fetched responses and the shutdown wake the
+                // thread up.
+                transfers
+                    .reactor
+                    .poll(&transfers.multi, self.scheduler.poll_interval)
+                    .unwrap();
+            }
+            // Before the transfers complete, so the headers come first.
+            transfers.send_headers();
+
+            let mut finished = Vec::new();
+            transfers.multi.messages(|message| {
+                let token = message.token().expect("failed to get the token");
+                let handle = transfers
+                    .handles
+                    .get(&token)
+                    .expect("the download value should exist in the HashMap");
+                if let Some(result) = message.result_for2(handle) {
+                    finished.push((token, result));
+                }
+            });
+            for (token, result) in finished {
+                transfers.finish(token, result);
+            }
+            let cancel_before = self.cancel_before.swap(0, Ordering::Relaxed);
+            if cancel_before > 0 {
+                transfers.cancel(cancel_before);
+            }
+            transfers.pause(self.paused_transfers.load(Ordering::Relaxed));
+            transfers.unpause();
+            transfers.send_heads();
+            transfers.expire();
+            transfers.start_delayed();
+            transfers.schedule();
+            if transfers.handles.is_empty() && self.flush_connections.swap(false, Ordering::Relaxed) {
+                // The connection cache goes with its multi handle.
+                (transfers.multi, transfers.reactor) = self.new_multi();
+            }
+            transfers.update_gauges();
+            // A retry may have been started while finishing transfers, or
+            // requests wait for their crawl delay.
+            processing_requests =
+                !transfers.handles.is_empty() || !transfers.delayed.is_empty() || !transfers.hosts.is_empty();
+        }
+    }
+}
+
+/// The error of the responses of cancelled requests.
+const CANCELLED: &str = "cancelled";
+
+/// The error of the transfers aborted from their `on_headers` callback.
+const ABORTED: &str = "aborted";
+
+/// The error of the requests whose deadline passed.
+const DEADLINE_EXCEEDED: &str = "deadline exceeded";
+/// The error of the transfers receiving nothing for `inactivity_timeout`.
+const INACTIVITY_TIMEOUT: &str = "inactivity timeout";
+
+/// The error of the requests to a host denied by `allowed_hosts` or
+/// `denied_hosts`, redirects included.
+const HOST_NOT_ALLOWED: &str = "host not allowed";
+
+/// The error of the requests rejected for the quota of their host.
+const QUOTA_EXCEEDED: &str = "host quota exceeded";
+/// The start of the error of the requests not started once a stop
+/// condition is reached.
+const DOWNLOADER_STOPPED: &str = "downloader stopped";
+
+/// Identifies a cached robots.txt: the user agent and the origin.
+type RobotsKey = (String, String);
+
+/// How long a robots.txt file is cached.
+const ROBOTS_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long the rules of an unreachable robots.txt are cached after its
+/// first failure, doubling with each failure in a row up to
+/// `ROBOTS_MAX_RETRY_AGE`.
+const ROBOTS_RETRY_AGE: Duration = Duration::from_secs(60);
+const ROBOTS_MAX_RETRY_AGE: Duration = Duration::from_secs(30 * 60);
+
+enum RobotsEntry {
+    /// robots.txt is being downloaded, the requests wait for it.
+    Fetching(Vec<Request>),
+    Ready {
+        robots: Robots,
+        expires: Instant,
+        /// When the next request may start, according to the crawl delay.
+        next_start: Instant,
+    },
+}
+
+/// The transfers currently driven by the downloader thread.
+struct Transfers<'a> {
+    multi: Multi,
+    reactor: Reactor,
+    handles: HashMap<usize, Easy2Handle<Collector>>,
+    tasks: HashMap<usize, Request>,
+    last_token: usize,
+    downloader: &'a Downloader,
+    robots: HashMap<RobotsKey, RobotsEntry>,
+    /// The failures in a row to download each robots.txt file.
+    robots_failures: HashMap<RobotsKey, u32>,
+    /// Requests waiting for their start time.
+    delayed: Vec<(Instant, Request)>,
+    /// The streamed transfers whose response was not sent yet.
+    heads: HashSet<usize>,
+    /// The transfers with an `on_headers` callback not called yet.
+    awaiting_headers: HashSet<usize>,
+    /// Whether the running transfers are paused by `pause()`.
+    paused: bool,
+    /// The requests ready to start, by host, and the hosts that have some
+    /// in the order they take turns.
+    ready: HashMap<String, VecDeque<Request>>,
+    hosts: VecDeque<String>,
+    /// The number of running transfers to each host.
+    host_transfers: HashMap<String, usize>,
+    /// The earliest deadline of the requests, when the waiting ones past
+    /// their deadline are failed next.
+    next_deadline: Option<Instant>,
+}
+
+impl Drop for Transfers<'_> {
+    /// Detach the transfers while the callbacks of the multi handle, which
+    /// curl calls to forget their sockets, still exist.
+    fn drop(&mut self) {
+        self.handles.clear();
+    }
+}
+
+impl<'a> Transfers<'a> {
+    fn new(downloader: &'a Downloader) -> Self {
+        let (multi, reactor) = downloader.new_multi();
+        Transfers {
+            multi,
+            reactor,
+            handles: HashMap::new(),
+            tasks: HashMap::new(),
+            last_token: 0,
+            downloader,
+            robots: HashMap::new(),
+            robots_failures: HashMap::new(),
+            delayed: Vec::new(),
+            heads: HashSet::new(),
+            awaiting_headers: HashSet::new(),
+            paused: false,
+            ready: HashMap::new(),
+            hosts: VecDeque::new(),
+            host_transfers: HashMap::new(),
+            next_deadline: None,
+        }
+    }
+
+    /// Start a new request, once the host policy and robots.txt allow it.
+    fn submit(&mut self, task: Request) {
+        if !self.downloader.allows_host(&task.url) {
+            return self.fail(&task, (Category::Policy, HOST_NOT_ALLOWED));
+        }
+        if let Some(deadline) = task.deadline {
+            self.next_deadline = Some(self.next_deadline.map_or(deadline, |next| next.min(deadline)));
+        }
+        let Some(agent) = task.robots_agent.clone() else {
+            return self.start(task);
+        };
+        let Some(origin) = http_origin(&task.url) else {
+            return self.start(task);
+        };
+        let key = (agent, origin);
+        match self.robots.get_mut(&key) {
+            Some(RobotsEntry::Fetching(waiting)) => waiting.push(task),
+            Some(RobotsEntry::Ready { expires, .. }) if *expires > self.downloader.now() => {
+                self.admit(&key, task)
+            }
+            _ => {
+                // Over the proxy, the TLS settings and the `connect_to`
+                // entries of the first request to the site.
+                let robots_request = Request {
+                    url: format!("{}/robots.txt", key.1),
+                    proxy: task.proxy.clone(),
+                    tls: task.tls.clone(),
+                    http_version: task.http_version,
+                    connect_to: task.connect_to.clone(),
+                    dns: task.dns.clone(),
+                    connection: task.connection,
+                    robots_for: Some(key.clone()),
+                    ..Default::default()
+                };
+                self.robots.insert(key, RobotsEntry::Fetching(vec![task]));
+                self.start(robots_request);
+            }
+        }
+    }
+
+    /// Check the request against robots.txt and schedule it after the
+    /// crawl delay.
+    fn admit(&mut self, key: &RobotsKey, task: Request) {
+        let Some(RobotsEntry::Ready { robots, next_start, .. }) = self.robots.get_mut(key) else {
+            return self.start(task);
+        };
+        let allowed = match Url::parse(&task.url) {
+            Ok(url) => match url.query() {
+                Some(query) => robots.is_allowed(&format!("{}?{}", url.path(), query)),
+                None => robots.is_allowed(url.path()),
+            },
+            Err(_) => true,
+        };
+        if !allowed {
+            let response = Response::error(&task, (Category::Policy, "disallowed by robots.txt"));
+            self.send(response);
+            return;
+        }
+        let now = self.downloader.now();
+        let start = (*next_start).max(now);
+        *next_start = start + robots.crawl_delay.unwrap_or_default();
+        if start <= now {
+            self.start(task);
+        } else {
+            self.delayed.push((start, task));
+        }
+    }
+
+    fn update_gauges(&self) {
+        let robots_waiting: usize = self
+            .robots
+            .values()
+            .map(|entry| match entry {
+                RobotsEntry::Fetching(waiting) => waiting.len(),
+                RobotsEntry::Ready { .. } => 0,
+            })
+            .sum();
+        let in_flight = self.tasks.values().filter(|task| !task.is_internal()).count();
+        let ready: usize = self.ready.values().map(VecDeque::len).sum();
+        self.downloader.waiting.store(self.delayed.len() + robots_waiting + ready, Ordering::Relaxed);
+        self.downloader.in_flight.store(in_flight, Ordering::Relaxed);
+    }
+
+    /// Start the delayed requests whose time has come.
+    fn start_delayed(&mut self) {
+        let now = self.downloader.now();
+        let (ready, delayed) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(start, _)| *start <= now);
+        self.delayed = delayed;
+        for (_, task) in ready {
+            self.start(task);
+        }
+    }
+
+    /// Cache a downloaded robots.txt and admit the requests waiting for it.
+    fn robots_downloaded(&mut self, key: RobotsKey, status_code: i64, data: &[u8]) {
+        let robots = match status_code {
+            200..=299 => Robots::parse(&String::from_utf8_lossy(data), &key.0),
+            // No robots.txt: everything is allowed.
+            400..=499 => Robots::allow_all(),
+            // Unreachable: assume everything is disallowed, until it is
+            // downloaded again soon.
+            _ => Robots::disallow_all(),
+        };
+        let max_age = if matches!(status_code, 200..=299 | 400..=499) {
+            self.robots_failures.remove(&key);
+            ROBOTS_MAX_AGE
+        } else {
+            let failures = self.robots_failures.entry(key.clone()).or_default();
+            let max_age = ROBOTS_RETRY_AGE.saturating_mul(1 << (*failures).min(16));
+            *failures += 1;
+            max_age.min(ROBOTS_MAX_RETRY_AGE)
+        };
+        let now = self.downloader.now();
+        let entry = RobotsEntry::Ready {
+            robots,
+            expires: now + max_age,
+            next_start: now,
+        };
+        let waiting = match self.robots.insert(key.clone(), entry) {
+            Some(RobotsEntry::Fetching(waiting)) => waiting,
+            _ => Vec::new(),
+        };
+        for task in waiting {
+            self.admit(&key, task);
+        }
+    }
+
+    /// Queue the request to start once the limits allow.
+    fn start(&mut self, task: Request) {
+        let host = host_of(&task.url);
+        match self.ready.get_mut(&host) {
+            Some(queue) => queue.push_back(task),
+            None => {
+                self.ready.insert(host.clone(), VecDeque::from([task]));
+                self.hosts.push_back(host);
+            }
+        }
+    }
+
+    /// Start the ready requests the limits allow, the hosts taking turns.
+    ///
+    /// Nothing starts while the downloader is paused.
+    fn schedule(&mut self) {
+        if self.downloader.suspended.load(Ordering::Relaxed) {
+            return;
+        }
+        let config = self.downloader.scheduler;
+        // The number of hosts in a row skipped for being at their limit.
+        let mut skipped = 0;
+        while skipped < self.hosts.len() {
+            if config.max_transfers.is_some_and(|max| self.tasks.len() >= max) {
+                break;
+            }
+            if let Some(error) = self.downloader.stopped() {
+                // Failing a robots.txt download may make others ready.
+                while !self.ready.is_empty() {
+                    self.hosts.clear();
+                    for task in std::mem::take(&mut self.ready).into_values().flatten() {
+                        self.fail(&task, (Category::Policy, error.as_str()));
+                    }
+                }
+                break;
+            }
+            let host = self.hosts.pop_front().expect("the hosts should not be empty");
+            let running = self.host_transfers.get(&host).copied().unwrap_or(0);
+            let over_quota = self.downloader.quota_exceeded(&host).is_some();
+            if over_quota && !self.downloader.defers_over_quota() {
+                let queue = self.ready.remove(&host).expect("a host should have ready requests");
+                for task in queue {
+                    self.fail(&task, (Category::Policy, QUOTA_EXCEEDED));
+                }
+                continue;
+            }
+            // The throttle counts the start, so it comes last.
+            if over_quota
+                || config.max_host_transfers.is_some_and(|max| running >= max)
+                || !self.downloader.throttle_allows(&host, running)
+            {
+                self.hosts.push_back(host);
+                skipped += 1;
+                continue;
+            }
+            skipped = 0;
+            let queue = self.ready.get_mut(&host).expect("a host should have ready requests");
+            let task = queue.pop_front().expect("the ready requests should not be empty");
+            if queue.is_empty() {
+                self.ready.remove(&host);
+            } else {
+                self.hosts.push_back(host);
+            }
+            self.launch(task);
+        }
+    }
+
+    /// Remove a transfer, now that it is over.
+    fn remove_task(&mut self, token: usize) -> Option<Request> {
+        let task = self.tasks.remove(&token)?;
+        self.awaiting_headers.remove(&token);
+        let host = host_of(&task.url);
+        if let Some(running) = self.host_transfers.get_mut(&host) {
+            *running -= 1;
+            if *running == 0 {
+                self.host_transfers.remove(&host);
+            }
+        }
+        Some(task)
+    }
+
+    /// Add the request to the multi handle.
+    ///
+    /// A panic only fails this request.
+    fn launch(&mut self, task: Request) {
+        let origin = task.origin();
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| self.try_start(task))) {
+            let message = panic_message(&payload);
+            log::error!("panic while starting {}: {}", origin.url, message);
+            self.fail(&origin, format!("panic: {}", message));
+        }
+    }
+
+    fn try_start(&mut self, mut task: Request) {
+        let now = self.downloader.now();
+        if task.expired(now) {
+            self.fail(&task, (Category::Timeout, DEADLINE_EXCEEDED));
+            return;
+        }
+        let token = self.last_token;
+        self.last_token += 1;
+
+        let collector = match task.collector(token, &self.downloader.buffers) {
+            Ok(collector) => collector,
+            Err(error) => {
+                self.fail(&task, error);
+                return;
+            }
+        };
+
+        let request = match task.to_easy(collector, now) {
+            Ok(request) => request,
+            Err(error) => {
+                self.fail(&task, error);
+                return;
+            }
+        };
+
+        if !task.is_internal() {
+            self.downloader.count_start();
+        }
+        let mut handle = self.multi.add2(request).unwrap();
+        handle.set_token(token).unwrap();
+        log::debug!("start {} {}", task.url, format_labels(&task.labels));
+        task.started = Some(Timestamp::now());
+
+        //
+        if task.stream.is_some() {
+            self.heads.insert(token);
+        }
+        if task.on_headers.is_some() {
+            self.awaiting_headers.insert(token);
+        }
+        if !task.is_internal() {
+            self.downloader.set_stage(task.sequence, Stage::InFlight);
+        }
+        self.handles.insert(token, handle);
+        *self.host_transfers.entry(host_of(&task.url)).or_default() += 1;
+        self.tasks.insert(token, task);
+    }
+
+    /// Pass the headers of the transfers that received them to their
+    /// `on_headers` callback.
+    fn send_headers(&mut self) {
+        let received: Vec<usize> = self
+            .awaiting_headers
+            .iter()
+            .copied()
+            .filter(|token| self.handles[token].get_ref().headers_complete)
+            .collect();
+        for token in received {
+            self.awaiting_headers.remove(&token);
+            let (task, collector) = (&self.tasks[&token], self.handles[&token].get_ref());
+            let headers = ResponseHeaders {
+                url: task.url.clone(),
+                status_code: collector.status as i64,
+                headers: collector.headers.clone(),
+                http_version: collector.version,
+                abort: collector.abort.clone(),
+            };
+            let callback = task.on_headers.clone().expect("the request should have an on_headers callback");
+            if self.downloader.callback_sender.send(Event::Headers(callback, headers)).is_err() {
+                self.downloader.channels_intact.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Send the response of the streamed transfers whose body started.
+    fn send_heads(&mut self) {
+        let started: Vec<usize> = self
+            .heads
+            .iter()
+            .copied()
+            .filter(|token| self.handles[token].get_ref().body_started)
+            .collect();
+        for token in started {
+            self.heads.remove(&token);
+            let (task, collector) = (&self.tasks[&token], self.handles[&token].get_ref());
+            let response = Response {
+                sequence: task.sequence,
+                url: task.url.clone(),
+                status_code: collector.status as i64,
+                headers: collector.headers.clone(),
+                data: Vec::new(),
+                compressed: None,
+                encoding: charset::detect(collector.header_value("Content-Type"), &[], false),
+                path: None,
+                filetime: None,
+                not_modified: collector.status == 304,
+                http_version: collector.version,
+                error: None,
+                category: None,
+                curl_code: None,
+                span: None,
+                queued: task.queued,
+                started: task.started,
+                finished: Some(Timestamp::now()),
+                stream: collector.stream.clone(),
+                nbytes: None,
+                redirects: task.redirects.clone(),
+                original_url: task.original_url.clone(),
+                processed: Vec::new(),
+                digests: Vec::new(),
+                content_length: collector
+                    .header_value("Content-Length")
+                    .and_then(|length| length.trim().parse().ok()),
+                size_downloaded: 0,
+                truncated: false,
+                parts: None,
+            };
+            self.send(response);
+        }
+    }
+
+    /// Pause or resume all the running transfers.
+    fn pause(&mut self, paused: bool) {
+        if paused == self.paused {
+            return;
+        }
+        self.paused = paused;
+        let mut failed = Vec::new();
+        for (token, handle) in &self.handles {
+            // Resuming fails like `unpause()` for a dropped stream.
+            if let Err(error) = curl_ext::pause(handle, paused) {
+                failed.push((*token, error));
+            }
+        }
+        for (token, error) in failed {
+            self.finish(token, Err(error));
+        }
+    }
+
+    /// Resume the streamed transfers Python made room for.
+    ///
+    /// They stay paused while all the transfers are, until `resume()`.
+    fn unpause(&mut self) {
+        if self.paused {
+            return;
+        }
+        let tokens = std::mem::take(&mut *self.downloader.unpaused.lock().unwrap());
+        for token in tokens {
+            let Some(handle) = self.handles.get(&token) else {
+                continue;
+            };
+            // curl writes the paused data right away, and fails when the
+            // response was dropped meanwhile.
+            if let Err(error) = handle.unpause_write() {
+                self.finish(token, Err(error));
+            }
+        }
+    }
+
+    /// Emit the response of a completed transfer, or try it again.
+    ///
+    /// A panic only fails this request.
+    fn finish(&mut self, token: usize, result: Result<(), curl::Error>) {
+        let Some(origin) = self.tasks.get(&token).map(Request::origin) else {
+            return;
+        };
+        let stream = self.handles.get(&token).and_then(|handle| handle.get_ref().stream.clone());
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| self.try_finish(token, result))) {
+            let message = panic_message(&payload);
+            log::error!("panic while finishing {}: {}", origin.url, message);
+            self.handles.remove(&token);
+            self.remove_task(token);
+            let head_sent = !self.heads.remove(&token);
+            match stream {
+                Some(stream) if head_sent => stream.finish(Some(format!("panic: {}", message))),
+                _ => self.fail(&origin, format!("panic: {}", message)),
+            }
+        }
+    }
+
+    fn try_finish(&mut self, token: usize, result: Result<(), curl::Error>) {
+        let handle = self
+            .handles
+            .remove(&token)
+            .expect("the download value should exist in the HashMap");
+        // Detach the transfer so the body can be moved out of its collector.
+        let mut easy = self.multi.remove2(handle).unwrap();
+        let mut task = self.remove_task(token).expect("the task should exist in the HashMap");
+
+        let location = result.is_ok().then(|| easy.get_ref().redirect_location().map(str::to_owned)).flatten();
+        if let Some(location) = location {
+            let status_code = easy.get_ref().status;
+            let total_time = easy.total_time().unwrap_or_default();
+            let bytes = easy.download_size().unwrap_or(0.0) as u64;
+            self.downloader.record(&task, status_code as i64, false, total_time, bytes);
+            match task.follow(status_code, &location) {
+                Ok(Some(next)) => {
+                    log::debug!("redirect {} to {}", task.url, next.url);
+                    self.heads.remove(&token);
+                    self.downloader.buffers.put(std::mem::take(&mut easy.get_mut().data));
+                    self.submit(next);
+                    return;
+                }
+                // The redirect is the response.
+                Ok(None) => {}
+                Err(error) => {
+                    self.heads.remove(&token);
+                    self.send(Response::error(&task, (Category::Protocol, error)));
+                    return;
+                }
+            }
+        }
+
+        let mut response = task.response(&mut easy, result);
+
+        if let Some(key) = task.robots_for.take() {
+            self.robots_downloaded(key, response.status_code, &response.data);
+            self.downloader.buffers.put(response.data);
+            return;
+        }
+        if task.preconnect {
+            if let Some(error) = &response.error {
+                log::debug!("preconnect to {} failed: {}", task.url, error);
+            }
+            self.downloader.buffers.put(response.data);
+            return;
+        }
+
+        let failed = task.is_failure(&response);
+        let aborted = response.status_code < 0 && easy.get_ref().aborted().is_some();
+        if failed && !aborted && response.status_code < 0 && task.expired(self.downloader.now()) {
+            response.error = Some(DEADLINE_EXCEEDED.to_owned());
+            response.category = Some(Category::Timeout);
+        }
+        let total_time = easy.total_time().unwrap_or_default();
+        log::debug!(
+            "finish {} status={} time={:?} {}{}",
+            task.url,
+            response.status_code,
+            total_time,
+            format_labels(&task.labels),
+            response.error.as_ref().map(|error| format!(" error={}", error)).unwrap_or_default(),
+        );
+        let bytes = easy.download_size().unwrap_or(0.0) as u64;
+        self.downloader.record(&task, response.status_code, failed, total_time, bytes);
+
+        let delay = self.downloader.scheduler.retry_backoff.delay(task.attempts);
+        let retry = self.downloader.should_retry(&task, failed && !aborted, delay);
+        if let Some(stream) = easy.get_mut().stream.take() {
+            if !self.heads.remove(&token) {
+                // The response was sent when the body started, only the end
+                // of the stream is left.
+                stream.finish(response.error);
+                return;
+            }
+            if !retry {
+                stream.finish(response.error.clone());
+                response.stream = Some(stream);
+            }
+        }
+
+        if retry {
+            task.attempts += 1;
+            self.downloader.buffers.put(response.data);
+            if delay.is_zero() {
+                self.start(task);
+            } else {
+                self.delayed.push((self.downloader.now() + delay, task));
+            }
+            return;
+        }
+
+        response.span = task.span(&easy);
+        task.process(&mut response);
+        self.send(response);
+    }
+
+    /// Fail the requests with a sequence lower than `before`, queued or
+    /// running.
+    fn cancel(&mut self, before: u64) {
+        let cancelled = |task: &Request| !task.is_internal() && task.sequence < before;
+        let (mut tasks, kept): (Vec<Request>, Vec<Request>) =
+            self.downloader.task_receiver.try_iter().partition(|task| cancelled(task));
+        let tokens: Vec<usize> = self
+            .tasks
+            .iter()
+            .filter(|(_, task)| cancelled(task))
+            .map(|(token, _)| *token)
+            .collect();
+        for token in tokens {
+            let task = self.remove_task(token).expect("the task should exist in the HashMap");
+            let handle = self.handles.remove(&token).expect("the download value should exist in the HashMap");
+            let easy = self.multi.remove2(handle).unwrap();
+            let head_sent = !self.heads.remove(&token);
+            match &easy.get_ref().stream {
+                Some(stream) if head_sent => stream.finish(Some(CANCELLED.to_owned())),
+                _ => tasks.push(task),
+            }
+        }
+        tasks.extend(self.take_waiting(cancelled));
+        for task in tasks {
+            self.send(Response::error(&task, (Category::Cancelled, CANCELLED)));
+        }
+        for task in kept {
+            self.submit(task);
+        }
+    }
+
+    /// Remove the requests waiting to start that match `selected`.
+    fn take_waiting(&mut self, selected: impl Fn(&Request) -> bool) -> Vec<Request> {
+        let (delayed, kept_delayed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(_, task)| selected(task));
+        self.delayed = kept_delayed;
+        let mut tasks: Vec<Request> = delayed.into_iter().map(|(_, task)| task).collect();
+        for entry in self.robots.values_mut() {
+            if let RobotsEntry::Fetching(waiting) = entry {
+                let (dropped, kept): (Vec<_>, Vec<_>) = std::mem::take(waiting).into_iter().partition(&selected);
+                *waiting = kept;
+                tasks.extend(dropped);
+            }
+        }
+        for queue in self.ready.values_mut() {
+            let (dropped, kept): (VecDeque<_>, VecDeque<_>) = std::mem::take(queue).into_iter().partition(&selected);
+            *queue = kept;
+            tasks.extend(dropped);
+        }
+        self.ready.retain(|_, queue| !queue.is_empty());
+        let ready = &self.ready;
+        self.hosts.retain(|host| ready.contains_key(host));
+        tasks
+    }
+
+    /// Fail the waiting requests whose deadline passed, once the earliest
+    /// one did. The running ones time out on their own.
+    fn expire(&mut self) {
+        let now = self.downloader.now();
+        if self.next_deadline.is_none_or(|deadline| now < deadline) {
+            return;
+        }
+        for task in self.take_waiting(|task| !task.is_internal() && task.expired(now)) {
+            self.send(Response::error(&task, (Category::Timeout, DEADLINE_EXCEEDED)));
+        }
+        let waiting = self.delayed.iter().map(|(_, task)| task).chain(self.ready.values().flatten());
+        let robots_waiting = self.robots.values().flat_map(|entry| match entry {
+            RobotsEntry::Fetching(waiting) => waiting.as_slice(),
+            _ => &[],
+        });
+        self.next_deadline = waiting
+            .chain(robots_waiting)
+            .chain(self.tasks.values())
+            .filter_map(|task| task.deadline)
+            .min();
+    }
+
+    /// Fail every request, after the downloader thread panicked.
+    fn fail_all(&mut self, error: &str) {
+        let mut tasks = Vec::new();
+        for (token, task) in self.tasks.drain() {
+            let stream = self.handles.get(&token).and_then(|handle| handle.get_ref().stream.clone());
+            match stream {
+                Some(stream) if !self.heads.contains(&token) => stream.finish(Some(error.to_owned())),
+                _ => tasks.push(task),
+            }
+        }
+        self.handles.clear();
+        self.heads.clear();
+        self.awaiting_headers.clear();
+        self.host_transfers.clear();
+        self.hosts.clear();
+        tasks.extend(self.ready.drain().flat_map(|(_, queue)| queue));
+        tasks.extend(self.delayed.drain(..).map(|(_, task)| task));
+        for (_, entry) in self.robots.drain() {
+            if let RobotsEntry::Fetching(waiting) = entry {
+                tasks.extend(waiting);
+            }
+        }
+        for task in tasks.into_iter().filter(|task| !task.is_internal()) {
+            self.send(Response::error(&task, error));
+        }
+    }
+
+    /// Fail a request without a response from its transfer: the
+    /// preconnections silently, and a robots.txt download as if the site
+    /// were unreachable, for the requests waiting for it.
+    fn fail(&mut self, task: &Request, error: impl Into<Failure>) {
+        if let Some(key) = &task.robots_for {
+            log::debug!("robots.txt download {} failed: {}", task.url, error.into().message);
+            self.robots_downloaded(key.clone(), -1, &[]);
+        } else if task.preconnect {
+            log::debug!("preconnect to {} failed: {}", task.url, error.into().message);
+        } else {
+            self.send(Response::error(task, error));
+        }
+    }
+
+    fn send(&self, response: Response) {
+        self.downloader.send(response);
+    }
+}
+
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
+
+/// The modification time of a file in seconds since the epoch.
+fn file_mtime(path: &str) -> Option<i64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_secs() as i64)
+}
+
+/// Seconds since the epoch from a `datetime` or a number.
+fn epoch_seconds(time: &Bound<'_, PyAny>) -> PyResult<i64> {
+    Ok(epoch_time(time)?.floor() as i64)
+}
+
+fn epoch_time(time: &Bound<'_, PyAny>) -> PyResult<f64> {
+    if time.hasattr("timestamp")? {
+        time.call_method0("timestamp")?.extract()
+    } else {
+        time.extract().map_err(|_| PyTypeError::new_err("expected a datetime or a number of seconds"))
+    }
+}
+
+/// The instant of a deadline given as a `datetime` or a number of seconds
+/// since the epoch, on `clock` or the real one.
+fn deadline_instant(time: &Bound<'_, PyAny>, clock: Option<&ManualClock>) -> PyResult<Instant> {
+    let (now, epoch) = clock::now(clock);
+    let left = epoch_time(time)? - epoch;
+    Ok(now + Duration::try_from_secs_f64(left.max(0.0)).unwrap_or(Duration::MAX / 2))
+}
+
+/// What `on_redirect` says of a redirect from `url` to `target`: the URL
+/// to follow, or None to deny it.
+///
+/// The callback returns None or True to follow `target` as it is, a
+/// string to follow that URL instead, or False to deny the redirect; it
+/// is denied when the callback raises.
+fn redirect_verdict(py: Python<'_>, callback: &PyObject, url: &str, target: &str, status_code: u32) -> Option<String> {
+    let verdict = match callback.call1(py, (url, target, status_code)) {
+        Ok(verdict) => verdict.into_bound(py),
+        Err(error) => {
+            error.write_unraisable_bound(py, Some(callback.bind(py)));
+            return None;
+        }
+    };
+    if let Ok(url) = verdict.downcast::<PyString>() {
+        return Some(url.to_string());
+    }
+    (verdict.is_none() || verdict.is_truthy().unwrap_or(false)).then(|| target.to_owned())
+}
+
+/// The origin of an HTTP(S) URL, like `https://example.com:8080`.
+fn http_origin(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    match url.scheme() {
+        "http" | "https" => Some(url.origin().ascii_serialization()),
+        _ => None,
+    }
+}
+
+
+create_exception!(pycurse, HTTPStatusError, pyo3::exceptions::PyException);
+create_exception!(pycurse, DeadlineExceeded, pyo3::exceptions::PyTimeoutError);
+create_exception!(pycurse, InactivityTimeout, pyo3::exceptions::PyTimeoutError);
+
+#[pyclass]
+struct ResponsePython {
+    url: String,
+    status_code: i64,
+    headers: Vec<(String, String)>,
+    data: Vec<u8>,
+    encoding: Option<String>,
+    path: Option<String>,
+    filetime: Option<i64>,
+    not_modified: bool,
+    http_version: Option<&'static str>,
+    error: Option<String>,
+    category: Option<Category>,
+    curl_code: Option<u32>,
+    queued: Option<Timestamp>,
+    started: Option<Timestamp>,
+    finished: Option<Timestamp>,
+    /// Where `data` goes back once the response is dropped.
+    buffers: Arc<BufferPool>,
+    stream: Option<BodyReader>,
+    nbytes: Option<usize>,
+    redirects: Vec<String>,
+    original_url: Option<String>,
+    processed: Vec<(Processor, Result<Output, String>)>,
+    digests: Vec<(Algorithm, String)>,
+    content_length: Option<u64>,
+    size_downloaded: u64,
+    truncated: bool,
+    parts: Option<Vec<byteranges::Part>>,
+    /// `processed` as Python objects, once converted.
+    processed_objects: GILOnceCell<Py<PyDict>>,
+}
+
+impl ResponsePython {
+    fn new(response: Response, downloader: &Arc<Downloader>) -> Self {
+        ResponsePython {
+            url: response.url,
+            status_code: response.status_code,
+            headers: response.headers,
+            data: response.data,
+            encoding: response.encoding,
+            path: response.path,
+            filetime: response.filetime,
+            not_modified: response.not_modified,
+            http_version: response.http_version,
+            error: response.error,
+            category: response.category,
+            curl_code: response.curl_code,
+            queued: response.queued,
+            started: response.started,
+            finished: response.finished,
+            buffers: downloader.buffers.clone(),
+            stream: response.stream.map(|stream| BodyReader {
+                stream,
+                downloader: downloader.clone(),
+            }),
+            nbytes: response.nbytes,
+            redirects: response.redirects,
+            original_url: response.original_url,
+            processed: response.processed,
+            digests: response.digests,
+            content_length: response.content_length,
+            size_downloaded: response.size_downloaded,
+            truncated: response.truncated,
+            parts: response.parts,
+            processed_objects: GILOnceCell::new(),
+        }
+    }
+
+    fn cache_control_of(&self) -> cache::CacheControl {
+        let values = self
+            .headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("Cache-Control"))
+            .map(|(_, value)| value.as_str());
+        cache::CacheControl::parse(values)
+    }
+
+    fn header_date<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Option<Bound<'py, PyDateTime>>> {
+        self.header_value(name)
+            .and_then(httpdate::parse)
+            .map(|time| utc_datetime(py, time))
+            .transpose()
+    }
+
+    fn links_of(&self) -> Vec<link::Link> {
+        let values = self
+            .headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("Link"))
+            .map(|(_, value)| value.as_str());
+        link::parse(values, &self.url)
+    }
+
+    fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .rev()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn next_chunk(&mut self, py: Python<'_>, timeout: Option<f64>) -> PyResult<Option<Vec<u8>>> {
+        let Some(reader) = &self.stream else {
+            return Err(PyRuntimeError::new_err("the response is not streamed"));
+        };
+        let deadline = timeout
+            .map(|timeout| seconds(timeout, "timeout"))
+            .transpose()?
+            .map(|timeout| Instant::now() + timeout);
+        let stream = reader.stream.clone();
+        loop {
+            // Wake up regularly to handle Ctrl-C.
+            let mut wait = Duration::from_millis(100);
+            if let Some(deadline) = deadline {
+                wait = wait.min(deadline.saturating_duration_since(Instant::now()));
+            }
+            match py.allow_threads(|| stream.next(wait)) {
+                Next::Chunk { data, resume } => {
+                    if resume {
+                        reader.downloader.unpause(stream.token);
+                    }
+                    return Ok(Some(data));
+                }
+                Next::End(None) => return Ok(None),
+                Next::End(Some(error)) => {
+                    let message = format!("the transfer of {} failed: {}", self.url, error);
+                    let inactive = error == INACTIVITY_TIMEOUT;
+                    self.error = Some(error);
+                    return Err(match inactive {
+                        true => InactivityTimeout::new_err(message),
+                        false => PyConnectionError::new_err(message),
+                    });
+                }
+                Next::Timeout => {
+                    if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+                        return Err(PyTimeoutError::new_err("no chunk received in time"));
+                    }
+                    py.check_signals()?;
+                }
+            }
+        }
+    }
+}
+
+/// The reading end of a streamed body.
+struct BodyReader {
+    stream: Arc<Stream>,
+    /// The downloader running the transfer, to resume it.
+    downloader: Arc<Downloader>,
+}
+
+impl Drop for ResponsePython {
+    fn drop(&mut self) {
+        self.buffers.put(std::mem::take(&mut self.data));
+        if let Some(reader) = &self.stream {
+            // A paused transfer must run to notice it is aborted.
+            if reader.stream.close() {
+                reader.downloader.unpause(reader.stream.token);
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl ResponsePython {
+    /// Raise `HTTPStatusError` if the status code is 4xx or 5xx,
+    /// `DeadlineExceeded` if the deadline of the request passed, and
+    /// `InactivityTimeout` if its transfer received nothing for
+    /// `inactivity_timeout`.
+    ///
+    /// The response is available as the `response` attribute of the
+    /// exception.
+    fn raise_for_status(slf: &Bound<'_, Self>) -> PyResult<()> {
+        let (status_code, url, error) = {
+            let response = slf.borrow();
+            (response.status_code, response.url.clone(), response.error.clone())
+        };
+        let error = match status_code {
+            _ if error.as_deref() == Some(DEADLINE_EXCEEDED) => {
+                DeadlineExceeded::new_err(format!("deadline exceeded for url: {}", url))
+            }
+            _ if error.as_deref() == Some(INACTIVITY_TIMEOUT) => {
+                InactivityTimeout::new_err(format!("inactivity timeout for url: {}", url))
+            }
+            400..=499 => HTTPStatusError::new_err(format!("{} Client Error for url: {}", status_code, url)),
+            500..=599 => HTTPStatusError::new_err(format!("{} Server Error for url: {}", status_code, url)),
+            _ => return Ok(()),
+        };
+        error.value_bound(slf.py()).setattr("response", slf)?;
+        Err(error)
+    }
+
+    #[getter]
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    #[getter]
+    fn status_code(&self) -> i64 {
+        self.status_code
+    }
+
+    /// The headers of the response, as a list of `(name, value)` pairs.
+    #[getter]
+    fn headers(&self) -> Vec<(&str, &str)> {
+        self.headers.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect()
+    }
+
+    /// The last value of the header `name`, compared case-insensitively.
+    #[pyo3(signature = (name, default=None))]
+    fn header<'a>(&'a self, name: &str, default: Option<&'a str>) -> Option<&'a str> {
+        self.header_value(name).or(default)
+    }
+
+    /// The links of the `Link` headers, RFC 8288, like the pages of a
+    /// paginated API.
+    ///
+    /// A list of dicts with the `url`, resolved against the URL of the
+    /// response, the `rel` relation types, and the other `params`.
+    #[getter]
+    fn links<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let list = PyList::empty_bound(py);
+        for link in self.links_of() {
+            let item = PyDict::new_bound(py);
+            item.set_item("url", link.url)?;
+            item.set_item("rel", link.rel)?;
+            item.set_item("params", link.params.into_py_dict_bound(py))?;
+            list.append(item)?;
+        }
+        Ok(list)
+    }
+
+    /// The body as UTF-8 text, raising `UnicodeDecodeError` for another
+    /// body: `text` decodes with the charset, `content` is the bytes.
+    #[getter]
+    fn data(&self, py: Python<'_>) -> PyResult<&str> {
+        str::from_utf8(&self.data).map_err(|error| match PyUnicodeDecodeError::new_utf8_bound(py, &self.data, error) {
+            Ok(error) => PyErr::from_value_bound(error.into_any()),
+            Err(error) => error,
+        })
+    }
+
+    /// The body as bytes.
+    #[getter]
+    fn content<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new_bound(py, &self.data)
+    }
+
+    /// The results of the `process` steps of the request, by name: the
+    /// same dict on each access. The steps that failed are in
+    /// `processing_errors` instead.
+    #[getter]
+    fn processed<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = self.processed_objects.get_or_try_init(py, || -> PyResult<_> {
+            let dict = PyDict::new_bound(py);
+            for (processor, output) in &self.processed {
+                if let Ok(output) = output {
+                    dict.set_item(processor.name(), output.to_python(py)?)?;
+                }
+            }
+            Ok(dict.unbind())
+        })?;
+        Ok(dict.bind(py).clone())
+    }
+
+    /// The size of the body announced by the `Content-Length` header, None
+    /// without one. Like `size_downloaded`, it is the size before decoding
+    /// a `Content-Encoding`.
+    #[getter]
+    fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// The number of bytes of the body received, also for a failed
+    /// transfer. Not known yet for a streamed response, whose `fetch()`
+    /// returns before the body.
+    #[getter]
+    fn size_downloaded(&self) -> u64 {
+        self.size_downloaded
+    }
+
+    /// Whether the body is partial: shorter than its `Content-Length`, or
+    /// cut short by a transfer failing in its middle. Responses without a
+    /// body, to `headers_only` or with a 204 or 304 status, are not.
+    #[getter]
+    fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The ranges of a 206 Partial Content response, as a list of
+    /// `((first, last), bytes)` with the first and last bytes of each
+    /// range: the parts of a `multipart/byteranges` body, or the single
+    /// range of its `Content-Range`. None for another response, for a
+    /// request without `ranges` and for a body which cannot be split.
+    #[getter]
+    fn parts<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyList>> {
+        let parts = self.parts.as_ref()?.iter().map(|part| {
+            let body = PyBytes::new_bound(py, &self.data[part.body.clone()]);
+            ((part.first, part.last), body).to_object(py)
+        });
+        Some(PyList::new_bound(py, parts))
+    }
+
+    /// The hex digests of the body, by name, for the `digests` of the
+    /// request. Empty when the transfer failed.
+    #[getter]
+    fn digests<'py>(&self, py: Python<'py>) -> Bound<'py, PyDict> {
+        self.digests
+            .iter()
+            .map(|(algorithm, digest)| (algorithm.name(), digest))
+            .into_py_dict_bound(py)
+    }
+
+    /// Why the `process` steps that failed did, by name.
+    #[getter]
+    fn processing_errors<'py>(&self, py: Python<'py>) -> Bound<'py, PyDict> {
+        self.processed
+            .iter()
+            .filter_map(|(processor, output)| Some((processor.name(), output.as_ref().err()?)))
+            .into_py_dict_bound(py)
+    }
+
+    /// The codec decoding the body, from its byte order mark, the
+    /// `Content-Type` header or, with `sniff_charset`, a `<meta>` tag.
+    #[getter]
+    fn encoding(&self) -> Option<&str> {
+        self.encoding.as_deref()
+    }
+
+    /// The body decoded with `encoding`, UTF-8 when unknown. Invalid bytes
+    /// are replaced by U+FFFD.
+    ///
+    /// The body is decoded where it is, without a copy as `bytes`; for a
+    /// body too large to hold twice, `iter_text()` decodes it in chunks.
+    #[getter]
+    fn text<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyString>> {
+        let decode = |encoding: &CStr| {
+            // SAFETY: the data outlives the call, which copies it into the
+            // new string.
+            unsafe {
+                let text = ffi::PyUnicode_Decode(
+                    self.data.as_ptr() as *const c_char,
+                    self.data.len() as ffi::Py_ssize_t,
+                    encoding.as_ptr(),
+                    c"replace".as_ptr(),
+                );
+                Bound::from_owned_ptr_or_err(py, text)
+            }
+        };
+        // A charset with a NUL character is not one Python knows.
+        let encoding = CString::new(self.encoding.as_deref().unwrap_or("utf-8")).unwrap_or_default();
+        let text = match decode(&encoding) {
+            Ok(text) => text,
+            // A charset Python does not know.
+            Err(error) if error.is_instance_of::<PyLookupError>(py) || encoding.is_empty() => decode(c"utf-8")?,
+            Err(error) => return Err(error),
+        };
+        Ok(text.downcast_into::<PyString>()?)
+    }
+
+    /// Iterate over the body decoded with `encoding`, as `str` chunks of at
+    /// most `chunk_size` bytes of the body each, a character split between
+    /// two chunks decoded with the next one.
+    ///
+    /// A streamed body is decoded as it is received, a chunk per chunk
+    /// received; `timeout` is the one of `read_chunk()`.
+    #[pyo3(signature = (chunk_size=65536, timeout=None))]
+    fn iter_text(slf: &Bound<'_, Self>, chunk_size: usize, timeout: Option<f64>) -> PyResult<TextChunks> {
+        if chunk_size == 0 {
+            return Err(PyValueError::new_err("chunk_size must be at least 1"));
+        }
+        let py = slf.py();
+        let codecs = py.import_bound("codecs")?;
+        let decoder = |encoding: &str| -> PyResult<_> {
+            codecs.call_method1("getincrementaldecoder", (encoding,))?.call1(("replace",))
+        };
+        let decoder = match decoder(slf.borrow().encoding.as_deref().unwrap_or("utf-8")) {
+            Ok(decoder) => decoder,
+            // A charset Python does not know.
+            Err(error) if error.is_instance_of::<PyLookupError>(py) => decoder("utf-8")?,
+            Err(error) => return Err(error),
+        };
+        Ok(TextChunks {
+            response: slf.clone().unbind(),
+            decoder: decoder.unbind(),
+            chunk_size,
+            timeout,
+            position: 0,
+            complete: false,
+        })
+    }
+
+    /// The next chunk of a streamed body, or None once it is complete.
+    ///
+    /// Waits at most `timeout` seconds, forever by default, then raises
+    /// `TimeoutError`. `ConnectionError` is raised when the transfer fails
+    /// in the middle of the body, `InactivityTimeout` when it received
+    /// nothing for `inactivity_timeout`, and `error` is then set.
+    #[pyo3(signature = (timeout=None))]
+    fn read_chunk<'py>(&mut self, py: Python<'py>, timeout: Option<f64>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        Ok(self.next_chunk(py, timeout)?.map(|data| PyBytes::new_bound(py, &data)))
+    }
+
+    /// Iterate over the chunks of a streamed body, waiting at most
+    /// `timeout` seconds for each.
+    #[pyo3(signature = (timeout=None))]
+    fn iter_chunks(slf: Py<Self>, timeout: Option<f64>) -> Chunks {
+        Chunks { response: slf, timeout }
+    }
+
+    /// Iterate over the lines of the body, as `bytes` without their
+    /// delimiter.
+    ///
+    /// Lines end with `\n` or `\r\n` by default, or with `delimiter`, a
+    /// `str` or `bytes`. A streamed body is read as it is received, for
+    /// NDJSON APIs or log streams; `timeout` is the one of `read_chunk()`.
+    #[pyo3(signature = (delimiter=None, timeout=None))]
+    fn iter_lines(
+        slf: &Bound<'_, Self>,
+        delimiter: Option<&Bound<'_, PyAny>>,
+        timeout: Option<f64>,
+    ) -> PyResult<Lines> {
+        let delimiter = match delimiter {
+            None => None,
+            Some(delimiter) => {
+                let delimiter = if let Ok(text) = delimiter.downcast::<PyString>() {
+                    text.to_str()?.as_bytes().to_vec()
+                } else if let Ok(bytes) = delimiter.downcast::<PyBytes>() {
+                    bytes.as_bytes().to_vec()
+                } else {
+                    return Err(PyTypeError::new_err("delimiter must be str or bytes"));
+                };
+                if delimiter.is_empty() {
+                    return Err(PyValueError::new_err("delimiter must not be empty"));
+                }
+                Some(delimiter)
+            }
+        };
+        let response = slf.borrow();
+        // The body of a response that is not streamed is already complete.
+        let (pending, complete) = match response.stream {
+            Some(_) => (Vec::new(), false),
+            None => (response.data.clone(), true),
+        };
+        Ok(Lines {
+            response: slf.clone().unbind(),
+            delimiter,
+            timeout,
+            pending,
+            searched: 0,
+            complete,
+        })
+    }
+
+    /// The body as a read-only `memoryview`, without copying it.
+    ///
+    /// The view keeps the response alive.
+    fn memory<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyMemoryView>> {
+        PyMemoryView::from_bound(slf.as_any())
+    }
+
+    /// Export the body with the buffer protocol, read-only.
+    unsafe fn __getbuffer__(slf: Bound<'_, Self>, view: *mut ffi::Py_buffer, flags: c_int) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("view is null"));
+        }
+        if flags & ffi::PyBUF_WRITABLE == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("the response body is read-only"));
+        }
+        // The body is not modified while the response is alive, and the
+        // view holds a reference to the response.
+        let (buf, len) = {
+            let response = slf.borrow();
+            (response.data.as_ptr(), response.data.len())
+        };
+        (*view).obj = slf.into_any().into_ptr();
+        (*view).buf = buf as *mut c_void;
+        (*view).len = len as ffi::Py_ssize_t;
+        (*view).readonly = 1;
+        (*view).itemsize = 1;
+        (*view).format = if flags & ffi::PyBUF_FORMAT == ffi::PyBUF_FORMAT {
+            c"B".as_ptr() as *mut c_char
+        } else {
+            ptr::null_mut()
+        };
+        (*view).ndim = 1;
+        (*view).shape = if flags & ffi::PyBUF_ND == ffi::PyBUF_ND { &mut (*view).len } else { ptr::null_mut() };
+        (*view).strides = if flags & ffi::PyBUF_STRIDES == ffi::PyBUF_STRIDES {
+            &mut (*view).itemsize
+        } else {
+            ptr::null_mut()
+        };
+        (*view).suboffsets = ptr::null_mut();
+        (*view).internal = ptr::null_mut();
+        Ok(())
+    }
+
+    /// The file the body was saved to, if the request had a `path`.
+    #[getter]
+    fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// When the request was added, when its last transfer started and when
+    /// the response was received, in seconds since the epoch like
+    /// `time.time()`. None when the request did not get that far.
+    #[getter]
+    fn queued_at(&self) -> Option<f64> {
+        self.queued.map(|queued| queued.unix_nanos() as f64 / 1e9)
+    }
+
+    #[getter]
+    fn started_at(&self) -> Option<f64> {
+        self.started.map(|started| started.unix_nanos() as f64 / 1e9)
+    }
+
+    #[getter]
+    fn finished_at(&self) -> Option<f64> {
+        self.finished.map(|finished| finished.unix_nanos() as f64 / 1e9)
+    }
+
+    /// The seconds the request waited before its last transfer started,
+    /// the earlier attempts included, and the seconds of that transfer.
+    /// Both come from the monotonic clock, unaffected by changes of the
+    /// wall clock.
+    #[getter]
+    fn queue_time(&self) -> Option<f64> {
+        Some(self.started?.since(&self.queued?).as_secs_f64())
+    }
+
+    #[getter]
+    fn transfer_time(&self) -> Option<f64> {
+        Some(self.finished?.since(&self.started?).as_secs_f64())
+    }
+
+    /// The `Date` header, as an aware `datetime` in UTC.
+    #[getter]
+    fn date<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDateTime>>> {
+        self.header_date(py, "Date")
+    }
+
+    /// The `Last-Modified` header, as an aware `datetime` in UTC.
+    #[getter]
+    fn last_modified<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDateTime>>> {
+        self.header_date(py, "Last-Modified")
+    }
+
+    /// The `Expires` header, as an aware `datetime` in UTC. None when it
+    /// is missing or invalid, like `Expires: 0`, which means already
+    /// expired.
+    #[getter]
+    fn expires<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDateTime>>> {
+        self.header_date(py, "Expires")
+    }
+
+    /// The modification time of the file downloaded to `path`, as reported
+    /// by the server, as an aware `datetime` in UTC.
+    #[getter]
+    fn filetime<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDateTime>>> {
+        self.filetime.map(|time| utc_datetime(py, time)).transpose()
+    }
+
+    /// The directives of the `Cache-Control` headers, as a dict of their
+    /// lowercase names to their argument, or True when they have none.
+    #[getter]
+    fn cache_control<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        for (name, argument) in self.cache_control_of().directives() {
+            match argument {
+                Some(argument) => dict.set_item(name, argument)?,
+                None => dict.set_item(name, true)?,
+            }
+        }
+        Ok(dict)
+    }
+
+    /// The `max-age` directive, in seconds.
+    #[getter]
+    fn max_age(&self) -> Option<u64> {
+        self.cache_control_of().seconds("max-age")
+    }
+
+    #[getter]
+    fn no_store(&self) -> bool {
+        self.cache_control_of().has("no-store")
+    }
+
+    #[getter]
+    fn no_cache(&self) -> bool {
+        self.cache_control_of().has("no-cache")
+    }
+
+    /// The `stale-while-revalidate` directive, in seconds.
+    #[getter]
+    fn stale_while_revalidate(&self) -> Option<u64> {
+        self.cache_control_of().seconds("stale-while-revalidate")
+    }
+
+    /// How long the response stays fresh for a private cache, in seconds:
+    /// `max-age`, `Expires` minus `Date`, or 10% of the time since
+    /// `Last-Modified`. None when nothing tells.
+    #[getter]
+    fn freshness_lifetime(&self) -> Option<u64> {
+        let response_time = self.finished?.unix_nanos() / 1_000_000_000;
+        let header_time = |name| self.header_value(name).and_then(httpdate::parse);
+        cache::freshness_lifetime(
+            &self.cache_control_of(),
+            self.status_code,
+            header_time("Date"),
+            self.header_value("Expires").map(httpdate::parse),
+            header_time("Last-Modified"),
+            response_time as i64,
+        )
+    }
+
+    /// The current age of the response in seconds, counting the time it
+    /// spent in caches according to `Age` and `Date`.
+    #[getter]
+    fn age(&self) -> Option<f64> {
+        let unix_seconds = |timestamp: Timestamp| timestamp.unix_nanos() as f64 / 1e9;
+        let response_time = unix_seconds(self.finished?);
+        let request_time = self.started.map_or(response_time, unix_seconds);
+        Some(cache::current_age(
+            self.header_value("Date").and_then(httpdate::parse),
+            self.header_value("Age").and_then(|age| age.trim().parse().ok()),
+            request_time,
+            response_time,
+            unix_seconds(Timestamp::now()),
+        ))
+    }
+
+    /// Whether the response is younger than its `freshness_lifetime`.
+    #[getter]
+    fn is_fresh(&self) -> bool {
+        match (self.freshness_lifetime(), self.age()) {
+            (Some(lifetime), Some(age)) => age < lifetime as f64,
+            _ => false,
+        }
+    }
+
+    /// Whether the server reported that the resource has not changed.
+    #[getter]
+    fn not_modified(&self) -> bool {
+        self.not_modified
+    }
+
+    /// The HTTP version of the response: `1.0`, `1.1`, `2` or `3`.
+    #[getter]
+    fn http_version(&self) -> Option<&str> {
+        self.http_version
+    }
+
+    /// The size of the body written to the buffer of `into`, None for the
+    /// other requests.
+    #[getter]
+    fn nbytes(&self) -> Option<usize> {
+        self.nbytes
+    }
+
+    /// The URLs of the redirects followed, in order; `url` is the one of
+    /// the final response.
+    #[getter]
+    fn redirects(&self) -> Vec<&str> {
+        self.redirects.iter().map(String::as_str).collect()
+    }
+
+    /// The URL given to the request, when `host_map` or `force_https`
+    /// rewrote it; None otherwise.
+    #[getter]
+    fn original_url(&self) -> Option<&str> {
+        self.original_url.as_deref()
+    }
+
+    /// Why the request failed, when `status_code` is -1.
+    #[getter]
+    fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// What kind of failure `error` is: `dns`, `connect` when the
+    /// connection failed or was lost, `tls`, `timeout` (deadlines
+    /// included), `protocol` for invalid responses and redirects,
+    /// `too_large` for the size limits, `cancelled`, `policy` for the
+    /// requests refused by the host policy, a quota, robots.txt,
+    /// `accept_content_types` or a stop condition, or `other`. None when the request did not
+    /// fail; error status codes are not classified.
+    #[getter]
+    fn error_category(&self) -> Option<&'static str> {
+        self.category.map(Category::name)
+    }
+
+    /// The `CURLE_*` code of the curl error, if curl reported one.
+    #[getter]
+    fn curl_code(&self) -> Option<u32> {
+        self.curl_code
+    }
+}
+
+/// The iterator of `ResponsePython.iter_chunks()`.
+#[pyclass]
+struct Chunks {
+    response: Py<ResponsePython>,
+    timeout: Option<f64>,
+}
+
+#[pymethods]
+impl Chunks {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        self.response.borrow_mut(py).read_chunk(py, self.timeout)
+    }
+}
+
+/// The iterator of `ResponsePython.iter_text()`.
+#[pyclass]
+struct TextChunks {
+    response: Py<ResponsePython>,
+    /// The incremental decoder of the encoding, keeping the bytes of a
+    /// character not complete yet.
+    decoder: PyObject,
+    chunk_size: usize,
+    timeout: Option<f64>,
+    /// How much of a body that is not streamed was decoded.
+    position: usize,
+    /// Whether the whole body was decoded.
+    complete: bool,
+}
+
+#[pymethods]
+impl TextChunks {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyString>>> {
+        let decoder = self.decoder.bind(py);
+        while !self.complete {
+            let mut response = self.response.borrow_mut(py);
+            let data = match response.stream {
+                Some(_) => response.next_chunk(py, self.timeout)?.map(|data| PyBytes::new_bound(py, &data)),
+                None => {
+                    let end = (self.position + self.chunk_size).min(response.data.len());
+                    let data = &response.data[self.position..end];
+                    self.position = end;
+                    (!data.is_empty()).then(|| PyBytes::new_bound(py, data))
+                }
+            };
+            drop(response);
+            // The bytes left of an incomplete character are replaced.
+            self.complete = data.is_none();
+            let data = data.unwrap_or_else(|| PyBytes::new_bound(py, b""));
+            let text = decoder.call_method1("decode", (data, self.complete))?.downcast_into::<PyString>()?;
+            if !text.is_empty()? {
+                return Ok(Some(text));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The iterator of `ResponsePython.iter_lines()`.
+#[pyclass]
+struct Lines {
+    response: Py<ResponsePython>,
+    /// None for `\n` and `\r\n`.
+    delimiter: Option<Vec<u8>>,
+    timeout: Option<f64>,
+    /// The body received and not split into lines yet.
+    pending: Vec<u8>,
+    /// How much of `pending` is known not to hold a delimiter.
+    searched: usize,
+    /// Whether the whole body is in `pending`.
+    complete: bool,
+}
+
+#[pymethods]
+impl Lines {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        loop {
+            if let Some(line) = self.split_line() {
+                return Ok(Some(PyBytes::new_bound(py, &line)));
+            }
+            if self.complete {
+                // The last line may have no delimiter.
+                if self.pending.is_empty() {
+                    return Ok(None);
+                }
+                let line = std::mem::take(&mut self.pending);
+                self.searched = 0;
+                return Ok(Some(PyBytes::new_bound(py, &line)));
+            }
+            match self.response.borrow_mut(py).next_chunk(py, self.timeout)? {
+                Some(data) => self.pending.extend_from_slice(&data),
+                None => self.complete = true,
+            }
+        }
+    }
+}
+
+impl Lines {
+    /// Take the first complete line out of `pending`.
+    fn split_line(&mut self) -> Option<Vec<u8>> {
+        let delimiter = self.delimiter.as_deref().unwrap_or(b"\n");
+        // A delimiter may span the data searched and the data received
+        // since.
+        let from = self.searched.saturating_sub(delimiter.len() - 1);
+        let Some(position) = self.pending[from..]
+            .windows(delimiter.len())
+            .position(|window| window == delimiter)
+        else {
+            self.searched = self.pending.len();
+            return None;
+        };
+        let end = from + position;
+        let mut line: Vec<u8> = self.pending.drain(..end + delimiter.len()).collect();
+        line.truncate(end);
+        if self.delimiter.is_none() && line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        self.searched = 0;
+        Some(line)
+    }
+}
+
+/// The most easy handles `request()` keeps idle, for the calls made
+/// concurrently from as many threads.
+const MAX_IDLE_EASY: usize = 16;
+
+/// A struct to store a curl easy handle.
+#[pyclass]
+struct CurlDownloader {
+    downloader: Arc<Downloader>,
+    max_retries: u32,
+    fail_on_status: bool,
+    sniff_charset: bool,
+    decompress: bool,
+    decode_content: bool,
+    allow_http09: bool,
+    ignore_content_length: bool,
+    tls: TlsOptions,
+    http_version: Option<HttpVersion>,
+    connect_to: Vec<String>,
+    dns: DnsOptions,
+    connection: ConnectionOptions,
+    /// The browser profile and the locale of the requests.
+    profile: Option<Profile>,
+    locale: Option<String>,
+    robots_agent: Option<String>,
+    url_rewrites: Option<UrlRewrites>,
+    span_exporter: Option<PyObject>,
+    inject_traceparent: bool,
+    /// The number of chunks a streamed body buffers before its transfer is
+    /// paused.
+    max_stream_chunks: usize,
+    /// Abort the transfers receiving nothing for this long.
+    inactivity_timeout: Option<Duration>,
+    max_header_bytes: Option<usize>,
+    max_headers: Option<usize>,
+    /// Deliver the responses in the order of the requests.
+    ordered: bool,
+    inbox: Inbox,
+    /// The idle easy handles of `request()`, with their connections.
+    easy: Mutex<Vec<Easy2<Collector>>>,
+    callback_executor: CallbackExecutor,
+    /// Whether the thread calling the callbacks was started.
+    callback_thread: Mutex<bool>,
+    /// Set by `drain()`, new requests are refused.
+    draining: AtomicBool,
+    /// The settings it was created with, for `config()`.
+    config: Py<PyDict>,
+}
+
+/// How the callbacks of the requests are called.
+enum CallbackExecutor {
+    /// In the thread receiving the responses, one after the other.
+    Inline,
+    /// Submitted to a `concurrent.futures` executor.
+    Submit(PyObject),
+    /// Scheduled on an asyncio event loop.
+    Loop(PyObject),
+    /// Scheduled on a trio run, with its token.
+    Token(PyObject),
+}
+
+impl CallbackExecutor {
+    fn new(py: Python<'_>, executor: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
+        let Some(executor) = executor else {
+            return Ok(CallbackExecutor::Inline);
+        };
+        if let Ok(name) = executor.downcast::<PyString>() {
+            return match name.to_str()? {
+                "inline" => Ok(CallbackExecutor::Inline),
+                name => Err(PyValueError::new_err(format!("unknown callback executor: {}", name))),
+            };
+        }
+        if let Ok(workers) = executor.extract::<usize>() {
+            if workers == 0 {
+                return Err(PyValueError::new_err("callback_executor must be at least 1"));
+            }
+            let kwargs = [("thread_name_prefix", "pycurse-callback")].into_py_dict_bound(py);
+            let pool = py
+                .import_bound("concurrent.futures")?
+                .getattr("ThreadPoolExecutor")?
+                .call((workers,), Some(&kwargs))?;
+            return Ok(CallbackExecutor::Submit(pool.unbind()));
+        }
+        if executor.hasattr("call_soon_threadsafe")? {
+            Ok(CallbackExecutor::Loop(executor.clone().unbind()))
+        } else if executor.hasattr("run_sync_soon")? {
+            Ok(CallbackExecutor::Token(executor.clone().unbind()))
+        } else if executor.hasattr("submit")? {
+            Ok(CallbackExecutor::Submit(executor.clone().unbind()))
+        } else {
+            Err(PyTypeError::new_err(
+                "callback_executor must be 'inline', a number of threads, an executor, an event loop or a trio token",
+            ))
+        }
+    }
+
+    fn clone_ref(&self, py: Python<'_>) -> Self {
+        match self {
+            CallbackExecutor::Inline => CallbackExecutor::Inline,
+            CallbackExecutor::Submit(executor) => CallbackExecutor::Submit(executor.clone_ref(py)),
+            CallbackExecutor::Loop(event_loop) => CallbackExecutor::Loop(event_loop.clone_ref(py)),
+            CallbackExecutor::Token(token) => CallbackExecutor::Token(token.clone_ref(py)),
+        }
+    }
+
+    fn run(&self, py: Python<'_>, call: Py<CallbackCall>) -> PyResult<()> {
+        match self {
+            CallbackExecutor::Inline => {
+                call.borrow(py).__call__(py);
+            }
+            CallbackExecutor::Submit(executor) => {
+                executor.call_method1(py, "submit", (call,))?;
+            }
+            CallbackExecutor::Loop(event_loop) => {
+                event_loop.call_method1(py, "call_soon_threadsafe", (call,))?;
+            }
+            CallbackExecutor::Token(token) => {
+                token.call_method1(py, "run_sync_soon", (call,))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The headers of a response whose body is still downloading.
+#[pyclass]
+struct ResponseHeaders {
+    url: String,
+    status_code: i64,
+    headers: Vec<(String, String)>,
+    http_version: Option<&'static str>,
+    abort: Arc<AtomicBool>,
+}
+
+#[pymethods]
+impl ResponseHeaders {
+    #[getter]
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    #[getter]
+    fn status_code(&self) -> i64 {
+        self.status_code
+    }
+
+    /// The headers of the response, as a list of `(name, value)` pairs.
+    #[getter]
+    fn headers(&self) -> Vec<(&str, &str)> {
+        self.headers.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect()
+    }
+
+    /// The last value of the header `name`, compared case-insensitively.
+    #[pyo3(signature = (name, default=None))]
+    fn header<'a>(&'a self, name: &str, default: Option<&'a str>) -> Option<&'a str> {
+        let value = self.headers.iter().rev().find(|(key, _)| key.eq_ignore_ascii_case(name));
+        value.map(|(_, value)| value.as_str()).or(default)
+    }
+
+    #[getter]
+    fn http_version(&self) -> Option<&str> {
+        self.http_version
+    }
+
+    /// Stop the transfer as its next data arrives.
+    fn abort(&self) {
+        self.abort.store(true, Ordering::Relaxed);
+    }
 }
 
+/// What the callback thread hands to the Python callbacks.
+enum Event {
+    /// The response of a request with a `callback`.
+    Response(Box<Response>),
+    /// The headers of a response for its `on_headers` callback, with the
+    /// body still on its way.
+    Headers(Arc<PyObject>, ResponseHeaders),
+}
 
-struct Collector(Vec<u8>);
-impl Handler for Collector {
-    fn write(&mut self, data: &[u8]) -> Result<usize, WriteError> {
-        self.0.extend_from_slice(data);
-        Ok(data.len())
+/// A callback to call with its response, or the headers of the response.
+#[pyclass]
+struct CallbackCall {
+    callback: PyObject,
+    argument: PyObject,
+}
+
+#[pymethods]
+impl CallbackCall {
+    /// Exceptions go to `sys.unraisablehook`, as nothing waits for the
+    /// result.
+    fn __call__(&self, py: Python<'_>) {
+        if let Err(error) = self.callback.call1(py, (self.argument.clone_ref(py),)) {
+            error.write_unraisable_bound(py, Some(self.callback.bind(py)));
+        }
     }
 }
 
-struct Downloader {
-    task_sender: Sender<String>,
-    task_receiver: Receiver<String>,
-    response_sender: Sender<Response>,
-    response_receiver: Receiver<Response>,
-    running: bool,
+/// The responses received from the downloader thread, shared by the Python
+/// threads fetching them.
+///
+/// One thread at a time receives from the downloader thread, the others
+/// wait for it to signal `arrived`.
+#[derive(Default)]
+struct Inbox {
+    state: Mutex<InboxState>,
+    arrived: Condvar,
+}
+
+#[derive(Default)]
+struct InboxState {
+    /// The number of requests added, and of responses delivered.
+    submitted: u64,
+    delivered: u64,
+    /// Responses received but not returned yet, by sequence.
+    received: BTreeMap<u64, Response>,
+    /// The sequence of the next response in ordered mode, and the later
+    /// ones returned by `wait()` already or going to their callback.
+    next_sequence: u64,
+    waited: HashSet<u64>,
+    /// The number of requests whose response goes to their callback.
+    callbacks: u64,
+    /// The handles `wait()` waits for, which `fetch()` leaves alone.
+    awaited: HashSet<u64>,
+    /// The tickets of the threads in `fetch()`, served in turn.
+    fetchers: VecDeque<u64>,
+    next_ticket: u64,
+    /// Set while a thread receives from the downloader thread.
+    receiving: bool,
+    /// The paginations whose next page depends on the response of the
+    /// request with this sequence.
+    pages: HashMap<u64, Pagination>,
+}
+
+impl InboxState {
+    /// Take the response of `handle`, or the next one for the fetcher
+    /// with this ticket if it comes first.
+    fn pick(&mut self, handle: Option<u64>, ticket: u64, ordered: bool) -> Option<Response> {
+        let sequence = match handle {
+            Some(handle) => handle,
+            None if self.fetchers.front() != Some(&ticket) => return None,
+            None if ordered => {
+                while self.waited.remove(&self.next_sequence) {
+                    self.next_sequence += 1;
+                }
+                if self.awaited.contains(&self.next_sequence) {
+                    return None;
+                }
+                self.next_sequence
+            }
+            None => *self.received.keys().find(|sequence| !self.awaited.contains(sequence))?,
+        };
+        let response = self.received.remove(&sequence)?;
+        self.delivered += 1;
+        if ordered {
+            if sequence == self.next_sequence {
+                self.next_sequence += 1;
+            } else if sequence > self.next_sequence {
+                self.waited.insert(sequence);
+            }
+        }
+        Some(response)
+    }
+}
+
+/// The place of a Python thread waiting for a response in the inbox.
+struct Turn<'a> {
+    inbox: &'a Inbox,
+    /// The handle `wait()` waits for, or None in `fetch()`.
+    handle: Option<u64>,
+    ticket: u64,
+}
+
+impl<'a> Turn<'a> {
+    fn new(inbox: &'a Inbox, handle: Option<u64>) -> Self {
+        let mut state = inbox.state.lock().unwrap();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        match handle {
+            Some(handle) => {
+                state.awaited.insert(handle);
+            }
+            None => state.fetchers.push_back(ticket),
+        }
+        Turn { inbox, handle, ticket }
+    }
+
+    /// Wait until `deadline` for the response of this turn.
+    fn take(
+        &self,
+        receiver: &Receiver<Response>,
+        ordered: bool,
+        deadline: Instant,
+    ) -> Result<Response, RecvTimeoutError> {
+        let mut state = self.inbox.state.lock().unwrap();
+        loop {
+            if let Some(response) = state.pick(self.handle, self.ticket, ordered) {
+                // The next fetcher may have a response waiting too.
+                self.inbox.arrived.notify_all();
+                return Ok(response);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            if state.receiving {
+                state = self.inbox.arrived.wait_timeout(state, deadline - now).unwrap().0;
+                continue;
+            }
+            state.receiving = true;
+            drop(state);
+            let received = receiver.recv_deadline(deadline);
+            state = self.inbox.state.lock().unwrap();
+            state.receiving = false;
+            self.inbox.arrived.notify_all();
+            match received {
+                Ok(response) => {
+                    state.received.insert(response.sequence, response);
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+    }
+
+    /// Up to `max` responses of this turn received already, without
+    /// waiting.
+    fn take_available(&self, receiver: &Receiver<Response>, ordered: bool, max: usize) -> Vec<Response> {
+        let mut state = self.inbox.state.lock().unwrap();
+        for response in receiver.try_iter().take(max) {
+            state.received.insert(response.sequence, response);
+        }
+        let mut responses = Vec::new();
+        while responses.len() < max {
+            match state.pick(self.handle, self.ticket, ordered) {
+                Some(response) => responses.push(response),
+                None => break,
+            }
+        }
+        self.inbox.arrived.notify_all();
+        responses
+    }
 }
 
-impl Drop for Downloader {
+impl Drop for Turn<'_> {
     fn drop(&mut self) {
-        self.running = false;
+        let mut state = self.inbox.state.lock().unwrap();
+        match self.handle {
+            Some(handle) => {
+                state.awaited.remove(&handle);
+            }
+            None => state.fetchers.retain(|ticket| *ticket != self.ticket),
+        }
+        self.inbox.arrived.notify_all();
     }
 }
 
-impl Downloader {
-    fn new() -> Self {
-        let (task_sender, task_receiver) =  unbounded();
-        let (response_sender, response_receiver) = unbounded();
+/// A request whose response links to a next page to request.
+struct Pagination {
+    /// The request of the page, copied for the next one.
+    request: Request,
+    /// The number of the page, from 1.
+    page: u32,
+    max_pages: Option<u32>,
+    /// Called with the response, returns the URL of the next page.
+    next_page: Option<PyObject>,
+}
+
+/// A dict of the variables named, by name, for `CurlDownloader::config()`.
+macro_rules! settings {
+    ($py:expr, $($name:ident),* $(,)?) => {
+        [$((stringify!($name), $name.to_object($py))),*].into_py_dict_bound($py).unbind()
+    };
+}
+
+#[pymethods]
+impl CurlDownloader {
+    /// Failed transfers are tried again up to `max_retries` times. With
+    /// `fail_on_status`, 4xx and 5xx responses count as failures too.
+    ///
+    /// A retry waits a random time up to `retry_backoff` seconds, doubled
+    /// for each next retry and capped at `retry_max_backoff`: the retries
+    /// of transfers failing together spread out instead of hitting the
+    /// server at once. By default retries start right away. With
+    /// `retry_budget`, a ratio like 0.2, the retries over the last
+    /// `retry_budget_window` seconds may not exceed that share of the
+    /// requests; past it, failures are returned without retry.
+    ///
+    /// `host_max_bytes` and `host_max_time` are quotas per host: the bytes
+    /// downloaded from it and the seconds spent in its transfers over the
+    /// last `host_quota_window` seconds. Once a host used up its quota, the
+    /// next requests to it wait until it is under it again or, with
+    /// `host_quota_exceeded='reject'`, fail with a `host quota exceeded`
+    /// error. The running transfers still complete.
+    ///
+    /// `max_total_bytes`, `max_requests` and `max_elapsed` stop a whole
+    /// job: once the downloader downloaded that many bytes, started that
+    /// many transfers, retries and redirects included, or ran for that many
+    /// seconds, it starts no more transfers. The running ones complete,
+    /// the requests not started fail with a `downloader stopped` error
+    /// telling the condition reached, and `stop_reason` tells it too.
+    ///
+    /// `text` decodes bodies with the charset of their `Content-Type`. With
+    /// `sniff_charset`, HTML bodies without one are searched for a
+    /// `<meta charset>` in their first kilobyte, as browsers do.
+    ///
+    /// With `decompress`, the encodings supported by libcurl are requested
+    /// with `Accept-Encoding`, and compressed bodies are decoded as they are
+    /// received: the compressed body is never held in memory. Downloads
+    /// with `resume` are not compressed, as their ranges would apply to the
+    /// compressed body. Stacked encodings like `gzip, gzip` are decoded in
+    /// turn, up to 5 of them. With `decode_content=False`, which requests
+    /// can override, `Content-Encoding` is ignored and the bodies are kept
+    /// as received: for servers lying about the encoding, or to store the
+    /// compressed bodies.
+    ///
+    /// For ancient servers, like the ones of embedded devices, both off by
+    /// default: `allow_http09` accepts HTTP/0.9 responses, a body without
+    /// status line nor headers, with the status code 0, instead of failing
+    /// them as an unsupported protocol; `ignore_content_length`
+    /// ignores the `Content-Length` of the responses, for servers sending a
+    /// wrong one, and reads the bodies until the connection closes.
+    /// Requests can override them.
+    ///
+    /// `tls_min_version` and `tls_max_version` restrict the TLS versions
+    /// negotiated with servers: `1.0`, `1.1`, `1.2` or `1.3`. `tls_ciphers`
+    /// is the OpenSSL cipher list used up to TLS 1.2, like
+    /// `ECDHE-RSA-AES128-GCM-SHA256:ECDHE-RSA-AES256-GCM-SHA384`, and
+    /// `tls13_ciphers` the TLS 1.3 cipher suites, like
+    /// `TLS_AES_256_GCM_SHA384`. With `alpn=False`, the HTTP version is not
+    /// negotiated in the TLS handshake, so HTTPS requests use HTTP/1.1.
+    ///
+    /// With `tls_session_cache=False`, TLS sessions are not resumed: each
+    /// connection does a full handshake, for servers mishandling session
+    /// IDs or tickets. `ssl_engine` is the OpenSSL engine used for the
+    /// cryptographic operations, like `pkcs11` for keys held by an HSM;
+    /// `ValueError` is raised when libcurl cannot load it. Both apply to
+    /// all the requests of the downloader.
+    ///
+    /// `http_version` is the HTTP version used: `1.0`, `1.1`, `2`, which
+    /// falls back to HTTP/1.1 when the server does not support it, or
+    /// `2-prior-knowledge` for HTTP/2 without negotiation. By default HTTP/2
+    /// is used over TLS when the server offers it. `http_version` of
+    /// responses tells the version used.
+    ///
+    /// `connect_to` is a list of `HOST:PORT:CONNECT-TO-HOST:CONNECT-TO-PORT`
+    /// entries: a request for `HOST:PORT` connects to `CONNECT-TO-HOST` and
+    /// `CONNECT-TO-PORT` instead, while the `Host` header, TLS SNI and
+    /// certificate check still use `HOST`. Empty fields match any host or
+    /// port, or keep them: `example.com:443:10.0.0.7:` sends requests for
+    /// `https://example.com/` to 10.0.0.7.
+    ///
+    /// With a libcurl built with c-ares, `dns_servers` is a list of
+    /// `host[:port]` resolvers used instead of the ones of
+    /// `/etc/resolv.conf`. The DNS queries are sent from the network
+    /// interface `dns_interface`, or from the local addresses
+    /// `dns_local_ip4` and `dns_local_ip6`. `ValueError` is raised when
+    /// libcurl does not use c-ares, which `curl_info()` tells. Resolved
+    /// addresses are cached for `dns_cache_timeout` seconds, 60 by default,
+    /// rounded down to whole seconds: a lower one, or zero not to cache
+    /// them, picks up the DNS changes of a failover sooner.
+    ///
+    /// Connections stay open to be reused by later requests. A connection
+    /// idle for more than `max_connection_age` seconds (118 by default),
+    /// or opened more than `max_connection_lifetime` seconds ago, is closed
+    /// instead of being reused, before a load balancer silently drops it.
+    /// Both are rounded down to whole seconds. At most
+    /// `connection_cache_size` idle connections are kept, by default four
+    /// times the number of transfers running, and `close_idle_connections()`
+    /// closes them.
+    ///
+    /// At most `max_total_connections` connections are open at a time, and
+    /// `max_host_connections` to the same host: the transfers over the
+    /// limit wait for a connection, in libcurl, unlike the ones over
+    /// `max_transfers` which wait in the queue. An HTTP/2 connection
+    /// carries up to `max_concurrent_streams` transfers at a time, 100 by
+    /// default, and with `multiplex=False` only one: transfers then open
+    /// connections of their own. libcurl no longer supports HTTP/1.1
+    /// pipelining.
+    ///
+    /// A streamed body buffers at most `max_stream_chunks` chunks of up to
+    /// 16 KiB: past that, its transfer is paused until the chunks are read.
+    ///
+    /// A transfer receiving no bytes for `inactivity_timeout` seconds, by
+    /// default without limit, fails with the error `inactivity timeout`,
+    /// which is not retried: reading a streamed body then raises
+    /// `InactivityTimeout`. Unlike a `deadline`, it lets a long-lived
+    /// stream run for hours as long as something arrives, like the
+    /// keep-alive comments of server-sent events. The time a streamed
+    /// transfer is paused, its chunks not read, does not count.
+    ///
+    /// A response whose header block is over `max_header_bytes` bytes, or
+    /// with more than `max_headers` headers, fails without retry as soon as
+    /// it goes over, so a hostile server cannot fill the memory with
+    /// headers. libcurl refuses header blocks over 300 KiB anyway.
+    ///
+    /// `profile`, `chrome`, `firefox` or `safari`, sends the `User-Agent`,
+    /// `Accept`, `Accept-Language` and, for Chrome, `sec-ch-ua` headers of
+    /// that browser on Windows, or macOS for Safari, with every request.
+    /// `locale`, like `de-DE`, is the language of its `Accept-Language`,
+    /// `en-US` by default; without `profile`, it only sets that header. The
+    /// `headers` of a request replace the ones of the same name.
+    ///
+    /// In `ordered` mode, `fetch()` and iteration return the responses in
+    /// the order the requests were added: a response received early waits
+    /// for the ones of the requests added before. The requests with a
+    /// callback are not waited for.
+    ///
+    /// Requests can override these settings, except the DNS and connection
+    /// cache ones; their `connect_to` entries come before the ones of the
+    /// downloader.
+    ///
+    /// With `respect_robots`, the robots.txt file of each site is downloaded
+    /// and cached for a day: URLs it disallows for `robots_agent` fail
+    /// without being requested, and requests to a site are spaced by its
+    /// `Crawl-delay`. A missing robots.txt, a 4xx, allows everything. An
+    /// unreachable one, a transfer failure or a 5xx, disallows everything
+    /// for a minute, doubling with each failure in a row up to 30 minutes.
+    ///
+    /// `allowed_hosts` and `denied_hosts` are lists of host patterns: globs
+    /// like `*.example.com`, or suffix rules like `.example.com` matching
+    /// the domain and its subdomains. Requests, and the redirects they
+    /// follow, to a host denied or not allowed fail with a `host not
+    /// allowed` error without being sent; denied patterns win.
+    ///
+    /// `host_map` routes hosts to others as the requests are added: a dict,
+    /// or a list of pairs, of host patterns to `HOST` or `HOST:PORT`, the
+    /// first matching pattern applying, like `{"example.com":
+    /// "mirror.example.org"}`. `force_https` is a list of host patterns,
+    /// like `["*"]`, whose `http` URLs are requested over `https`. Both
+    /// match the host of the URL as given, next pages included but not
+    /// redirects; the host policy and robots.txt apply to the rewritten
+    /// URL, and `original_url` of the response keeps the one given.
+    ///
+    /// `span_exporter` is called with a dict describing the span of each
+    /// request, by `fetch()`, by `request()` or before the `callback` of
+    /// the request, in OpenTelemetry terms: `name`, `trace_id`, `span_id`,
+    /// `parent_span_id`, `start_time` and `end_time` in
+    /// nanoseconds since the epoch, and `attributes` with the time spent in
+    /// the queue, in DNS resolution, connecting, in the TLS handshake, until
+    /// the first byte and in total. With `inject_traceparent`, the span is
+    /// propagated to the server with a W3C `traceparent` header.
+    ///
+    /// Each downloader runs its own thread. While transfers are running, it
+    /// waits for network activity at most `poll_interval` seconds between
+    /// iterations; when idle, it waits for a new request at most
+    /// `idle_timeout` seconds. A new request wakes the thread up
+    /// immediately. Up to `batch_size` queued requests are started per
+    /// iteration.
+    ///
+    /// With `max_buffered_bytes`, no new transfer starts while the bodies of
+    /// the responses not fetched yet add up to that many bytes; the running
+    /// transfers still complete. Requests wait in the queue until `fetch()`
+    /// makes room.
+    ///
+    /// With `compress_buffered`, the downloader thread compresses those
+    /// bodies with zstd, and `fetch()` or the callback executor
+    /// decompresses them: a crawl adding requests much faster than it
+    /// fetches holds less memory, for some CPU. Bodies under 1 KiB or which
+    /// do not shrink are kept as they are, and `max_buffered_bytes` counts
+    /// the compressed sizes.
+    ///
+    /// At most `max_transfers` transfers run at a time, and at most
+    /// `max_host_transfers` to the same host; the other requests wait.
+    /// Hosts take turns to start their waiting requests, so a host with a
+    /// long queue does not hold back the requests to the others.
+    ///
+    /// With `adaptive_throttle`, a host answering 429 Too Many Requests or
+    /// 503 Service Unavailable is slowed down: each time, the transfers
+    /// running to it are halved, down to one, and the wait between their
+    /// starts doubles, from 0.1 up to 60 seconds; the first slow down
+    /// starts from `max_host_transfers` transfers, 16 without. The
+    /// responses to the transfers started before a slow down do not slow
+    /// down again. Each success brings the host back gradually.
+    ///
+    /// With `archive`, the path of a file, every response is appended to
+    /// it as it is received, by the downloader thread, in the
+    /// `archive_format` `warc` or `jsonl`, by default the one of the
+    /// extension of the file. A WARC file gets a `response` record per
+    /// response, failed requests left out, with the headers curl decoded,
+    /// like `Content-Encoding`, renamed `X-Archive-Orig-*`. A JSONL file
+    /// gets an object per line with the `url`, `status_code`,
+    /// `http_version`, `headers`, `body`, or `body_base64` when it is not
+    /// UTF-8, `error`, `redirects` and the `queued`, `started` and
+    /// `finished` times in seconds since the epoch. The bodies of streamed
+    /// responses are not archived, neither are the ones saved to `path` or
+    /// written `into` a buffer.
+    ///
+    /// `config()` and `to_toml()` export these settings, and `from_config()`
+    /// and `from_toml()` create a downloader from them, to keep them in a
+    /// configuration file.
+    ///
+    /// `callback_executor` tells how the callbacks of the requests are
+    /// called: `'inline'`, the default, calls them one after the other in a
+    /// thread receiving the responses; a number of threads calls them in a
+    /// thread pool of that size; a `concurrent.futures` executor, an
+    /// asyncio event loop or a trio token gets them submitted, or scheduled
+    /// with `call_soon_threadsafe()` or `run_sync_soon()`. Slow callbacks
+    /// should not run inline, as they delay the responses of the next ones.
+    /// `pycurse.aio` uses callbacks to await responses from asyncio, trio
+    /// and anyio.
+    ///
+    /// `clock`, a `pycurse.testing.FakeClock`, replaces the real clock of
+    /// the retry backoff and the deadlines: advancing it starts the waiting
+    /// retries and expires the deadlines without waiting, and `request()`
+    /// advances it instead of sleeping between its retries. The timeouts of
+    /// curl, running transfers included, keep the real time.
+    #[new]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (
+        *, max_retries=0, fail_on_status=false, sniff_charset=false, decompress=false, decode_content=true,
+        allow_http09=false, ignore_content_length=false,
+        tls_min_version=None, tls_max_version=None, tls_ciphers=None, tls13_ciphers=None,
+        alpn=None, tls_session_cache=true, ssl_engine=None, http_version=None, connect_to=None,
+        dns_servers=None, dns_interface=None, dns_local_ip4=None, dns_local_ip6=None, dns_cache_timeout=None,
+        max_connection_age=None, max_connection_lifetime=None, connection_cache_size=None,
+        max_total_connections=None, max_host_connections=None, max_concurrent_streams=None, multiplex=None,
+        max_stream_chunks=16, inactivity_timeout=None, max_header_bytes=None, max_headers=None,
+        profile=None, locale=None,
+        ordered=false, respect_robots=false, robots_agent="curl", allowed_hosts=None, denied_hosts=None,
+        host_map=None, force_https=None, span_exporter=None, inject_traceparent=false,
+        poll_interval=0.01, idle_timeout=0.5, batch_size=1, max_buffered_bytes=None, compress_buffered=false,
+        max_transfers=None, max_host_transfers=None, retry_backoff=0.0, retry_max_backoff=30.0,
+        retry_budget=None, retry_budget_window=10.0,
+        host_max_bytes=None, host_max_time=None, host_quota_window=60.0, host_quota_exceeded="defer",
+        max_total_bytes=None, max_requests=None, max_elapsed=None,
+        adaptive_throttle=false, archive=None, archive_format=None,
+        callback_executor=None, clock=None,
+    ))]
+    fn new(
+        py: Python<'_>,
+        max_retries: u32,
+        fail_on_status: bool,
+        sniff_charset: bool,
+        decompress: bool,
+        decode_content: bool,
+        allow_http09: bool,
+        ignore_content_length: bool,
+        tls_min_version: Option<&str>,
+        tls_max_version: Option<&str>,
+        tls_ciphers: Option<String>,
+        tls13_ciphers: Option<String>,
+        alpn: Option<bool>,
+        tls_session_cache: bool,
+        ssl_engine: Option<String>,
+        http_version: Option<&str>,
+        connect_to: Option<Vec<String>>,
+        dns_servers: Option<Vec<String>>,
+        dns_interface: Option<String>,
+        dns_local_ip4: Option<String>,
+        dns_local_ip6: Option<String>,
+        dns_cache_timeout: Option<f64>,
+        max_connection_age: Option<f64>,
+        max_connection_lifetime: Option<f64>,
+        connection_cache_size: Option<usize>,
+        max_total_connections: Option<usize>,
+        max_host_connections: Option<usize>,
+        max_concurrent_streams: Option<usize>,
+        multiplex: Option<bool>,
+        max_stream_chunks: usize,
+        inactivity_timeout: Option<f64>,
+        max_header_bytes: Option<usize>,
+        max_headers: Option<usize>,
+        profile: Option<&str>,
+        locale: Option<String>,
+        ordered: bool,
+        respect_robots: bool,
+        robots_agent: &str,
+        allowed_hosts: Option<Vec<String>>,
+        denied_hosts: Option<Vec<String>>,
+        host_map: Option<&Bound<'_, PyAny>>,
+        force_https: Option<Vec<String>>,
+        span_exporter: Option<PyObject>,
+        inject_traceparent: bool,
+        poll_interval: f64,
+        idle_timeout: f64,
+        batch_size: usize,
+        max_buffered_bytes: Option<usize>,
+        compress_buffered: bool,
+        max_transfers: Option<usize>,
+        max_host_transfers: Option<usize>,
+        retry_backoff: f64,
+        retry_max_backoff: f64,
+        retry_budget: Option<f64>,
+        retry_budget_window: f64,
+        host_max_bytes: Option<u64>,
+        host_max_time: Option<f64>,
+        host_quota_window: f64,
+        host_quota_exceeded: &str,
+        max_total_bytes: Option<u64>,
+        max_requests: Option<u64>,
+        max_elapsed: Option<f64>,
+        adaptive_throttle: bool,
+        archive: Option<&str>,
+        archive_format: Option<&str>,
+        callback_executor: Option<&Bound<'_, PyAny>>,
+        clock: Option<Bound<'_, ManualClock>>,
+    ) -> PyResult<Self> {
+        init::init(None)?;
+        let host_map = host_map.map(rewrite::host_map_pairs).transpose()?;
+        let config = settings!(
+            py, max_retries, fail_on_status, sniff_charset, decompress, decode_content,
+            allow_http09, ignore_content_length,
+            tls_min_version, tls_max_version, tls_ciphers, tls13_ciphers,
+            alpn, tls_session_cache, ssl_engine, http_version, connect_to,
+            dns_servers, dns_interface, dns_local_ip4, dns_local_ip6, dns_cache_timeout,
+            max_connection_age, max_connection_lifetime, connection_cache_size,
+            max_total_connections, max_host_connections, max_concurrent_streams, multiplex,
+            max_stream_chunks, inactivity_timeout, max_header_bytes, max_headers, profile, locale,
+            ordered, respect_robots, robots_agent, allowed_hosts, denied_hosts,
+            host_map, force_https,
+            inject_traceparent,
+            poll_interval, idle_timeout, batch_size, max_buffered_bytes, compress_buffered,
+            max_transfers, max_host_transfers, retry_backoff, retry_max_backoff,
+            retry_budget, retry_budget_window,
+            host_max_bytes, host_max_time, host_quota_window, host_quota_exceeded,
+            max_total_bytes, max_requests, max_elapsed,
+            adaptive_throttle, archive, archive_format,
+        );
+        if batch_size == 0 {
+            return Err(PyValueError::new_err("batch_size must be at least 1"));
+        }
+        if max_stream_chunks == 0 {
+            return Err(PyValueError::new_err("max_stream_chunks must be at least 1"));
+        }
+        if max_transfers == Some(0) || max_host_transfers == Some(0) {
+            return Err(PyValueError::new_err("max_transfers and max_host_transfers must be at least 1"));
+        }
+        let scheduler = SchedulerConfig {
+            poll_interval: seconds(poll_interval, "poll_interval")?,
+            idle_timeout: seconds(idle_timeout, "idle_timeout")?,
+            batch_size,
+            max_buffered_bytes,
+            compress_buffered,
+            max_transfers,
+            max_host_transfers,
+            retry_backoff: Backoff {
+                base: seconds(retry_backoff, "retry_backoff")?,
+                max: seconds(retry_max_backoff, "retry_max_backoff")?,
+            },
+        };
+        let limits = [max_total_connections, max_host_connections, max_concurrent_streams];
+        if limits.contains(&Some(0)) {
+            return Err(PyValueError::new_err(
+                "max_total_connections, max_host_connections and max_concurrent_streams must be at least 1",
+            ));
+        }
+        let multi_config = MultiConfig {
+            max_connects: connection_cache_size,
+            max_total_connections,
+            max_host_connections,
+            max_concurrent_streams,
+            multiplex,
+        };
+        multi_config
+            .apply(&mut Multi::new())
+            .map_err(|error| PyValueError::new_err(format!("unsupported multi option: {}", error)))?;
+        let retry_budget = match retry_budget {
+            Some(ratio) if !(0.0..=1.0).contains(&ratio) => {
+                return Err(PyValueError::new_err("retry_budget must be between 0 and 1"));
+            }
+            Some(ratio) => Some(RetryBudget::new(ratio, seconds(retry_budget_window, "retry_budget_window")?)),
+            None => None,
+        };
+        let defer = match host_quota_exceeded {
+            "defer" => true,
+            "reject" => false,
+            _ => return Err(PyValueError::new_err("host_quota_exceeded must be 'defer' or 'reject'")),
+        };
+        let host_max_time = host_max_time.map(|time| seconds(time, "host_max_time")).transpose()?;
+        if host_max_bytes == Some(0) || host_max_time.is_some_and(|time| time.is_zero()) {
+            return Err(PyValueError::new_err("host_max_bytes and host_max_time must be positive"));
+        }
+        let host_quotas = (host_max_bytes.is_some() || host_max_time.is_some()).then(|| {
+            seconds(host_quota_window, "host_quota_window")
+                .map(|window| HostQuotas::new(host_max_bytes, host_max_time, window, defer))
+        });
+        let max_elapsed = max_elapsed.map(|elapsed| seconds(elapsed, "max_elapsed")).transpose()?;
+        if max_total_bytes == Some(0) || max_requests == Some(0) || max_elapsed.is_some_and(|time| time.is_zero()) {
+            return Err(PyValueError::new_err("max_total_bytes, max_requests and max_elapsed must be positive"));
+        }
+        let stop_conditions = (max_total_bytes.is_some() || max_requests.is_some() || max_elapsed.is_some())
+            .then(|| StopConditions::new(max_total_bytes, max_requests, max_elapsed));
+        let host_policy = (allowed_hosts.is_some() || denied_hosts.is_some())
+            .then(|| HostPolicy::new(allowed_hosts.unwrap_or_default(), denied_hosts.unwrap_or_default()));
+        let url_rewrites = (host_map.is_some() || force_https.is_some())
+            .then(|| UrlRewrites::new(host_map.unwrap_or_default(), force_https.unwrap_or_default()))
+            .transpose()?;
+        let host_throttle = adaptive_throttle.then(|| HostThrottle::new(max_host_transfers.unwrap_or(16)));
+        let host_quotas = host_quotas.transpose()?;
+        let archive = archive
+            .map(|path| {
+                let format = archive::Format::new(archive_format, path).map_err(PyValueError::new_err)?;
+                PyResult::Ok(Archive::open(path, format)?)
+            })
+            .transpose()?;
+        let downloader = Downloader::new(
+            scheduler,
+            multi_config,
+            retry_budget,
+            host_quotas,
+            host_throttle,
+            host_policy,
+            stop_conditions,
+            archive,
+            clock.map(Bound::unbind),
+        )
+        .map_err(|error| PyRuntimeError::new_err(format!("cannot create the event loop: {}", error)))?;
+        let curl_downloader = CurlDownloader {
+            downloader: Arc::new(downloader),
+            max_retries,
+            fail_on_status,
+            sniff_charset,
+            decompress,
+            decode_content,
+            allow_http09,
+            ignore_content_length,
+            tls: TlsOptions::new(tls_min_version, tls_max_version, tls_ciphers, tls13_ciphers, alpn)?
+                .with_downloader_settings(tls_session_cache, ssl_engine)?,
+            http_version: http_version.map(parse_http_version).transpose()?,
+            connect_to: connect_to
+                .map(|entries| entries.iter().try_for_each(|entry| options::check_connect_to(entry)).map(|()| entries))
+                .transpose()?
+                .unwrap_or_default(),
+            dns: DnsOptions {
+                cache_timeout: dns_cache_timeout
+                    .map(|timeout| seconds(timeout, "dns_cache_timeout"))
+                    .transpose()?,
+                ..DnsOptions::new(dns_servers, dns_interface, dns_local_ip4, dns_local_ip6)?
+            },
+            connection: ConnectionOptions {
+                max_age: max_connection_age.map(|age| seconds(age, "max_connection_age")).transpose()?,
+                max_lifetime: max_connection_lifetime
+                    .map(|lifetime| seconds(lifetime, "max_connection_lifetime"))
+                    .transpose()?,
+                ..Default::default()
+            },
+            profile: profile.map(Profile::parse).transpose().map_err(PyValueError::new_err)?,
+            locale: locale.map(checked_locale).transpose()?,
+            robots_agent: respect_robots.then(|| robots_agent.to_owned()),
+            url_rewrites,
+            span_exporter,
+            inject_traceparent,
+            max_stream_chunks,
+            inactivity_timeout: checked_inactivity_timeout(inactivity_timeout)?,
+            max_header_bytes,
+            max_headers,
+            ordered,
+            inbox: Inbox::default(),
+            easy: Mutex::new(Vec::new()),
+            callback_executor: CallbackExecutor::new(py, callback_executor)?,
+            callback_thread: Mutex::new(false),
+            draining: AtomicBool::new(false),
+            config,
+        };
+        // Started once every setting is checked: an invalid one leaves no
+        // thread behind.
+        let worker = Arc::clone(&curl_downloader.downloader);
+        thread::Builder::new()
+            .name("pycurse".to_owned())
+            .spawn(move || worker.thread_runner())
+            .map_err(|error| PyRuntimeError::new_err(format!("cannot start the downloader thread: {}", error)))?;
+        Ok(curl_downloader)
+    }
+
+    /// The settings of the downloader: a dict of the keyword arguments it
+    /// was created with, and of the defaults of the others. `span_exporter`,
+    /// `callback_executor` and `clock`, Python objects, are left out.
+    fn config<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.config.bind(py).copy()
+    }
+
+    /// The settings of `config()` as a TOML document, without the ones left
+    /// to None.
+    fn to_toml(&self, py: Python<'_>) -> PyResult<String> {
+        toml::dumps(self.config.bind(py))
+    }
 
-        Downloader {
-            task_sender: task_sender,
-            task_receiver: task_receiver,
-            response_sender: response_sender,
-            response_receiver: response_receiver,
-            running: true,
+    /// Create a downloader with the settings of `config`, a dict like the
+    /// one of `config()`, and the keyword arguments `overrides`, which win:
+    /// `span_exporter`, `callback_executor` and `clock` are given that way.
+    /// Unknown settings raise `TypeError`.
+    #[classmethod]
+    #[pyo3(signature = (config, **overrides))]
+    fn from_config<'py>(
+        cls: &Bound<'py, PyType>,
+        config: &Bound<'py, PyDict>,
+        overrides: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let config = config.copy()?;
+        if let Some(overrides) = overrides {
+            config.update(overrides.as_mapping())?;
         }
+        cls.call((), Some(&config))
+    }
+
+    /// Create a downloader with the settings of the TOML document `toml`,
+    /// like the one of `to_toml()`, see `from_config()`. With `table`, like
+    /// `tool.pycurse`, the settings are the keys of that table of the
+    /// document instead of its top-level ones. `ValueError` is raised for
+    /// an invalid document.
+    #[classmethod]
+    #[pyo3(signature = (toml, table=None, **overrides))]
+    fn from_toml<'py>(
+        cls: &Bound<'py, PyType>,
+        toml: &str,
+        table: Option<&str>,
+        overrides: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = cls.py();
+        let config = toml::parse(toml, table).map_err(PyValueError::new_err)?.to_python(py)?;
+        Self::from_config(cls, config.downcast_bound(py)?, overrides)
     }
 
-    fn add_request(&mut self, url: &str) -> PyResult<()> {
-        match self.task_sender.send(url.to_owned()) {
-            Err(_) => return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Failed to send task")),
-            Ok(_) => return Ok(()),
+    /// Initialize curl downloader with the URL.
+    ///
+    /// Returns the handle of the request, to wait for its response with
+    /// `wait()`.
+    ///
+    /// The options are checked before the request is queued: an invalid
+    /// value, like a `connect_to` entry not of the expected form or a
+    /// string with a NUL character, or options which cannot apply
+    /// together, like `stream` and `path`, raise `ValueError`.
+    ///
+    /// `headers` is a dict or a list of `(name, value)` pairs added to the
+    /// request; they replace the headers curl sends by default, like
+    /// `User-Agent`. Names and values are validated: `ValueError` is raised
+    /// for a value with a CR, an LF or another control character.
+    ///
+    /// If `json` is given, the object is serialized to JSON and sent as the
+    /// body of a POST request with `Content-Type: application/json`.
+    ///
+    /// If `data` is a dict, it is sent as an
+    /// `application/x-www-form-urlencoded` POST body; `str` and `bytes` are
+    /// sent as they are.
+    ///
+    /// `auth` is a `(username, password)` tuple and `auth_scheme` one of
+    /// `basic`, `digest`, `ntlm`, `negotiate` or `any`. Negotiate (Kerberos)
+    /// uses the credentials of the current ticket when `auth` is omitted.
+    ///
+    /// `proxy` is the proxy URL. With `proxy_tunnel`, plain HTTP requests
+    /// are also tunneled through `CONNECT`. `proxy_ca`, `proxy_cert` and
+    /// `proxy_key` are PEM files used for the TLS connection to an HTTPS
+    /// proxy.
+    ///
+    /// `tls_min_version`, `tls_max_version`, `tls_ciphers` and
+    /// `tls13_ciphers`, `alpn`, `http_version`, `decode_content`,
+    /// `allow_http09` and `ignore_content_length` override the settings of
+    /// the downloader, `connect_to` entries come
+    /// first.
+    ///
+    /// `host`, a name with an optional port, is the host the request is
+    /// made for while it still goes to the host of the URL:
+    /// `request('https://10.0.0.7/', host='example.com')` is a request for
+    /// `https://example.com/`, with its `Host` header, TLS SNI and
+    /// certificate check, sent to 10.0.0.7. The response has that URL. A
+    /// `Host` header naming another host than an HTTPS URL raises
+    /// `ValueError`, as the TLS SNI would not match it.
+    /// `proxy_tls_ciphers` and `proxy_tls13_ciphers` are the ciphers of the
+    /// connection to an HTTPS proxy.
+    ///
+    /// With `path`, the body is saved to that file instead of being kept in
+    /// memory. The file only appears once the download succeeded; with
+    /// `preserve_mtime` its modification time is the one sent by the server.
+    /// With `resume`, an interrupted download is kept and continued by the
+    /// next request for the same `path`, using a range request guarded by
+    /// `If-Range` so a changed resource is downloaded again from the start.
+    /// With `only_if_newer`, an existing file is only downloaded again if
+    /// the resource was modified after the file; otherwise the response has
+    /// `not_modified` set and the file is left untouched.
+    ///
+    /// `if_none_match` is an ETag, or a list of them, and `if_modified_since`
+    /// a `datetime` or a number of seconds since the epoch, sent as
+    /// conditional request headers: the response has `not_modified` set
+    /// when the server answers 304 Not Modified. Naive datetimes are in
+    /// local time, as with `datetime.timestamp()`. `if_modified_since`
+    /// replaces the time of the file of `only_if_newer`.
+    ///
+    /// With `fresh_connect`, the request opens a new connection instead of
+    /// reusing one, and with `forbid_reuse` its connection is closed
+    /// afterwards: health checks and latency probes measure a full
+    /// connection without disturbing the pool. With `fresh_dns`, the host
+    /// is resolved again instead of from the DNS cache, while a connection
+    /// already open to it is still reused without `fresh_connect`.
+    ///
+    /// `upload` is the path of a file sent as the body: with `PUT` to an
+    /// HTTP URL, or stored at the path of an `ftp://`, `ftps://` or
+    /// `sftp://` URL, when libcurl supports the protocol. The file is read
+    /// as it is sent. With `create_dirs`, the missing directories of an FTP
+    /// or SFTP upload are created.
+    ///
+    /// With `headers_only`, the transfer is aborted once the headers are
+    /// received, and the response has them with an empty body: a check of
+    /// the existence or the size of a resource, for the servers answering
+    /// `HEAD` requests wrongly. The connection is closed rather than reused.
+    ///
+    /// `accept_content_types` is a list of media types, like `text/html` or
+    /// `image/*`, and `max_content_length` a number of bytes: a successful
+    /// response of another type, or a body announced or received larger,
+    /// aborts the transfer as soon as it is known and fails the request
+    /// without retry, instead of downloading a body to discard.
+    ///
+    /// `into` is a writable buffer, like a `bytearray`, a `memoryview` or a
+    /// contiguous numpy array, the body is written to from its start instead
+    /// of `data`; `nbytes` is then the size of the body. A body larger
+    /// than the buffer fails the request without retry. The buffer must not
+    /// be used until the response is received.
+    ///
+    /// `process` is a list of steps run on the body in the downloader
+    /// thread once it is received, without the GIL, their results in
+    /// `processed`: `json` parses it like `json.loads()`, `crc32` is its
+    /// CRC-32 as an int, and `links` the list of the `href` and `src`
+    /// links of an HTML body, resolved against its `<base>` or URL, each
+    /// once and without fragment, only the http and https ones. `sitemap`
+    /// parses a sitemap, gzipped or not, into a dict of its `urls` and,
+    /// for a sitemap index, its `sitemaps`, and `feed` an RSS or Atom feed
+    /// into a dict of its `title`, `link` and `entries`. A step that fails, like `json` on an invalid
+    /// document, leaves the response as it is, with the reason in
+    /// `processing_errors`. The steps run on the final response, not on
+    /// the attempts retried.
+    ///
+    /// `digests` is a list of hash functions, `sha256` or `blake3`,
+    /// computed on the body as it is received, in memory, in `path` or in
+    /// `into`, their hex digests in `digests`. They are the ones of the
+    /// body decoded, unless `decode_content` is false, and of the whole
+    /// file for a resumed download.
+    ///
+    /// `ranges` is a list of `(first, last)` byte ranges requested, `last`
+    /// included, or None for the rest of the resource: the ranges of a 206
+    /// response are then in `parts`, a `multipart/byteranges` body split.
+    /// A server may also answer with the whole resource, a 200 without
+    /// `parts`, and a body which cannot be split is kept as received,
+    /// without `parts` either. Ranges are requested without compression.
+    ///
+    /// With `follow_redirects`, the redirects are followed, up to
+    /// `max_redirects` of them before failing the request. The response is
+    /// the final one, with the URLs redirected from in `redirects`. A 303,
+    /// or a 301 or 302 to a POST, is followed with a GET, and the
+    /// credentials and the `Authorization` and `Cookie` headers are not
+    /// sent to another origin: another host or port, or `http` after
+    /// `https`. Only `http` and `https` targets are followed, a redirect to
+    /// another scheme fails the request. `on_redirect`, which implies
+    /// following the redirects, is called in the downloader thread with the
+    /// URL, the target and the status code of each redirect: it returns
+    /// None or True to follow it, a URL to follow instead, or False to stop
+    /// and return the redirect as the response. The other transfers of the
+    /// downloader wait while it runs, so it should return quickly.
+    ///
+    /// With `stream`, the response is returned by `fetch()` as soon as its
+    /// body starts, and the body is read with `read_chunk()` or
+    /// `iter_chunks()` instead of `data`. A transfer whose chunks are not
+    /// read is paused, and dropping the response aborts it.
+    ///
+    /// With `paginate`, the pages of a paginated API are requested one after
+    /// the other: once `fetch()` returns the response of a page, the next
+    /// page is requested, with the same options, up to `max_pages` pages.
+    /// The next page is the `next` link of the `Link` header, or the URL
+    /// returned by `next_page`, called with the response; None ends the
+    /// pagination.
+    ///
+    /// `profile` and `locale` override the browser profile and the locale
+    /// of the downloader.
+    ///
+    /// `labels` is a dict of strings, like `{"engine": "google"}`, added to
+    /// the log events of the request and used to break down `stats()`.
+    ///
+    /// `traceparent` is the W3C trace context of the parent span of the
+    /// request span.
+    ///
+    /// `deadline` is a `datetime` or a number of seconds since the epoch
+    /// bounding the whole request: the time waiting in the queues, every
+    /// attempt with its redirects and the waits between retries. Once it
+    /// passes, the request fails with the error `deadline exceeded`, and
+    /// `raise_for_status()` raises `DeadlineExceeded`.
+    ///
+    /// `inactivity_timeout` overrides the one of the downloader: it bounds
+    /// the time without bytes received or sent rather than the whole
+    /// transfer, for streams.
+    ///
+    /// `callback` is called with the response instead of `fetch()`
+    /// returning it, as `callback_executor` tells. Callbacks do not apply to
+    /// paginations.
+    ///
+    /// `on_headers` is called the same way as soon as the headers of the
+    /// response are received, while the body is still downloading, with an
+    /// object holding the `url`, `status_code`, `headers` and
+    /// `http_version`; its `abort()` stops the transfer, which then fails
+    /// with the error `aborted` without being retried. It is called again
+    /// for each retry.
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (
+        url, *, headers=None, json=None, data=None, auth=None, auth_scheme=None,
+        proxy=None, proxy_tunnel=false, proxy_ca=None, proxy_cert=None, proxy_key=None,
+        proxy_tls_ciphers=None, proxy_tls13_ciphers=None,
+        tls_min_version=None, tls_max_version=None, tls_ciphers=None, tls13_ciphers=None,
+        alpn=None, http_version=None, connect_to=None, host=None, fresh_connect=false, fresh_dns=false,
+        forbid_reuse=false, path=None, preserve_mtime=false, resume=false, only_if_newer=false,
+        if_none_match=None, if_modified_since=None, upload=None, create_dirs=false, headers_only=false,
+        accept_content_types=None, max_content_length=None, into=None, decode_content=None,
+        allow_http09=None, ignore_content_length=None, process=None,
+        digests=None, ranges=None,
+        follow_redirects=false, max_redirects=10, on_redirect=None,
+        stream=false, paginate=false, max_pages=None, next_page=None,
+        profile=None, locale=None, labels=None, traceparent=None, deadline=None, inactivity_timeout=None,
+        callback=None, on_headers=None,
+    ))]
+    fn add_request(
+        &self,
+        py: Python<'_>,
+        url: &str,
+        headers: Option<&Bound<'_, PyAny>>,
+        json: Option<&Bound<'_, PyAny>>,
+        data: Option<&Bound<'_, PyAny>>,
+        auth: Option<(String, String)>,
+        auth_scheme: Option<&str>,
+        proxy: Option<String>,
+        proxy_tunnel: bool,
+        proxy_ca: Option<String>,
+        proxy_cert: Option<String>,
+        proxy_key: Option<String>,
+        proxy_tls_ciphers: Option<String>,
+        proxy_tls13_ciphers: Option<String>,
+        tls_min_version: Option<&str>,
+        tls_max_version: Option<&str>,
+        tls_ciphers: Option<String>,
+        tls13_ciphers: Option<String>,
+        alpn: Option<bool>,
+        http_version: Option<&str>,
+        connect_to: Option<Vec<String>>,
+        host: Option<&str>,
+        fresh_connect: bool,
+        fresh_dns: bool,
+        forbid_reuse: bool,
+        path: Option<String>,
+        preserve_mtime: bool,
+        resume: bool,
+        only_if_newer: bool,
+        if_none_match: Option<&Bound<'_, PyAny>>,
+        if_modified_since: Option<&Bound<'_, PyAny>>,
+        upload: Option<String>,
+        create_dirs: bool,
+        headers_only: bool,
+        accept_content_types: Option<Vec<String>>,
+        max_content_length: Option<u64>,
+        into: Option<&Bound<'_, PyAny>>,
+        decode_content: Option<bool>,
+        allow_http09: Option<bool>,
+        ignore_content_length: Option<bool>,
+        process: Option<Vec<String>>,
+        digests: Option<Vec<String>>,
+        ranges: Option<Vec<(u64, Option<u64>)>>,
+        follow_redirects: bool,
+        max_redirects: u32,
+        on_redirect: Option<PyObject>,
+        stream: bool,
+        paginate: bool,
+        max_pages: Option<u32>,
+        next_page: Option<PyObject>,
+        profile: Option<&str>,
+        locale: Option<String>,
+        labels: Option<Labels>,
+        traceparent: Option<&str>,
+        deadline: Option<&Bound<'_, PyAny>>,
+        inactivity_timeout: Option<f64>,
+        callback: Option<PyObject>,
+        on_headers: Option<PyObject>,
+    ) -> PyResult<u64> {
+        let paginate = paginate || next_page.is_some();
+        let mut request = self.build_request(RequestOptions {
+            url,
+            headers,
+            json,
+            data,
+            auth,
+            auth_scheme,
+            proxy,
+            proxy_tunnel,
+            proxy_ca,
+            proxy_cert,
+            proxy_key,
+            proxy_tls_ciphers,
+            proxy_tls13_ciphers,
+            tls_min_version,
+            tls_max_version,
+            tls_ciphers,
+            tls13_ciphers,
+            alpn,
+            http_version,
+            connect_to,
+            host,
+            fresh_connect,
+            fresh_dns,
+            forbid_reuse,
+            path,
+            preserve_mtime,
+            resume,
+            only_if_newer,
+            if_none_match,
+            if_modified_since,
+            upload,
+            create_dirs,
+            headers_only,
+            accept_content_types,
+            max_content_length,
+            into,
+            decode_content,
+            allow_http09,
+            ignore_content_length,
+            process,
+            digests,
+            ranges,
+            follow_redirects,
+            max_redirects,
+            on_redirect,
+            profile,
+            locale,
+            labels,
+            traceparent,
+            deadline,
+            inactivity_timeout,
+            stream,
+            paginate,
+            max_pages,
+            callback: callback.is_some(),
+        })?;
+        if callback.is_some() || on_headers.is_some() {
+            self.start_callback_thread(py)?;
         }
+        request.stream = stream.then_some(self.max_stream_chunks);
+        request.on_headers = on_headers.map(Arc::new);
+        let pagination = paginate.then(|| Pagination { request: request.clone(), page: 1, max_pages, next_page });
+        self.submit(request, pagination, callback)
     }
 
-    fn get_response(&mut self) -> Result<Response, std::sync::mpsc::RecvError> {
-        match self.response_receiver.recv() {
-            Ok(response) => Ok(response),
-            Err(_) => Err(std::sync::mpsc::RecvError),
+    /// Perform a request right away and return its response, bypassing the
+    /// queue.
+    ///
+    /// The transfer runs in the calling thread with the GIL released, on an
+    /// easy handle of its own whose connections are reused by the next
+    /// calls; concurrent calls each take a handle, a new one when all are
+    /// busy, and run in parallel. It takes the options of `add_request()`
+    /// but `stream` and the pagination ones, and is retried the same way,
+    /// but robots.txt rules and `max_buffered_bytes` do not apply.
+    /// `timeout` is the time allowed for each attempt, in seconds. Ctrl-C
+    /// interrupts the request.
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (
+        url, *, timeout=None, headers=None, json=None, data=None, auth=None, auth_scheme=None,
+        proxy=None, proxy_tunnel=false, proxy_ca=None, proxy_cert=None, proxy_key=None,
+        proxy_tls_ciphers=None, proxy_tls13_ciphers=None,
+        tls_min_version=None, tls_max_version=None, tls_ciphers=None, tls13_ciphers=None,
+        alpn=None, http_version=None, connect_to=None, host=None, fresh_connect=false, fresh_dns=false,
+        forbid_reuse=false, path=None, preserve_mtime=false, resume=false, only_if_newer=false,
+        if_none_match=None, if_modified_since=None, upload=None, create_dirs=false, headers_only=false,
+        accept_content_types=None, max_content_length=None, into=None, decode_content=None,
+        allow_http09=None, ignore_content_length=None, process=None,
+        digests=None, ranges=None,
+        follow_redirects=false, max_redirects=10, on_redirect=None, profile=None, locale=None,
+        labels=None, traceparent=None, deadline=None, inactivity_timeout=None,
+    ))]
+    fn request(
+        &self,
+        py: Python<'_>,
+        url: &str,
+        timeout: Option<f64>,
+        headers: Option<&Bound<'_, PyAny>>,
+        json: Option<&Bound<'_, PyAny>>,
+        data: Option<&Bound<'_, PyAny>>,
+        auth: Option<(String, String)>,
+        auth_scheme: Option<&str>,
+        proxy: Option<String>,
+        proxy_tunnel: bool,
+        proxy_ca: Option<String>,
+        proxy_cert: Option<String>,
+        proxy_key: Option<String>,
+        proxy_tls_ciphers: Option<String>,
+        proxy_tls13_ciphers: Option<String>,
+        tls_min_version: Option<&str>,
+        tls_max_version: Option<&str>,
+        tls_ciphers: Option<String>,
+        tls13_ciphers: Option<String>,
+        alpn: Option<bool>,
+        http_version: Option<&str>,
+        connect_to: Option<Vec<String>>,
+        host: Option<&str>,
+        fresh_connect: bool,
+        fresh_dns: bool,
+        forbid_reuse: bool,
+        path: Option<String>,
+        preserve_mtime: bool,
+        resume: bool,
+        only_if_newer: bool,
+        if_none_match: Option<&Bound<'_, PyAny>>,
+        if_modified_since: Option<&Bound<'_, PyAny>>,
+        upload: Option<String>,
+        create_dirs: bool,
+        headers_only: bool,
+        accept_content_types: Option<Vec<String>>,
+        max_content_length: Option<u64>,
+        into: Option<&Bound<'_, PyAny>>,
+        decode_content: Option<bool>,
+        allow_http09: Option<bool>,
+        ignore_content_length: Option<bool>,
+        process: Option<Vec<String>>,
+        digests: Option<Vec<String>>,
+        ranges: Option<Vec<(u64, Option<u64>)>>,
+        follow_redirects: bool,
+        max_redirects: u32,
+        on_redirect: Option<PyObject>,
+        profile: Option<&str>,
+        locale: Option<String>,
+        labels: Option<Labels>,
+        traceparent: Option<&str>,
+        deadline: Option<&Bound<'_, PyAny>>,
+        inactivity_timeout: Option<f64>,
+    ) -> PyResult<Py<ResponsePython>> {
+        if self.draining.load(Ordering::Relaxed) {
+            return Err(PyRuntimeError::new_err("the downloader is draining"));
+        }
+        let timeout = timeout.map(|timeout| seconds(timeout, "timeout")).transpose()?;
+        let request = self.build_request(RequestOptions {
+            url,
+            headers,
+            json,
+            data,
+            auth,
+            auth_scheme,
+            proxy,
+            proxy_tunnel,
+            proxy_ca,
+            proxy_cert,
+            proxy_key,
+            proxy_tls_ciphers,
+            proxy_tls13_ciphers,
+            tls_min_version,
+            tls_max_version,
+            tls_ciphers,
+            tls13_ciphers,
+            alpn,
+            http_version,
+            connect_to,
+            host,
+            fresh_connect,
+            fresh_dns,
+            forbid_reuse,
+            path,
+            preserve_mtime,
+            resume,
+            only_if_newer,
+            if_none_match,
+            if_modified_since,
+            upload,
+            create_dirs,
+            headers_only,
+            accept_content_types,
+            max_content_length,
+            into,
+            decode_content,
+            allow_http09,
+            ignore_content_length,
+            process,
+            digests,
+            ranges,
+            follow_redirects,
+            max_redirects,
+            on_redirect,
+            profile,
+            locale,
+            labels,
+            traceparent,
+            deadline,
+            inactivity_timeout,
+            ..Default::default()
+        })?;
+        let mut response = py.allow_threads(|| {
+            let mut easy = self.easy.lock().unwrap().pop();
+            let response = self.downloader.perform(&mut easy, request, timeout);
+            let mut idle = self.easy.lock().unwrap();
+            if let Some(easy) = easy.filter(|_| idle.len() < MAX_IDLE_EASY) {
+                idle.push(easy);
+            }
+            drop(idle);
+            let response = response?;
+            self.downloader.archive(&response);
+            PyResult::Ok(response)
+        })?;
+        if let Some(exporter) = &self.span_exporter {
+            export_span(py, exporter, &mut response);
         }
+        Py::new(py, ResponsePython::new(response, &self.downloader))
     }
 
-    fn get_task(&mut self, processing_requests: bool) -> Result<String, std::sync::mpsc::RecvError> {
-        if !processing_requests {
-            // block if there is no download
-            match self.task_receiver.recv_timeout(Duration::from_millis(500)) {
-                Ok(url) => return Ok(url),
-                Err(_) => return Err(std::sync::mpsc::RecvError),
+    /// Open `connections` connections to the host of `url`, from DNS to the
+    /// TLS handshake, and keep them open for the next requests to it, which
+    /// then skip that latency. The connections are opened by HEAD requests
+    /// to `url` whose responses are dropped; this returns without waiting
+    /// for them. Only the connections of `add_request()` are warmed up, not
+    /// the ones of `request()`.
+    ///
+    /// The requests to it must match the TLS and HTTP version settings of
+    /// the downloader to reuse these connections.
+    #[pyo3(signature = (url, *, connections=1))]
+    fn preconnect(&self, url: &str, connections: usize) -> PyResult<()> {
+        if self.draining.load(Ordering::Relaxed) {
+            return Err(PyRuntimeError::new_err("the downloader is draining"));
+        }
+        for _ in 0..connections {
+            self.downloader.add_request(Request {
+                url: url.to_owned(),
+                tls: self.tls.clone(),
+                http_version: self.http_version,
+                connect_to: self.connect_to.clone(),
+                dns: self.dns.clone(),
+                connection: self.connection,
+                preconnect: true,
+                ..Default::default()
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Close the connections kept open to be reused.
+    ///
+    /// The downloader thread closes them once no transfer is running.
+    fn close_idle_connections(&self) {
+        self.downloader.flush_connections.store(true, Ordering::Relaxed);
+        self.downloader.wake_up();
+    }
+
+    /// Transfer counters broken down by labels.
+    ///
+    /// Returns a list of dicts with the `labels`, the number of `requests`
+    /// and `failures`, and the cumulated `total_time` in seconds. Retries
+    /// count as separate requests.
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let snapshot = self.downloader.stats.lock().unwrap().snapshot();
+        let list = PyList::empty_bound(py);
+        for (labels, counters) in snapshot {
+            let item = PyDict::new_bound(py);
+            item.set_item("labels", labels)?;
+            item.set_item("requests", counters.requests)?;
+            item.set_item("failures", counters.failures)?;
+            item.set_item("total_time", counters.total_time.as_secs_f64())?;
+            list.append(item)?;
+        }
+        Ok(list)
+    }
+
+    /// Whether the downloader thread is running.
+    fn worker_alive(&self) -> bool {
+        self.downloader.worker_alive.load(Ordering::Relaxed)
+    }
+
+    /// Whether the downloader thread is running, iterated less than
+    /// `max_stall` seconds ago and its channels are intact.
+    #[pyo3(signature = (max_stall=5.0))]
+    fn is_healthy(&self, max_stall: f64) -> bool {
+        self.worker_alive()
+            && self.downloader.since_heartbeat().as_secs_f64() < max_stall
+            && self.downloader.channels_intact.load(Ordering::Relaxed)
+    }
+
+    /// The state of the downloader thread as a dict: `alive`,
+    /// `last_iteration` (seconds ago), `channels_intact`, and `lost` and
+    /// `duplicates`, the requests it lost track of when it panicked and
+    /// failed then, and the second responses to a request it dropped: each
+    /// request gets exactly one response, even across panics of the thread.
+    fn worker_status<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let status = PyDict::new_bound(py);
+        status.set_item("alive", self.worker_alive())?;
+        status.set_item("last_iteration", self.downloader.since_heartbeat().as_secs_f64())?;
+        status.set_item("channels_intact", self.downloader.channels_intact.load(Ordering::Relaxed))?;
+        status.set_item("lost", self.downloader.lost.load(Ordering::Relaxed))?;
+        status.set_item("duplicates", self.downloader.duplicates.load(Ordering::Relaxed))?;
+        Ok(status)
+    }
+
+    /// Make the downloader thread panic, for the tests of its recovery:
+    /// the requests it was handling fail, the queued ones start once it
+    /// restarted.
+    fn _inject_panic(&self) {
+        self.downloader.inject_panic.store(true, Ordering::Relaxed);
+        self.downloader.wake_up();
+    }
+
+    /// Stop starting transfers, until `resume()`.
+    ///
+    /// The requests added meanwhile, and the retries, stay queued. With
+    /// `transfers`, the running transfers are paused too; their timeouts
+    /// still run.
+    #[pyo3(signature = (transfers=false))]
+    fn pause(&self, transfers: bool) {
+        self.downloader.suspended.store(true, Ordering::Relaxed);
+        self.downloader.paused_transfers.store(transfers, Ordering::Relaxed);
+        self.downloader.wake_up();
+    }
+
+    /// Start transfers again after `pause()`, and resume the paused ones.
+    fn resume(&self) {
+        self.downloader.suspended.store(false, Ordering::Relaxed);
+        self.downloader.paused_transfers.store(false, Ordering::Relaxed);
+        self.downloader.wake_up();
+    }
+
+    /// Whether the downloader is paused by `pause()`.
+    #[getter]
+    fn paused(&self) -> bool {
+        self.downloader.suspended.load(Ordering::Relaxed)
+    }
+
+    /// The stop condition the downloader reached, like `max_requests
+    /// reached, 1000 requests started`, None while it runs.
+    #[getter]
+    fn stop_reason(&self) -> Option<String> {
+        let mut conditions = self.downloader.stop_conditions.as_ref()?.lock().unwrap();
+        conditions.reason().map(str::to_owned)
+    }
+
+    /// Cancel the requests added so far, queued or running.
+    ///
+    /// Their responses are returned by `fetch()` as usual, with `error`
+    /// set to `cancelled`. Reading the streamed bodies already returned
+    /// raises `ConnectionError`.
+    fn cancel_all(&self) {
+        let submitted = self.inbox.state.lock().unwrap().submitted;
+        self.downloader.cancel_before.fetch_max(submitted, Ordering::Relaxed);
+        self.downloader.wake_up();
+    }
+
+    /// Refuse new requests, and wait until the ones added before completed.
+    ///
+    /// Their responses are still returned by `fetch()`; further
+    /// `add_request()` calls raise `RuntimeError`, and no next page of a
+    /// pagination is requested. Waits at most `timeout` seconds, forever by
+    /// default, and returns whether all the requests completed.
+    #[pyo3(signature = (timeout=None))]
+    fn drain(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<bool> {
+        self.draining.store(true, Ordering::Relaxed);
+        let deadline = timeout
+            .map(|timeout| seconds(timeout, "timeout"))
+            .transpose()?
+            .map(|timeout| Instant::now() + timeout);
+        loop {
+            let running = self
+                .downloader
+                .stages
+                .lock()
+                .unwrap()
+                .values()
+                .any(|(stage, _)| *stage != Stage::Completed);
+            if !running {
+                return Ok(true);
+            }
+            if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+                return Ok(false);
             }
+            py.allow_threads(|| thread::sleep(Duration::from_millis(10)));
+            py.check_signals()?;
         }
-        match self.task_receiver.try_recv() {
-            Ok(url) => Ok(url),
-            Err(_) => Err(std::sync::mpsc::RecvError),
+    }
+
+    /// The requests added whose response was not fetched yet, as a dict:
+    /// the number of requests `pending`, queued and not started, of the
+    /// ones `in_flight` and of the `completed` ones. With `details`, the
+    /// values are lists of `(handle, url)` tuples instead.
+    #[pyo3(signature = (details=false))]
+    fn queue_status<'py>(&self, py: Python<'py>, details: bool) -> PyResult<Bound<'py, PyDict>> {
+        let stages = self.downloader.stages.lock().unwrap();
+        let status = PyDict::new_bound(py);
+        let names = [("pending", Stage::Pending), ("in_flight", Stage::InFlight), ("completed", Stage::Completed)];
+        for (name, stage) in names {
+            let requests = stages.iter().filter(|(_, (request_stage, _))| *request_stage == stage);
+            if details {
+                let requests: Vec<(u64, &str)> = requests.map(|(handle, (_, url))| (*handle, url.as_str())).collect();
+                status.set_item(name, requests)?;
+            } else {
+                status.set_item(name, requests.count())?;
+            }
         }
+        Ok(status)
     }
 
-    fn thread_runner(&mut self) {
-        let multi = Multi::new();
-        let mut handles: HashMap<usize, Easy2Handle<Collector>> = HashMap::new();
-        let mut urls: HashMap<usize, String> = HashMap::new();
-        let mut last_token = 0;
-    
-        let mut processing_requests = true;
-        while self.running {
-            println!("loop");
-
-            match self.get_task(processing_requests) {
-                Ok(url) => {
-                    processing_requests = true;
-                    println!("Add request");
-
-                    let token = last_token;
-                    last_token += 1;
-            
-                    //
-                    let version = curl::Version::get();
-                    let mut request = Easy2::new(Collector(Vec::new()));
-                    request.url(&url).unwrap();
-                    request.useragent(&format!("curl/{}", version.version())).unwrap();
-                
-                    let mut handle = multi.add2(request).unwrap();
-                    handle.set_token(token).unwrap();
-            
-                    //
-                    handles.insert(token, handle);
-                    urls.insert(token, url.to_owned());
-                }
-                Err(_) => {
-                    // No more tasks to process.
-                }
-            }
-
-            // We still need to process the last messages when
-            // `Multi::perform` returns "0".
-            if multi.perform().unwrap() == 0 {
-                processing_requests = false;
-                println!("No more");
-            }
-    
-            multi.messages(|message| {
-                let token = message.token().expect("failed to get the token");
-                let handle = handles
-                    .get_mut(&token)
-                    .expect("the download value should exist in the HashMap");
-    
-                match message
-                    .result_for2(&handle)
-                    .expect("token mismatch with the `EasyHandle`")
-                {
-                    Ok(()) => {
-                        let http_status = handle
-                            .response_code()
-                            .expect("HTTP request finished without status code");
-
-                        println!("Response!!");
-                        self.response_sender.send(Response {
-                            url: urls[&token].clone(),
-                            status_code: http_status as i64,
-                            data: handle.get_ref().0.clone(),
-                        }).unwrap();
-                    }
-                    Err(error) => {
-                        println!("Error!! {}", error);
-                        self.response_sender.send(Response {
-                            url: urls[&token].clone(),
-                            status_code: -1,
-                            data: Vec::new(),
-                        }).unwrap();
-                    }
+    /// The transfer metrics in the Prometheus text exposition format.
+    ///
+    /// Request labels become Prometheus labels.
+    fn metrics_text(&self) -> String {
+        let queued = self.downloader.task_receiver.len() + self.downloader.waiting.load(Ordering::Relaxed);
+        let gauges = [
+            ("pycurse_queue_depth", "Requests waiting to be started.", queued as u64),
+            (
+                "pycurse_transfers_in_flight",
+                "Transfers in progress.",
+                self.downloader.in_flight.load(Ordering::Relaxed) as u64,
+            ),
+            ("pycurse_responses_pending", "Responses not fetched yet.", self.downloader.response_receiver.len() as u64),
+            (
+                "pycurse_responses_pending_bytes",
+                "Size of the bodies of the responses not fetched yet.",
+                self.downloader.buffered_bytes.load(Ordering::Relaxed) as u64,
+            ),
+        ];
+        self.downloader.stats.lock().unwrap().prometheus(&gauges)
+    }
+
+    /// Start download and read data by chunks.
+    ///
+    /// Python threads fetching at the same time get the responses in turn,
+    /// in the order they started to wait. Ctrl-C interrupts the wait.
+    fn fetch(&self, py: Python<'_>, timeout: u64) -> PyResult<Option<Py<ResponsePython>>> {
+        let deadline = Instant::now() + Duration::from_millis(timeout);
+        let turn = Turn::new(&self.inbox, None);
+        match self.receive(py, &turn, Some(deadline))? {
+            Ok(response) => Ok(Some(self.deliver(py, response)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Fetch up to `max_items` responses in one call: waits at most
+    /// `timeout` milliseconds for the first one, like `fetch()`, then takes
+    /// the ones received already without waiting. Returns an empty list if
+    /// none arrived in time.
+    ///
+    /// At high rates of small responses, it saves a call and a turn for
+    /// the GIL per response.
+    fn fetch_many(&self, py: Python<'_>, max_items: usize, timeout: u64) -> PyResult<Vec<Py<ResponsePython>>> {
+        if max_items == 0 {
+            return Ok(Vec::new());
+        }
+        let deadline = Instant::now() + Duration::from_millis(timeout);
+        let turn = Turn::new(&self.inbox, None);
+        let Ok(first) = self.receive(py, &turn, Some(deadline))? else {
+            return Ok(Vec::new());
+        };
+        let mut responses = vec![first];
+        let receiver = &self.downloader.response_receiver;
+        responses.extend(py.allow_threads(|| turn.take_available(receiver, self.ordered, max_items - 1)));
+        responses.into_iter().map(|response| self.deliver(py, response)).collect()
+    }
+
+    /// Wait for the response of the request with this `handle`, returned
+    /// by `add_request()`.
+    ///
+    /// The responses of other requests received meanwhile are kept for
+    /// `fetch()`, which leaves this one to `wait()` even in another thread.
+    /// Waits at most `timeout` seconds, forever by default, then raises
+    /// `TimeoutError`, which it also does for a response already returned.
+    #[pyo3(signature = (handle, timeout=None))]
+    fn wait(&self, py: Python<'_>, handle: u64, timeout: Option<f64>) -> PyResult<Py<ResponsePython>> {
+        if handle >= self.inbox.state.lock().unwrap().submitted {
+            return Err(PyValueError::new_err(format!("unknown request handle: {}", handle)));
+        }
+        if self.downloader.callbacks.lock().unwrap().contains_key(&handle) {
+            return Err(PyValueError::new_err("the response of this request goes to its callback"));
+        }
+        let deadline = timeout
+            .map(|timeout| seconds(timeout, "timeout"))
+            .transpose()?
+            .map(|timeout| Instant::now() + timeout);
+        let turn = Turn::new(&self.inbox, Some(handle));
+        match self.receive(py, &turn, deadline)? {
+            Ok(response) => self.deliver(py, response),
+            Err(RecvTimeoutError::Timeout) => Err(PyTimeoutError::new_err("no response received in time")),
+            Err(RecvTimeoutError::Disconnected) => Err(PyRuntimeError::new_err("the downloader thread is gone")),
+        }
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// The next response, until all the requests added got theirs.
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<Py<ResponsePython>>> {
+        let turn = Turn::new(&self.inbox, None);
+        loop {
+            let state = self.inbox.state.lock().unwrap();
+            if state.delivered + state.callbacks >= state.submitted {
+                return Ok(None);
+            }
+            drop(state);
+            let slice = Instant::now() + Duration::from_millis(100);
+            match py.allow_threads(|| self.take(&turn, slice)) {
+                Ok(response) => return Ok(Some(self.deliver(py, response)?)),
+                Err(RecvTimeoutError::Timeout) => py.check_signals()?,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(PyRuntimeError::new_err("the downloader thread is gone"));
                 }
+            }
+        }
+    }
+}
+
+impl CurlDownloader {
+    /// `url` as `host_map` and `force_https` rewrite it, with the URL as
+    /// given; None when they leave it as is.
+    fn rewrite(&self, url: &str) -> (Option<String>, Option<String>) {
+        match self.url_rewrites.as_ref().and_then(|rewrites| rewrites.apply(url)) {
+            Some(rewritten) => (Some(rewritten), Some(url.to_owned())),
+            None => (None, None),
+        }
+    }
+
+    /// A request with the options of `add_request()`.
+    fn build_request(&self, options: RequestOptions<'_>) -> PyResult<Request> {
+        options.validate()?;
+        let RequestOptions {
+            url,
+            headers,
+            json,
+            data,
+            auth,
+            auth_scheme,
+            proxy,
+            proxy_tunnel,
+            proxy_ca,
+            proxy_cert,
+            proxy_key,
+            proxy_tls_ciphers,
+            proxy_tls13_ciphers,
+            tls_min_version,
+            tls_max_version,
+            tls_ciphers,
+            tls13_ciphers,
+            alpn,
+            http_version,
+            connect_to,
+            host,
+            fresh_connect,
+            fresh_dns,
+            forbid_reuse,
+            path,
+            preserve_mtime,
+            resume,
+            only_if_newer,
+            if_none_match,
+            if_modified_since,
+            upload,
+            create_dirs,
+            headers_only,
+            accept_content_types,
+            max_content_length,
+            into,
+            decode_content,
+            allow_http09,
+            ignore_content_length,
+            process,
+            digests,
+            ranges,
+            follow_redirects,
+            max_redirects,
+            on_redirect,
+            profile,
+            locale,
+            labels,
+            traceparent,
+            deadline,
+            inactivity_timeout,
+            ..
+        } = options;
+        let (rewritten, original_url) = self.rewrite(url);
+        let url = rewritten.as_deref().unwrap_or(url);
+        if let Some(scheme) = upload.as_ref().and_then(|_| url.split_once("://")).map(|(scheme, _)| scheme) {
+            let scheme = scheme.to_ascii_lowercase();
+            if !curl::Version::get().protocols().any(|protocol| protocol == scheme) {
+                return Err(PyValueError::new_err(format!(
+                    "{} is not supported by this libcurl build",
+                    scheme
+                )));
+            }
+        }
+        // Any contiguous buffer, as bytes.
+        let into = into
+            .map(|buffer| PyMemoryView::from_bound(buffer)?.call_method1("cast", ("B",)))
+            .transpose()?
+            .map(|view| PyBuffer::<u8>::get_bound(&view))
+            .transpose()?;
+        if into.as_ref().is_some_and(|buffer| buffer.readonly()) {
+            return Err(PyTypeError::new_err("into must be a writable buffer"));
+        }
+        let process = process
+            .into_iter()
+            .flatten()
+            .map(|name| Processor::parse(&name))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(PyValueError::new_err)?;
+        let digests = digests
+            .into_iter()
+            .flatten()
+            .map(|name| Algorithm::parse(&name))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(PyValueError::new_err)?;
+        let ranges = ranges.map(|ranges| {
+            let ranges = ranges.into_iter().map(|range| match range {
+                (first, Some(last)) => format!("{}-{}", first, last),
+                (first, None) => format!("{}-", first),
             });
-    
-            if processing_requests {
-                // The sleeping time could be reduced to allow other processing.
-                // For instance, a thread could check a condition signalling the
-                // thread shutdown.
-                multi.wait(&mut [], Duration::from_millis(10)).unwrap();
+            ranges.collect::<Vec<_>>().join(",")
+        });
+        let if_modified_since = match (if_modified_since, &path) {
+            (Some(time), _) => Some(epoch_seconds(time)?),
+            (None, Some(path)) if only_if_newer => file_mtime(path),
+            _ => None,
+        };
+        let mut headers = headers.map(headers::from_python).transpose()?.unwrap_or_default();
+        let profile = profile.map(Profile::parse).transpose().map_err(PyValueError::new_err)?.or(self.profile);
+        let locale = locale.map(checked_locale).transpose()?.or_else(|| self.locale.clone());
+        let defaults = match (profile, locale) {
+            (Some(profile), locale) => profile.headers(locale.as_deref().unwrap_or("en-US")),
+            (None, Some(locale)) => vec![format!("Accept-Language: {}", profile::accept_language(&locale))],
+            (None, None) => Vec::new(),
+        };
+        for header in defaults {
+            let name = &header[..header.find(':').unwrap()];
+            if !headers::contains(&headers, name) {
+                headers.push(header);
+            }
+        }
+        let host_header = headers::value(&headers, "Host").filter(|value| !value.is_empty());
+        let (url, host_entry) = match host {
+            Some(_) if host_header.is_some() => {
+                return Err(PyValueError::new_err("host and a Host header are mutually exclusive"));
+            }
+            Some(host) => {
+                let (url, entry) = virtual_host(url, host)?;
+                (url, Some(entry))
+            }
+            None => {
+                if host_header.is_some_and(|value| mismatches_sni(url, value)) {
+                    return Err(PyValueError::new_err(
+                        "a Host header for another host than the URL would not match the TLS SNI, use host instead",
+                    ));
+                }
+                (url.to_owned(), None)
+            }
+        };
+        let mut request = Request {
+            url,
+            headers,
+            credentials: auth,
+            auth_scheme: auth_scheme.map(AuthScheme::parse).transpose()?,
+            proxy: ProxyOptions {
+                url: proxy,
+                tunnel: proxy_tunnel,
+                cainfo: proxy_ca,
+                cert: proxy_cert,
+                key: proxy_key,
+                tls: TlsOptions::new(None, None, proxy_tls_ciphers, proxy_tls13_ciphers, None)?,
+            },
+            tls: self.tls.merge(TlsOptions::new(tls_min_version, tls_max_version, tls_ciphers, tls13_ciphers, alpn)?)?,
+            http_version: http_version.map(parse_http_version).transpose()?.or(self.http_version),
+            connect_to: host_entry
+                .into_iter()
+                .chain(connect_to.into_iter().flatten())
+                .chain(self.connect_to.iter().cloned())
+                .collect(),
+            dns: DnsOptions {
+                cache_timeout: if fresh_dns { Some(Duration::ZERO) } else { self.dns.cache_timeout },
+                ..self.dns.clone()
+            },
+            connection: ConnectionOptions {
+                fresh_connect,
+                forbid_reuse,
+                ..self.connection
+            },
+            max_retries: self.max_retries,
+            fail_on_status: self.fail_on_status,
+            sniff_charset: self.sniff_charset,
+            decompress: self.decompress && !resume && ranges.is_none(),
+            raw_content: !decode_content.unwrap_or(self.decode_content),
+            allow_http09: allow_http09.unwrap_or(self.allow_http09),
+            ignore_content_length: ignore_content_length.unwrap_or(self.ignore_content_length),
+            path,
+            preserve_mtime,
+            resume,
+            if_modified_since,
+            upload,
+            create_dirs,
+            headers_only,
+            limits: ResponseLimits {
+                content_types: accept_content_types
+                    .map(|types| types.iter().map(|mime| mime.trim().to_ascii_lowercase()).collect()),
+                max_length: max_content_length,
+                max_header_bytes: self.max_header_bytes,
+                max_headers: self.max_headers,
+            },
+            into: into.map(Arc::new),
+            max_redirects: (follow_redirects || on_redirect.is_some()).then_some(max_redirects),
+            on_redirect: on_redirect.map(Arc::new),
+            process,
+            digests,
+            ranges,
+            robots_agent: self.robots_agent.clone(),
+            labels: labels.unwrap_or_default(),
+            queued: Some(Timestamp::now()),
+            inject_traceparent: self.inject_traceparent,
+            deadline: deadline
+                .map(|deadline| deadline_instant(deadline, self.downloader.clock.as_ref().map(Py::get)))
+                .transpose()?,
+            inactivity_timeout: checked_inactivity_timeout(inactivity_timeout)?.or(self.inactivity_timeout),
+            original_url,
+            ..Default::default()
+        };
+        if self.span_exporter.is_some() || self.inject_traceparent {
+            request.span = Some(SpanContext::new(traceparent).map_err(PyValueError::new_err)?);
+        }
+        if let (None, Some(AuthScheme::Negotiate)) = (&request.credentials, request.auth_scheme) {
+            // libcurl only tries GSS-Negotiate once a user name is set.
+            request.credentials = Some((String::new(), String::new()));
+        }
+        if let Some(etags) = if_none_match {
+            let etags = match etags.downcast::<PyString>() {
+                Ok(etag) => etag.to_str()?.to_owned(),
+                Err(_) => etags
+                    .extract::<Vec<String>>()
+                    .map_err(|_| PyTypeError::new_err("if_none_match must be a str or a list of str"))?
+                    .join(", "),
+            };
+            request.headers.push(headers::format("If-None-Match", &etags)?);
+        }
+        let content_type_set = headers::contains(&request.headers, "Content-Type");
+        if let Some(json) = json {
+            if !content_type_set {
+                request.headers.push("Content-Type: application/json".to_owned());
             }
+            request.body = Some(json::dumps(json)?.into_bytes());
         }
+        if let Some(data) = data {
+            if let Ok(dict) = data.downcast::<PyDict>() {
+                if !content_type_set {
+                    request.headers.push("Content-Type: application/x-www-form-urlencoded".to_owned());
+                }
+                request.body = Some(form::urlencode(dict)?.into_bytes());
+            } else if let Ok(text) = data.downcast::<PyString>() {
+                request.body = Some(text.to_str()?.as_bytes().to_vec());
+            } else if let Ok(bytes) = data.downcast::<PyBytes>() {
+                request.body = Some(bytes.as_bytes().to_vec());
+            } else {
+                return Err(PyTypeError::new_err("data must be a dict, str or bytes"));
+            }
+        }
+        Ok(request)
+    }
+
+    /// Queue a request, and return its handle.
+    fn submit(
+        &self,
+        mut request: Request,
+        pagination: Option<Pagination>,
+        callback: Option<PyObject>,
+    ) -> PyResult<u64> {
+        if self.draining.load(Ordering::Relaxed) {
+            return Err(PyRuntimeError::new_err("the downloader is draining"));
+        }
+        let mut state = self.inbox.state.lock().unwrap();
+        let sequence = state.submitted;
+        request.sequence = sequence;
+        // Held until the request is queued, for `Downloader::fail_lost()`.
+        let mut stages = self.downloader.stages.lock().unwrap();
+        stages.insert(sequence, (Stage::Pending, request.url.clone()));
+        if let Some(callback) = callback {
+            // Registered first, for the downloader thread to route the
+            // response.
+            self.downloader.callbacks.lock().unwrap().insert(sequence, callback);
+            state.callbacks += 1;
+            // Never in the inbox: the ordered responses do not wait for it.
+            state.waited.insert(sequence);
+        }
+        if let Err(error) = self.downloader.add_request(request) {
+            stages.remove(&sequence);
+            if self.downloader.callbacks.lock().unwrap().remove(&sequence).is_some() {
+                state.callbacks -= 1;
+                state.waited.remove(&sequence);
+            }
+            return Err(error);
+        }
+        if let Some(pagination) = pagination {
+            state.pages.insert(sequence, pagination);
+        }
+        state.submitted += 1;
+        Ok(sequence)
+    }
+
+    fn start_callback_thread(&self, py: Python<'_>) -> PyResult<()> {
+        let mut started = self.callback_thread.lock().unwrap();
+        if *started {
+            return Ok(());
+        }
+        let (downloader, executor) = (self.downloader.clone(), self.callback_executor.clone_ref(py));
+        let span_exporter = self.span_exporter.as_ref().map(|exporter| exporter.clone_ref(py));
+        thread::Builder::new()
+            .name("pycurse-callbacks".to_owned())
+            .spawn(move || downloader.run_callbacks(executor, span_exporter))
+            .map_err(|error| PyRuntimeError::new_err(format!("cannot start the callback thread: {}", error)))?;
+        *started = true;
+        Ok(())
+    }
+
+    /// Wait until `deadline` for the response of `turn`, without the GIL:
+    /// the downloader thread needs it to log.
+    fn take(&self, turn: &Turn<'_>, deadline: Instant) -> Result<Response, RecvTimeoutError> {
+        turn.take(&self.downloader.response_receiver, self.ordered, deadline)
+    }
+
+    /// Wait until `deadline`, forever without one, for the response of
+    /// `turn`, waking up regularly to handle Ctrl-C.
+    fn receive(
+        &self,
+        py: Python<'_>,
+        turn: &Turn<'_>,
+        deadline: Option<Instant>,
+    ) -> PyResult<Result<Response, RecvTimeoutError>> {
+        loop {
+            let mut slice = Instant::now() + Duration::from_millis(100);
+            if let Some(deadline) = deadline {
+                slice = slice.min(deadline);
+            }
+            match py.allow_threads(|| self.take(turn, slice)) {
+                Err(RecvTimeoutError::Timeout) if deadline.is_none_or(|deadline| Instant::now() < deadline) => {
+                    py.check_signals()?;
+                }
+                received => return Ok(received),
+            }
+        }
+    }
+
+    fn deliver(&self, py: Python<'_>, mut response: Response) -> PyResult<Py<ResponsePython>> {
+        self.downloader.release(&mut response);
+        if let Some(exporter) = &self.span_exporter {
+            export_span(py, exporter, &mut response);
+        }
+        let sequence = response.sequence;
+        let response = Py::new(py, ResponsePython::new(response, &self.downloader))?;
+        self.request_next_page(py, sequence, response.bind(py))?;
+        Ok(response)
+    }
+
+    /// Request the page after `response`, when it is a page of a
+    /// pagination.
+    fn request_next_page(
+        &self,
+        py: Python<'_>,
+        sequence: u64,
+        response: &Bound<'_, ResponsePython>,
+    ) -> PyResult<()> {
+        let Some(pagination) = self.inbox.state.lock().unwrap().pages.remove(&sequence) else {
+            return Ok(());
+        };
+        if pagination.max_pages.is_some_and(|max_pages| pagination.page >= max_pages) {
+            return Ok(());
+        }
+        let url = match &pagination.next_page {
+            Some(next_page) => match next_page.call1(py, (response,)).and_then(|url| url.extract(py)) {
+                Ok(url) => url,
+                Err(error) => {
+                    error.write_unraisable_bound(py, Some(response.as_any()));
+                    None
+                }
+            },
+            None => response
+                .borrow()
+                .links_of()
+                .into_iter()
+                .find(|link| link.has_rel("next"))
+                .map(|link| link.url),
+        };
+        let Some(url) = url else {
+            return Ok(());
+        };
+        if self.draining.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let mut request = pagination.request.clone();
+        (request.url, request.original_url) = match self.rewrite(&url) {
+            (Some(rewritten), original_url) => (rewritten, original_url),
+            (None, _) => (url, None),
+        };
+        request.queued = Some(Timestamp::now());
+        request.span = request.span.map(|span| span.sibling());
+        let pagination = Pagination {
+            page: pagination.page + 1,
+            ..pagination
+        };
+        self.submit(request, Some(pagination), None)?;
+        Ok(())
     }
 }
 
-lazy_static! {
-    static ref DOWNLOADER: Mutex<Downloader> = Mutex::new(Downloader::new());
+impl Drop for CurlDownloader {
+    fn drop(&mut self) {
+        // The thread stops after its current iteration.
+        self.downloader.running.store(false, Ordering::Relaxed);
+        self.downloader.wake_up();
+    }
 }
 
+/// The URL of a request to `url` made for `host`, a name with an optional
+/// port, and the `connect_to` entry still sending it to the host of `url`.
+fn virtual_host(url: &str, host: &str) -> PyResult<(String, String)> {
+    let invalid = || PyValueError::new_err(format!("invalid host: {:?}", host));
+    let mut target = Url::parse(url).map_err(|_| PyValueError::new_err(format!("invalid URL: {:?}", url)))?;
+    if !matches!(target.scheme(), "http" | "https") {
+        return Err(PyValueError::new_err("host requires an HTTP or HTTPS URL"));
+    }
+    let Some(connect_host) = target.host_str().map(str::to_owned) else {
+        return Err(PyValueError::new_err(format!("invalid URL: {:?}", url)));
+    };
+    let connect_port = target.port_or_known_default().unwrap_or_default();
+    let name = Url::parse(&format!("{}://{}/", target.scheme(), host)).map_err(|_| invalid())?;
+    let credentials = !name.username().is_empty() || name.password().is_some();
+    if name.path() != "/" || name.query().is_some() || name.fragment().is_some() || credentials {
+        return Err(invalid());
+    }
+    target.set_host(name.host_str()).map_err(|_| invalid())?;
+    if name.port().is_some() {
+        target.set_port(name.port()).map_err(|_| invalid())?;
+    }
+    let entry = format!(
+        "{}:{}:{}:{}",
+        target.host_str().unwrap_or_default(),
+        target.port_or_known_default().unwrap_or_default(),
+        connect_host,
+        connect_port,
+    );
+    Ok((target.into(), entry))
+}
 
-#[pyclass]
-struct ResponsePython {
-    url: String,
-    status_code: i64,
-    data: String,
+/// Whether a `Host` header names another host than the HTTPS URL, which
+/// would then be sent with the TLS SNI and certificate of the URL host.
+fn mismatches_sni(url: &str, host_header: &str) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+    if url.scheme() != "https" {
+        return false;
+    }
+    match Url::parse(&format!("https://{}/", host_header)) {
+        Ok(header) => {
+            header.host_str() != url.host_str() || header.port_or_known_default() != url.port_or_known_default()
+        }
+        Err(_) => true,
+    }
 }
 
-#[pymethods]
-impl ResponsePython {
-    #[getter]
-    fn url(&self) -> &str {
-        &self.url
+/// Sleep until `until`, still handling the signals like Ctrl-C.
+fn sleep_until(until: Instant) -> PyResult<()> {
+    while let Some(left) = until.checked_duration_since(Instant::now()).filter(|left| !left.is_zero()) {
+        thread::sleep(left.min(Duration::from_millis(100)));
+        Python::with_gil(|py| py.check_signals())?;
     }
+    Ok(())
+}
 
-    #[getter]
-    fn status_code(&self) -> i64 {
-        self.status_code
+/// The host of a URL, which the scheduling limits apply to.
+fn host_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_default()
+}
+
+/// A number of seconds since the epoch as an aware `datetime`.
+fn utc_datetime(py: Python<'_>, time: i64) -> PyResult<Bound<'_, PyDateTime>> {
+    PyDateTime::from_timestamp_bound(py, time as f64, Some(&timezone_utc_bound(py)))
+}
+
+/// Check `locale` is a language tag, as it goes into `Accept-Language`.
+fn checked_locale(locale: String) -> PyResult<String> {
+    if profile::is_locale(&locale) {
+        Ok(locale)
+    } else {
+        Err(PyValueError::new_err(format!("invalid locale: {:?}", locale)))
     }
+}
 
-    #[getter]
-    fn data(&self) -> &str {
-        &self.data
+/// Convert a number of seconds from Python to a `Duration`.
+fn seconds(value: f64, name: &str) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(value)
+        .map_err(|_| PyValueError::new_err(format!("{} must be a positive number of seconds", name)))
+}
+
+fn checked_inactivity_timeout(timeout: Option<f64>) -> PyResult<Option<Duration>> {
+    match timeout.map(|timeout| seconds(timeout, "inactivity_timeout")).transpose()? {
+        Some(timeout) if timeout.is_zero() => Err(PyValueError::new_err("inactivity_timeout must be positive")),
+        timeout => Ok(timeout),
     }
 }
 
-/// A struct to store a curl easy handle.
-#[pyclass]
-struct CurlDownloader {
+/// Hand the span of `response`, if it has one, to `exporter`.
+fn export_span(py: Python<'_>, exporter: &PyObject, response: &mut Response) {
+    let Some(span) = response.span.take() else {
+        return;
+    };
+    let result = span_to_dict(py, &span, response).and_then(|span| exporter.call1(py, (span,)));
+    if let Err(error) = result {
+        error.write_unraisable_bound(py, None);
+    }
 }
 
-#[pymethods]
-impl CurlDownloader {
-    #[new]
-    fn new() -> Self {
-        CurlDownloader {
-        }
+/// Describe a request span as a dict, for `span_exporter`.
+fn span_to_dict<'py>(py: Python<'py>, span: &Span, response: &Response) -> PyResult<Bound<'py, PyDict>> {
+    let context = &span.context;
+    let dict = PyDict::new_bound(py);
+    dict.set_item("name", "HTTP request")?;
+    dict.set_item("trace_id", format!("{:032x}", context.trace_id))?;
+    dict.set_item("span_id", format!("{:016x}", context.span_id))?;
+    dict.set_item("parent_span_id", context.parent_span_id.map(|id| format!("{:016x}", id)))?;
+    dict.set_item("start_time", span.queued.unix_nanos())?;
+    dict.set_item("end_time", span.finished.unix_nanos())?;
+    dict.set_item("status", if response.status_code < 0 { "error" } else { "ok" })?;
+
+    let attributes = PyDict::new_bound(py);
+    attributes.set_item("http.url", &response.url)?;
+    if response.status_code >= 0 {
+        attributes.set_item("http.status_code", response.status_code)?;
+    }
+    if let Some(error) = &response.error {
+        attributes.set_item("error.message", error)?;
     }
+    let timings = &span.timings;
+    attributes.set_item("pycurse.attempts", span.attempts)?;
+    attributes.set_item("pycurse.queue_time", span.started.since(&span.queued).as_secs_f64())?;
+    attributes.set_item("pycurse.dns_time", timings.namelookup.as_secs_f64())?;
+    attributes.set_item("pycurse.connect_time", timings.connect.as_secs_f64())?;
+    attributes.set_item("pycurse.tls_time", timings.appconnect.saturating_sub(timings.connect).as_secs_f64())?;
+    attributes.set_item("pycurse.ttfb", timings.starttransfer.as_secs_f64())?;
+    attributes.set_item("pycurse.total_time", timings.total.as_secs_f64())?;
+    for (key, value) in &span.labels {
+        attributes.set_item(format!("pycurse.label.{}", key), value)?;
+    }
+    dict.set_item("attributes", attributes)?;
+    Ok(dict)
+}
 
-    /// Initialize curl downloader with the URL.
-    fn add_request(&mut self, url: &str) -> PyResult<()> {
-        let mut downloader = DOWNLOADER.lock().unwrap();
-        downloader.add_request(url).unwrap();
-        return Ok(());
+/// The libcurl pycurse runs with.
+///
+/// Returns a dict with the `version`, the TLS backend `ssl_version`, the
+/// versions of the libraries libcurl uses (`libz_version`, `ares_version`,
+/// `nghttp2_version`, `brotli_version`, `zstd_version`, `libssh_version`;
+/// None when not used), the supported `protocols`, and `features`, a dict
+/// of flags like `http2`, `http3`, `brotli` or `zstd`. `vendored` tells
+/// whether libcurl is the one built with pycurse or the system one.
+#[pyfunction]
+fn curl_info(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let version = curl::Version::get();
+    let info = PyDict::new_bound(py);
+    info.set_item("version", version.version())?;
+    info.set_item("version_num", version.version_num())?;
+    info.set_item("vendored", version.vendored())?;
+    info.set_item("host", version.host())?;
+    info.set_item("ssl_version", version.ssl_version())?;
+    info.set_item("libz_version", version.libz_version())?;
+    info.set_item("ares_version", version.ares_version())?;
+    info.set_item("nghttp2_version", version.nghttp2_version())?;
+    info.set_item("brotli_version", version.brotli_version())?;
+    info.set_item("zstd_version", version.zstd_version())?;
+    info.set_item("libssh_version", version.libssh_version())?;
+    info.set_item("protocols", version.protocols().collect::<Vec<_>>())?;
+
+    let features = PyDict::new_bound(py);
+    for (name, enabled) in [
+        ("ssl", version.feature_ssl()),
+        ("http2", version.feature_http2()),
+        ("http3", version.feature_http3()),
+        ("https_proxy", version.feature_https_proxy()),
+        ("libz", version.feature_libz()),
+        ("brotli", version.feature_brotli()),
+        ("zstd", version.feature_zstd()),
+        ("ipv6", version.feature_ipv6()),
+        ("async_dns", version.feature_async_dns()),
+        ("idn", version.feature_idn()),
+        ("ntlm", version.feature_ntlm()),
+        ("spnego", version.feature_spnego()),
+        ("gss_negotiate", version.feature_gss_negotiate()),
+        ("unix_domain_socket", version.feature_unix_domain_socket()),
+        ("altsvc", version.feature_altsvc()),
+        ("hsts", version.feature_hsts()),
+        ("largefile", version.feature_largefile()),
+    ] {
+        features.set_item(name, enabled)?;
     }
+    info.set_item("features", features)?;
+    Ok(info)
+}
 
-    /// Start download and read data by chunks.
-    fn fetch(&mut self, timeout: u64) -> PyResult<Option<ResponsePython>> {
-        println!("fetch");
-        let downloader = DOWNLOADER.lock().unwrap();
-        println!("fetch - downloader locked");
-        let receiver = downloader.response_receiver.clone();
-        match receiver.recv_timeout(Duration::from_millis(timeout)) {
-            Ok(response) => {
-                println!("fetch - response");
-                return Ok(Some(ResponsePython {
-                    url: response.url,
-                    status_code: response.status_code,
-                    data: str::from_utf8(&response.data).unwrap().to_owned(),
-                }));
-            }
-            Err(_) => {
-                println!("fetch - error");
-                return Ok(None);
-            }
+/// Initialize libcurl with these flags, before the first downloader is
+/// created; downloaders otherwise initialize it with all of them.
+///
+/// `ssl` initializes the TLS library, which libcurl 7.57 and above always
+/// do, `win32` the Winsock sockets on Windows, and `ack_eintr` makes the
+/// waits of libcurl interrupted by a signal return early. Calling it again
+/// with the same flags does nothing; with other flags, or after a
+/// downloader was created with other flags, it raises `RuntimeError`.
+///
+/// pycurse links its own libcurl: pycurl or other extensions using curl
+/// in the same process initialize and clean up theirs independently, and
+/// pycurse never calls `curl_global_cleanup`.
+#[pyfunction]
+#[pyo3(signature = (*, ssl=true, win32=true, ack_eintr=false))]
+fn global_init(ssl: bool, win32: bool, ack_eintr: bool) -> PyResult<()> {
+    init::init(Some(init::InitFlags { ssl, win32, ack_eintr }))?;
+    Ok(())
+}
+
+/// How libcurl was initialized: a dict with `initialized`, False before
+/// the first downloader or `global_init()`, the `flags`, like `["ssl",
+/// "win32"]`, and `explicit`, whether `global_init()` chose them.
+#[pyfunction]
+fn global_init_state(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    let state = init::state();
+    dict.set_item("initialized", state.is_some())?;
+    dict.set_item("flags", state.map(|state| state.flags.names()))?;
+    dict.set_item("explicit", state.is_some_and(|state| state.explicit))?;
+    Ok(dict)
+}
+
+/// The downloaders of `get_downloader`: name to `(downloader, config)`.
+static DOWNLOADERS: GILOnceCell<Py<PyDict>> = GILOnceCell::new();
+
+/// The downloader called `name`, created with `config` on first use.
+///
+/// `config` are the keyword arguments of `CurlDownloader`. Every later call
+/// returns the same instance; passing a different `config` then raises
+/// `ValueError`.
+#[pyfunction]
+#[pyo3(signature = (name, **config))]
+fn get_downloader<'py>(
+    py: Python<'py>,
+    name: &str,
+    config: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let downloaders = DOWNLOADERS.get_or_init(py, || PyDict::new_bound(py).unbind()).bind(py);
+    let config = match config {
+        Some(config) => config.clone(),
+        None => PyDict::new_bound(py),
+    };
+    if let Some(entry) = downloaders.get_item(name)? {
+        let (downloader, existing): (Bound<'py, PyAny>, Bound<'py, PyDict>) = entry.extract()?;
+        if !config.is_empty() && !existing.eq(&config)? {
+            return Err(PyValueError::new_err(format!(
+                "downloader {:?} already exists with another configuration",
+                name
+            )));
         }
+        return Ok(downloader);
     }
+    let downloader = py.get_type_bound::<CurlDownloader>().call((), Some(&config))?;
+    downloaders.set_item(name, (&downloader, config))?;
+    Ok(downloader)
 }
 
-/// A Python module implemented in Rust.
+/// A Python module implemented in Rust, re-exported by the `pycurse`
+/// package.
 #[pymodule]
-fn pycurse(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_class::<CurlDownloader>()?;
+fn _pycurse(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
+    // log events go to the `pycurse` Python logger
+    pyo3_log::init();
 
-    // start downloader thread
-    thread::spawn(move || {
-        let mut downloader = DOWNLOADER.lock().unwrap();
-        downloader.thread_runner();
-    });
+    m.add_class::<CurlDownloader>()?;
+    m.add_class::<ManualClock>()?;
+    m.add_function(wrap_pyfunction!(get_downloader, m)?)?;
+    m.add_function(wrap_pyfunction!(curl_info, m)?)?;
+    m.add_function(wrap_pyfunction!(global_init, m)?)?;
+    m.add_function(wrap_pyfunction!(global_init_state, m)?)?;
+    m.add("HTTPStatusError", m.py().get_type_bound::<HTTPStatusError>())?;
+    m.add("DeadlineExceeded", m.py().get_type_bound::<DeadlineExceeded>())?;
+    m.add("InactivityTimeout", m.py().get_type_bound::<InactivityTimeout>())?;
 
     //
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirected(url: &str, location: &str) -> Result<Option<Request>, String> {
+        let request = Request {
+            url: url.to_owned(),
+            headers: vec!["Authorization: Bearer secret".to_owned(), "X-Other: kept".to_owned()],
+            credentials: Some(("user".to_owned(), "password".to_owned())),
+            max_redirects: Some(5),
+            ..Default::default()
+        };
+        request.follow(302, location)
+    }
+
+    #[test]
+    fn redirect_credentials() {
+        let same = redirected("https://example.com/", "/next").unwrap().unwrap();
+        assert_eq!(same.url, "https://example.com/next");
+        assert!(same.credentials.is_some());
+        assert_eq!(same.headers.len(), 2);
+        for location in ["http://example.com/next", "https://example.com:8443/", "https://other.test/"] {
+            let next = redirected("https://example.com/", location).unwrap().unwrap();
+            assert_eq!(next.credentials, None, "{location}");
+            assert_eq!(next.headers, ["X-Other: kept"], "{location}");
+        }
+    }
+
+    #[test]
+    fn redirect_schemes() {
+        assert!(redirected("http://example.com/", "https://example.com/").unwrap().is_some());
+        for location in ["file:///etc/hostname", "ftp://example.com/", "gopher://example.com/", "data:,secret"] {
+            let error = redirected("http://example.com/", location).err().unwrap();
+            assert!(error.contains("disallowed scheme"), "{error}");
+        }
+    }
+}
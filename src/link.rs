@@ -0,0 +1,123 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use url::Url;
+
+/// A link of a `Link` header, RFC 8288.
+pub struct Link {
+    /// The target, resolved against the URL of the response.
+    pub url: String,
+    /// The relation types, lowercase and separated by spaces.
+    pub rel: Option<String>,
+    /// The other parameters, with lowercase names.
+    pub params: Vec<(String, String)>,
+}
+
+impl Link {
+    /// Whether `rel` has the relation type `relation`.
+    pub fn has_rel(&self, relation: &str) -> bool {
+        self.rel
+            .as_deref()
+            .is_some_and(|rel| rel.split_ascii_whitespace().any(|value| value == relation))
+    }
+}
+
+/// Parse the values of the `Link` headers of a response from `base`.
+///
+/// Malformed links are skipped.
+pub fn parse<'a>(values: impl IntoIterator<Item = &'a str>, base: &str) -> Vec<Link> {
+    let base = Url::parse(base).ok();
+    let mut links = Vec::new();
+    for value in values {
+        let mut chars = value.chars().peekable();
+        while let Some(link) = parse_link(&mut chars) {
+            if let Some(mut link) = link {
+                if let Some(url) = base.as_ref().and_then(|base| base.join(&link.url).ok()) {
+                    link.url = url.into();
+                }
+                links.push(link);
+            }
+        }
+    }
+    links
+}
+
+/// Parse the next `<target>; name=value; ...` link, up to the comma
+/// ending it. Returns `Some(None)` for a malformed link and `None` at the
+/// end of the value.
+fn parse_link(chars: &mut Peekable<Chars<'_>>) -> Option<Option<Link>> {
+    skip(chars, |c| c.is_whitespace() || c == ',');
+    chars.peek()?;
+    if chars.next() != Some('<') {
+        skip(chars, |c| c != ',');
+        return Some(None);
+    }
+    let url: String = chars.by_ref().take_while(|&c| c != '>').collect();
+    let mut link = Link { url, rel: None, params: Vec::new() };
+    loop {
+        skip(chars, char::is_whitespace);
+        match chars.next() {
+            None | Some(',') => return Some(Some(link)),
+            Some(';') => {}
+            Some(_) => {
+                skip(chars, |c| c != ',');
+                return Some(None);
+            }
+        }
+        skip(chars, char::is_whitespace);
+        let name = take(chars, |c| !matches!(c, '=' | ';' | ',') && !c.is_whitespace()).to_ascii_lowercase();
+        skip(chars, char::is_whitespace);
+        let value = if chars.peek() == Some(&'=') {
+            chars.next();
+            skip(chars, char::is_whitespace);
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                quoted(chars)
+            } else {
+                take(chars, |c| !matches!(c, ';' | ',')).trim_end().to_owned()
+            }
+        } else {
+            String::new()
+        };
+        if name.is_empty() {
+            continue;
+        }
+        if name == "rel" {
+            // Only the first `rel` counts.
+            if link.rel.is_none() {
+                link.rel = Some(value.to_ascii_lowercase());
+            }
+        } else {
+            link.params.push((name, value));
+        }
+    }
+}
+
+/// The rest of a quoted string, after its opening quote.
+fn quoted(chars: &mut Peekable<Chars<'_>>) -> String {
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => value.extend(chars.next()),
+            _ => value.push(c),
+        }
+    }
+    value
+}
+
+fn take(chars: &mut Peekable<Chars<'_>>, predicate: impl Fn(char) -> bool) -> String {
+    let mut value = String::new();
+    while let Some(&c) = chars.peek() {
+        if !predicate(c) {
+            break;
+        }
+        value.push(c);
+        chars.next();
+    }
+    value
+}
+
+fn skip(chars: &mut Peekable<Chars<'_>>, predicate: impl Fn(char) -> bool) {
+    while chars.next_if(|&c| predicate(c)).is_some() {}
+}
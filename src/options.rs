@@ -0,0 +1,222 @@
+//! The options of a request, as given to `add_request()` or `request()`,
+//! checked before it is queued: a value the downloader thread would fail
+//! on, or a combination of options which cannot apply together, raises
+//! `ValueError` right away instead.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::stats::Labels;
+
+#[derive(Default)]
+pub struct RequestOptions<'a> {
+    pub url: &'a str,
+    pub headers: Option<&'a Bound<'a, PyAny>>,
+    pub json: Option<&'a Bound<'a, PyAny>>,
+    pub data: Option<&'a Bound<'a, PyAny>>,
+    pub auth: Option<(String, String)>,
+    pub auth_scheme: Option<&'a str>,
+    pub proxy: Option<String>,
+    pub proxy_tunnel: bool,
+    pub proxy_ca: Option<String>,
+    pub proxy_cert: Option<String>,
+    pub proxy_key: Option<String>,
+    pub proxy_tls_ciphers: Option<String>,
+    pub proxy_tls13_ciphers: Option<String>,
+    pub tls_min_version: Option<&'a str>,
+    pub tls_max_version: Option<&'a str>,
+    pub tls_ciphers: Option<String>,
+    pub tls13_ciphers: Option<String>,
+    pub alpn: Option<bool>,
+    pub http_version: Option<&'a str>,
+    pub connect_to: Option<Vec<String>>,
+    pub host: Option<&'a str>,
+    pub fresh_connect: bool,
+    pub fresh_dns: bool,
+    pub forbid_reuse: bool,
+    pub path: Option<String>,
+    pub preserve_mtime: bool,
+    pub resume: bool,
+    pub only_if_newer: bool,
+    pub if_none_match: Option<&'a Bound<'a, PyAny>>,
+    pub if_modified_since: Option<&'a Bound<'a, PyAny>>,
+    pub upload: Option<String>,
+    pub create_dirs: bool,
+    pub headers_only: bool,
+    pub accept_content_types: Option<Vec<String>>,
+    pub max_content_length: Option<u64>,
+    pub into: Option<&'a Bound<'a, PyAny>>,
+    pub decode_content: Option<bool>,
+    pub allow_http09: Option<bool>,
+    pub ignore_content_length: Option<bool>,
+    pub process: Option<Vec<String>>,
+    pub digests: Option<Vec<String>>,
+    pub ranges: Option<Vec<(u64, Option<u64>)>>,
+    pub follow_redirects: bool,
+    pub max_redirects: u32,
+    pub on_redirect: Option<PyObject>,
+    pub profile: Option<&'a str>,
+    pub locale: Option<String>,
+    pub labels: Option<Labels>,
+    pub traceparent: Option<&'a str>,
+    pub deadline: Option<&'a Bound<'a, PyAny>>,
+    pub inactivity_timeout: Option<f64>,
+    /// The options of `add_request()` only.
+    pub stream: bool,
+    pub paginate: bool,
+    pub max_pages: Option<u32>,
+    /// Whether the response goes to a callback.
+    pub callback: bool,
+}
+
+impl RequestOptions<'_> {
+    /// Check the values libcurl would refuse once the transfer starts, and
+    /// the options which exclude each other.
+    pub fn validate(&self) -> PyResult<()> {
+        if self.url.is_empty() {
+            return Err(PyValueError::new_err("url must not be empty"));
+        }
+        check_text("url", self.url)?;
+        if let Some((username, password)) = &self.auth {
+            check_text("auth", username)?;
+            check_text("auth", password)?;
+        }
+        let texts = [
+            ("proxy", &self.proxy),
+            ("proxy_ca", &self.proxy_ca),
+            ("proxy_cert", &self.proxy_cert),
+            ("proxy_key", &self.proxy_key),
+            ("proxy_tls_ciphers", &self.proxy_tls_ciphers),
+            ("proxy_tls13_ciphers", &self.proxy_tls13_ciphers),
+            ("tls_ciphers", &self.tls_ciphers),
+            ("tls13_ciphers", &self.tls13_ciphers),
+            ("path", &self.path),
+            ("upload", &self.upload),
+        ];
+        for (name, value) in texts {
+            if let Some(value) = value {
+                check_text(name, value)?;
+            }
+        }
+        for entry in self.connect_to.iter().flatten() {
+            check_connect_to(entry)?;
+        }
+        if let Some(host) = self.host {
+            check_text("host", host)?;
+        }
+        if let Some(types) = &self.accept_content_types {
+            if types.iter().any(|mime| mime.trim().is_empty() || !mime.contains('/')) {
+                return Err(PyValueError::new_err(
+                    "accept_content_types must be media types like text/html",
+                ));
+            }
+        }
+        self.validate_combinations()?;
+        if let Some(ranges) = &self.ranges {
+            if ranges.is_empty() {
+                return Err(PyValueError::new_err("ranges must not be empty"));
+            }
+            if let Some((first, Some(last))) = ranges
+                .iter()
+                .find(|(first, last)| last.is_some_and(|last| last < *first))
+            {
+                return Err(PyValueError::new_err(format!("invalid range: {}-{}", first, last)));
+            }
+        }
+        if self.max_pages == Some(0) {
+            return Err(PyValueError::new_err("max_pages must be at least 1"));
+        }
+        Ok(())
+    }
+
+    fn validate_combinations(&self) -> PyResult<()> {
+        let exclusive = |message: &str| Err(PyValueError::new_err(message.to_owned()));
+        let (path, into) = (self.path.is_some(), self.into.is_some());
+        if self.stream && path {
+            return exclusive("stream and path are mutually exclusive");
+        }
+        if self.stream && (self.headers_only || into) {
+            return exclusive("stream, headers_only and into are mutually exclusive");
+        }
+        if self.stream && self.process.is_some() {
+            return exclusive("process requires the body in memory, not streamed");
+        }
+        if self.stream && self.digests.is_some() {
+            return exclusive("stream and digests are mutually exclusive");
+        }
+        if self.stream && self.ranges.is_some() {
+            return exclusive("ranges requires the body in memory, not streamed");
+        }
+        if self.paginate && self.callback {
+            return exclusive("callback and paginate are mutually exclusive");
+        }
+        if self.headers_only && path {
+            return exclusive("headers_only and path are mutually exclusive");
+        }
+        if into && (path || self.headers_only) {
+            return exclusive("into, path and headers_only are mutually exclusive");
+        }
+        if self.process.is_some() && (path || into || self.headers_only) {
+            return exclusive("process requires the body in memory, not with path, into or headers_only");
+        }
+        if self.headers_only && self.digests.is_some() {
+            return exclusive("headers_only and digests are mutually exclusive");
+        }
+        if self.ranges.is_some() && (path || into) {
+            return exclusive("ranges requires the body in memory, not with path or into");
+        }
+        if (self.resume || self.only_if_newer) && !path {
+            return exclusive("resume and only_if_newer require a path");
+        }
+        if [self.json.is_some(), self.data.is_some(), self.upload.is_some()]
+            .iter()
+            .filter(|set| **set)
+            .count()
+            > 1
+        {
+            return exclusive("json, data and upload are mutually exclusive");
+        }
+        Ok(())
+    }
+}
+
+/// Check a string option passed to libcurl, which stops at a NUL
+/// character.
+pub fn check_text(name: &str, value: &str) -> PyResult<()> {
+    match value.contains('\0') {
+        true => Err(PyValueError::new_err(format!(
+            "{} must not contain a NUL character",
+            name
+        ))),
+        false => Ok(()),
+    }
+}
+
+/// Check a `HOST:PORT:CONNECT-TO-HOST:CONNECT-TO-PORT` entry, the hosts
+/// possibly IPv6 addresses between brackets and the fields empty.
+pub fn check_connect_to(entry: &str) -> PyResult<()> {
+    let invalid = || {
+        PyValueError::new_err(format!(
+            "invalid connect_to entry {:?}, expected HOST:PORT:CONNECT-TO-HOST:CONNECT-TO-PORT",
+            entry
+        ))
+    };
+    check_text("connect_to", entry)?;
+    let mut rest = entry;
+    for field in 0..4 {
+        let end = match rest.strip_prefix('[') {
+            Some(address) if field % 2 == 0 => address.find(']').ok_or_else(invalid)? + 2,
+            _ => rest.find(':').unwrap_or(rest.len()),
+        };
+        let (value, next) = rest.split_at(end);
+        if field % 2 == 1 && !value.is_empty() && value.parse::<u16>().is_err() {
+            return Err(invalid());
+        }
+        rest = match (field, next.strip_prefix(':')) {
+            (3, _) if next.is_empty() => next,
+            (0..=2, Some(next)) => next,
+            _ => return Err(invalid()),
+        };
+    }
+    Ok(())
+}
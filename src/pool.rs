@@ -0,0 +1,38 @@
+use std::sync::Mutex;
+
+/// The capacity of a new buffer.
+const INITIAL_CAPACITY: usize = 16 * 1024;
+/// Larger buffers are freed rather than kept for another transfer.
+const MAX_CAPACITY: usize = 1024 * 1024;
+/// The maximum number of idle buffers kept.
+const MAX_BUFFERS: usize = 1024;
+
+/// Body buffers recycled between transfers.
+///
+/// A buffer comes back once its response is dropped, cleared but with its
+/// capacity kept, so most small responses need no allocation.
+#[derive(Default)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    pub fn get(&self) -> Vec<u8> {
+        self.buffers
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(INITIAL_CAPACITY))
+    }
+
+    pub fn put(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() == 0 || buffer.capacity() > MAX_CAPACITY {
+            return;
+        }
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < MAX_BUFFERS {
+            buffer.clear();
+            buffers.push(buffer);
+        }
+    }
+}
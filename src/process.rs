@@ -0,0 +1,129 @@
+use crate::{feed, gzip, html, json, sitemap};
+use pyo3::prelude::*;
+
+/// The most a gzip body is decompressed to, like the 50 MB of the largest
+/// sitemaps with room to spare.
+const MAX_DECOMPRESSED_SIZE: usize = 256 * 1024 * 1024;
+
+/// A step run on the body of a response in the downloader thread, once it
+/// is received, so a hot path parses it without holding the GIL.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Processor {
+    /// Parse the body as JSON.
+    Json,
+    /// The CRC-32 of the body, the one of zlib and gzip.
+    Crc32,
+    /// The `href` and `src` links of an HTML body.
+    Links,
+    /// Parse a sitemap or a sitemap index, gzipped or not.
+    Sitemap,
+    /// Parse an RSS or Atom feed.
+    Feed,
+}
+
+impl Processor {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "json" => Ok(Processor::Json),
+            "crc32" => Ok(Processor::Crc32),
+            "links" => Ok(Processor::Links),
+            "sitemap" => Ok(Processor::Sitemap),
+            "feed" => Ok(Processor::Feed),
+            _ => Err(format!(
+                "unknown processor: {}, expected json, crc32, links, sitemap or feed",
+                name
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Processor::Json => "json",
+            Processor::Crc32 => "crc32",
+            Processor::Links => "links",
+            Processor::Sitemap => "sitemap",
+            Processor::Feed => "feed",
+        }
+    }
+
+    /// Run on the body of a response received from `url`.
+    pub fn run(self, body: &[u8], url: &str) -> Result<Output, String> {
+        match self {
+            Processor::Json => json::parse(body).map(Output::Json),
+            Processor::Crc32 => Ok(Output::Int(gzip::crc32(body))),
+            Processor::Links => Ok(Output::Strings(html::links(body, url))),
+            Processor::Sitemap | Processor::Feed if gzip::is_gzip(body) => {
+                self.run(&gzip::decompress(body, MAX_DECOMPRESSED_SIZE)?, url)
+            }
+            Processor::Sitemap => sitemap::parse(body, url).map(Output::Json),
+            Processor::Feed => feed::parse(body, url).map(Output::Json),
+        }
+    }
+}
+
+/// The result of a processor, converted to Python objects once the
+/// response reaches Python.
+pub enum Output {
+    Json(json::Value),
+    Int(u32),
+    Strings(Vec<String>),
+}
+
+impl Output {
+    pub fn to_python(&self, py: Python<'_>) -> PyResult<PyObject> {
+        match self {
+            Output::Json(value) => value.to_python(py),
+            Output::Int(value) => Ok(value.into_py(py)),
+            Output::Strings(values) => Ok(values.to_object(py)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gzip::tests::gzip;
+
+    fn json(output: Result<Output, String>) -> String {
+        let Ok(Output::Json(value)) = output else {
+            panic!("not a JSON output");
+        };
+        let mut out = String::new();
+        value.write(&mut out);
+        out
+    }
+
+    #[test]
+    fn names() {
+        for name in ["json", "crc32", "links", "sitemap", "feed"] {
+            assert_eq!(Processor::parse(name).unwrap().name(), name);
+        }
+        assert!(Processor::parse("yaml").unwrap_err().starts_with("unknown processor: yaml"));
+    }
+
+    #[test]
+    fn gzipped() {
+        let url = "https://example.com/sitemap.xml.gz";
+        let sitemap = b"<urlset><url><loc>/a</loc></url></urlset>";
+        let expected = concat!(
+            r#"{"urls":[{"loc":"https://example.com/a","lastmod":null,"changefreq":null,"priority":null}],"#,
+            r#""sitemaps":[]}"#,
+        );
+        assert_eq!(json(Processor::Sitemap.run(sitemap, url)), expected);
+        assert_eq!(json(Processor::Sitemap.run(&gzip(sitemap, 9), url)), expected);
+        let feed = b"<feed><title>T</title></feed>";
+        let expected = r#"{"format":"atom","title":"T","link":null,"entries":[]}"#;
+        assert_eq!(json(Processor::Feed.run(&gzip(feed, 1), url)), expected);
+        let compressed = gzip(sitemap, 6);
+        let error = Processor::Sitemap.run(&compressed[..compressed.len() - 4], url).err().unwrap();
+        assert_eq!(error, "truncated gzip file");
+    }
+
+    #[test]
+    fn crc32() {
+        let Ok(Output::Int(crc)) = Processor::Crc32.run(b"123456789", "https://example.com/") else {
+            panic!("not an integer output");
+        };
+        assert_eq!(crc, 0xcbf4_3926);
+    }
+}
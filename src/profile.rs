@@ -0,0 +1,103 @@
+/// The headers a browser sends with a page request, so scraping requests
+/// look like it consistently instead of mixing headers of several ones.
+///
+/// The versions are fixed, recent ones on Windows, or macOS for Safari.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Profile {
+    Chrome,
+    Firefox,
+    Safari,
+}
+
+impl Profile {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "chrome" => Ok(Profile::Chrome),
+            "firefox" => Ok(Profile::Firefox),
+            "safari" => Ok(Profile::Safari),
+            _ => Err(format!("unknown profile: {}, expected chrome, firefox or safari", name)),
+        }
+    }
+
+    /// The headers, as `Name: value` lines, for `locale` like `de-DE`.
+    pub fn headers(self, locale: &str) -> Vec<String> {
+        let (user_agent, accept) = match self {
+            Profile::Chrome => (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) \
+                 Chrome/141.0.0.0 Safari/537.36",
+                "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,\
+                 application/signed-exchange;v=b3;q=0.7",
+            ),
+            Profile::Firefox => (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:143.0) Gecko/20100101 Firefox/143.0",
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+            ),
+            Profile::Safari => (
+                "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) \
+                 Version/26.0 Safari/605.1.15",
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+            ),
+        };
+        let mut headers = vec![
+            format!("User-Agent: {}", user_agent),
+            format!("Accept: {}", accept),
+            format!("Accept-Language: {}", self.accept_language(locale)),
+        ];
+        if self == Profile::Chrome {
+            headers.extend([
+                r#"sec-ch-ua: "Google Chrome";v="141", "Not?A_Brand";v="8", "Chromium";v="141""#.to_owned(),
+                "sec-ch-ua-mobile: ?0".to_owned(),
+                r#"sec-ch-ua-platform: "Windows""#.to_owned(),
+            ]);
+        }
+        headers.push("Upgrade-Insecure-Requests: 1".to_owned());
+        headers
+    }
+
+    /// The `Accept-Language` of the browser set to `locale`, which falls
+    /// back to its language, then to English.
+    fn accept_language(self, locale: &str) -> String {
+        let language = locale.split('-').next().unwrap_or(locale);
+        let mut tags = vec![locale];
+        if language != locale {
+            tags.push(language);
+        }
+        if language != "en" {
+            tags.extend(["en-US", "en"]);
+        }
+        let weights: &[&str] = match self {
+            Profile::Firefox if tags.len() == 2 => &["0.5"],
+            Profile::Firefox => &["0.8", "0.5", "0.3"],
+            Profile::Chrome | Profile::Safari => &["0.9", "0.8", "0.7"],
+        };
+        accept_list(&tags, weights)
+    }
+}
+
+/// The `Accept-Language` of `locale` without a profile.
+pub fn accept_language(locale: &str) -> String {
+    let language = locale.split('-').next().unwrap_or(locale);
+    if language == locale {
+        locale.to_owned()
+    } else {
+        accept_list(&[locale, language], &["0.9"])
+    }
+}
+
+/// The tags, the first one without weight and the next ones with
+/// `weights`, the last weight repeated.
+fn accept_list(tags: &[&str], weights: &[&str]) -> String {
+    let mut list = tags[0].to_owned();
+    for (index, tag) in tags.iter().enumerate().skip(1) {
+        let weight = weights.get(index - 1).or(weights.last()).unwrap();
+        list.push_str(&format!(",{};q={}", tag, weight));
+    }
+    list
+}
+
+/// Whether `locale` is a language tag, like `en` or `pt-BR`.
+pub fn is_locale(locale: &str) -> bool {
+    locale
+        .split('-')
+        .all(|tag| (1..=8).contains(&tag.len()) && tag.bytes().all(|c| c.is_ascii_alphanumeric()))
+}
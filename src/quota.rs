@@ -0,0 +1,71 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Caps the bytes downloaded from each host and the time spent in its
+/// transfers over a sliding window.
+///
+/// A crawler shares its transfers between many sites; a quota keeps a
+/// single one with huge or slow responses from taking them all.
+pub struct HostQuotas {
+    max_bytes: Option<u64>,
+    max_time: Option<Duration>,
+    window: Duration,
+    /// The requests to a host over its quota wait until it is under it
+    /// again, instead of failing.
+    pub defer: bool,
+    /// When the transfers to each host ended, with their size and time,
+    /// oldest first.
+    usage: HashMap<String, VecDeque<(Instant, u64, Duration)>>,
+}
+
+impl HostQuotas {
+    pub fn new(max_bytes: Option<u64>, max_time: Option<Duration>, window: Duration, defer: bool) -> Self {
+        HostQuotas {
+            max_bytes,
+            max_time,
+            window,
+            defer,
+            usage: HashMap::new(),
+        }
+    }
+
+    /// Count a transfer to `host`.
+    pub fn record(&mut self, host: &str, bytes: u64, time: Duration) {
+        let now = Instant::now();
+        self.expire(now);
+        self.usage.entry(host.to_owned()).or_default().push_back((now, bytes, time));
+    }
+
+    /// When `host` gets under its quota again, None if it is now.
+    pub fn exceeded(&mut self, host: &str) -> Option<Instant> {
+        self.expire(Instant::now());
+        let transfers = self.usage.get(host)?;
+        let mut bytes: u64 = transfers.iter().map(|(_, bytes, _)| bytes).sum();
+        let mut time: Duration = transfers.iter().map(|(_, _, time)| *time).sum();
+        let over = |bytes: u64, time: Duration| {
+            self.max_bytes.is_some_and(|max| bytes >= max) || self.max_time.is_some_and(|max| time >= max)
+        };
+        if !over(bytes, time) {
+            return None;
+        }
+        // Under the quota once enough of the oldest transfers leave the
+        // window.
+        for (ended, transfer_bytes, transfer_time) in transfers {
+            bytes -= transfer_bytes;
+            time -= *transfer_time;
+            if !over(bytes, time) {
+                return Some(*ended + self.window);
+            }
+        }
+        unreachable!("the quota should not be exceeded without transfers")
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.usage.retain(|_, transfers| {
+            while transfers.front().is_some_and(|(ended, _, _)| now.duration_since(*ended) > self.window) {
+                transfers.pop_front();
+            }
+            !transfers.is_empty()
+        });
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use curl::multi::{Events, Multi, Socket};
+use polling::{Event, PollMode, Poller};
+
+/// Drives a `Multi` with curl's socket-action API.
+///
+/// curl tells through its socket callback which sockets to watch, and
+/// through its timer callback when it wants to be called back. The sockets
+/// are watched with epoll, kqueue or IOCP, so the cost of a wait does not
+/// grow with the number of transfers.
+pub struct Reactor {
+    poller: Arc<Poller>,
+    /// When curl wants `Multi::timeout` to be called.
+    deadline: Arc<Mutex<Option<Instant>>>,
+    events: polling::Events,
+}
+
+impl Reactor {
+    /// Install the callbacks of `multi`, watching its sockets with `poller`.
+    ///
+    /// `Poller::notify` interrupts the wait of `poll`.
+    pub fn attach(multi: &mut Multi, poller: Arc<Poller>) -> io::Result<Self> {
+        let deadline = Arc::new(Mutex::new(None));
+
+        let socket_poller = poller.clone();
+        let mut watched = HashSet::new();
+        multi
+            .socket_function(move |socket, events, _| {
+                if events.remove() {
+                    watched.remove(&socket);
+                    // curl removes a socket before closing it, an error here
+                    // only means it is already gone.
+                    let _ = socket_poller.delete(borrow(socket));
+                    return;
+                }
+                let interest = Event::new(socket as usize, events.input(), events.output());
+                let result = if watched.insert(socket) {
+                    // SAFETY: the socket stays open until curl removes it.
+                    unsafe { socket_poller.add_with_mode(raw(socket), interest, PollMode::Level) }
+                } else {
+                    socket_poller.modify_with_mode(borrow(socket), interest, PollMode::Level)
+                };
+                if let Err(error) = result {
+                    log::error!("failed to watch socket {}: {}", socket, error);
+                }
+            })
+            .map_err(io::Error::other)?;
+
+        let timer_deadline = deadline.clone();
+        multi
+            .timer_function(move |timeout| {
+                *timer_deadline.lock().unwrap() = timeout.map(|timeout| Instant::now() + timeout);
+                true
+            })
+            .map_err(io::Error::other)?;
+
+        Ok(Reactor {
+            poller,
+            deadline,
+            events: polling::Events::new(),
+        })
+    }
+
+    /// Wait at most `max_wait` for socket activity or curl's timer, and let
+    /// `multi` act on it.
+    pub fn poll(&mut self, multi: &Multi, max_wait: Duration) -> io::Result<()> {
+        let wait = match *self.deadline.lock().unwrap() {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()).min(max_wait),
+            None => max_wait,
+        };
+        self.events.clear();
+        self.poller.wait(&mut self.events, Some(wait))?;
+
+        for event in self.events.iter() {
+            let mut events = Events::new();
+            events.input(event.readable).output(event.writable);
+            multi.action(event.key as Socket, &events).map_err(io::Error::other)?;
+        }
+        let expired = self
+            .deadline
+            .lock()
+            .unwrap()
+            .is_some_and(|deadline| deadline <= Instant::now());
+        if expired {
+            *self.deadline.lock().unwrap() = None;
+            multi.timeout().map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn raw(socket: Socket) -> std::os::fd::RawFd {
+    socket
+}
+
+#[cfg(windows)]
+fn raw(socket: Socket) -> std::os::windows::io::RawSocket {
+    socket as _
+}
+
+#[cfg(unix)]
+fn borrow(socket: Socket) -> std::os::fd::BorrowedFd<'static> {
+    // SAFETY: only used while curl keeps the socket open.
+    unsafe { std::os::fd::BorrowedFd::borrow_raw(socket) }
+}
+
+#[cfg(windows)]
+fn borrow(socket: Socket) -> std::os::windows::io::BorrowedSocket<'static> {
+    // SAFETY: only used while curl keeps the socket open.
+    unsafe { std::os::windows::io::BorrowedSocket::borrow_raw(socket as _) }
+}
@@ -0,0 +1,75 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::trace::random_u64;
+
+/// How long failed transfers wait before they are tried again.
+#[derive(Clone, Copy, Default)]
+pub struct Backoff {
+    /// The wait before the first retry, doubled for each next one.
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// The wait before the retry following `attempt` failed attempts, with
+    /// full jitter: a random duration up to the exponential backoff, so
+    /// the retries of transfers that failed together spread out.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        if self.base.is_zero() {
+            return Duration::ZERO;
+        }
+        let ceiling = self.base.saturating_mul(2u32.saturating_pow(attempt)).min(self.max);
+        ceiling.mul_f64(random_u64() as f64 / u64::MAX as f64)
+    }
+}
+
+/// Caps the retries to a share of the requests over a sliding window.
+///
+/// When a server struggles, most requests fail and each retry adds to its
+/// load; with a budget, retries stop before they multiply the traffic.
+pub struct RetryBudget {
+    ratio: f64,
+    window: Duration,
+    /// When the requests were made and when they were retried, oldest
+    /// first.
+    requests: VecDeque<Instant>,
+    retries: VecDeque<Instant>,
+}
+
+impl RetryBudget {
+    pub fn new(ratio: f64, window: Duration) -> Self {
+        RetryBudget {
+            ratio,
+            window,
+            requests: VecDeque::new(),
+            retries: VecDeque::new(),
+        }
+    }
+
+    /// Count a request, on its first attempt.
+    pub fn record_request(&mut self) {
+        let now = Instant::now();
+        self.expire(now);
+        self.requests.push_back(now);
+    }
+
+    /// Whether a retry fits in the budget, in which case it is counted.
+    pub fn try_retry(&mut self) -> bool {
+        let now = Instant::now();
+        self.expire(now);
+        if self.retries.len() as f64 >= self.ratio * self.requests.len() as f64 {
+            return false;
+        }
+        self.retries.push_back(now);
+        true
+    }
+
+    fn expire(&mut self, now: Instant) {
+        for times in [&mut self.requests, &mut self.retries] {
+            while times.front().is_some_and(|time| now.duration_since(*time) > self.window) {
+                times.pop_front();
+            }
+        }
+    }
+}
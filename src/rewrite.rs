@@ -0,0 +1,98 @@
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use url::Url;
+
+use crate::hosts::pattern_matches;
+
+/// The rules rewriting the URLs of the requests as they are added: hosts
+/// routed to others, like mirrors, and hosts always requested over HTTPS.
+///
+/// The patterns are the ones of `HostPolicy`, matched against the host of
+/// the URL as given.
+pub struct UrlRewrites {
+    /// Lowercase patterns and the `host[:port]` replacing the hosts they
+    /// match, the first matching one applying.
+    hosts: Vec<(String, String)>,
+    https: Vec<String>,
+}
+
+impl UrlRewrites {
+    pub fn new(hosts: Vec<(String, String)>, https: Vec<String>) -> PyResult<Self> {
+        for (_, target) in &hosts {
+            // The target is checked by setting it on a URL.
+            let mut url = Url::parse("http://localhost/").unwrap();
+            if target.is_empty() || set_authority(&mut url, target).is_err() {
+                return Err(PyValueError::new_err(format!(
+                    "invalid host_map target {:?}, expected HOST or HOST:PORT",
+                    target
+                )));
+            }
+        }
+        Ok(UrlRewrites {
+            hosts: hosts
+                .into_iter()
+                .map(|(pattern, target)| (pattern.to_ascii_lowercase(), target))
+                .collect(),
+            https: https.into_iter().map(|pattern| pattern.to_ascii_lowercase()).collect(),
+        })
+    }
+
+    /// `url` rewritten, None when no rule applies to it.
+    pub fn apply(&self, url: &str) -> Option<String> {
+        let mut parsed = Url::parse(url).ok()?;
+        let host = parsed.host_str()?.to_ascii_lowercase();
+        let host = host.trim_end_matches('.');
+        let mut rewritten = false;
+        if let Some((_, target)) = self.hosts.iter().find(|(pattern, _)| pattern_matches(pattern, host)) {
+            rewritten = set_authority(&mut parsed, target).is_ok();
+        }
+        if parsed.scheme() == "http" && self.https.iter().any(|pattern| pattern_matches(pattern, host)) {
+            // An explicit port 80 goes with the scheme.
+            if parsed.port() == Some(80) {
+                let _ = parsed.set_port(None);
+            }
+            rewritten |= parsed.set_scheme("https").is_ok();
+        }
+        rewritten.then(|| parsed.into())
+    }
+}
+
+/// Set the host of `url`, and its port when `target` has one.
+fn set_authority(url: &mut Url, target: &str) -> Result<(), ()> {
+    let (host, port) = match target.rsplit_once(':') {
+        // Not the colons of an IPv6 address.
+        Some((host, port)) if !port.contains(']') && (!host.contains(':') || host.ends_with(']')) => {
+            (host, Some(port.parse::<u16>().map_err(|_| ())?))
+        }
+        _ => (target, None),
+    };
+    url.set_host(Some(host)).map_err(|_| ())?;
+    if port.is_some() {
+        url.set_port(port)?;
+    }
+    Ok(())
+}
+
+/// The `(pattern, target)` pairs of `host_map`, a dict or a list of pairs,
+/// the order kept.
+pub fn host_map_pairs(value: &Bound<'_, PyAny>) -> PyResult<Vec<(String, String)>> {
+    if let Ok(dict) = value.downcast::<PyDict>() {
+        return dict
+            .iter()
+            .map(|(pattern, target)| Ok((pattern.extract()?, target.extract()?)))
+            .collect();
+    }
+    let invalid = || PyTypeError::new_err("host_map must be a dict or a list of (pattern, host) pairs");
+    value
+        .iter()
+        .map_err(|_| invalid())?
+        .map(|pair| {
+            let pair: Vec<String> = pair?.extract().map_err(|_| invalid())?;
+            match <[String; 2]>::try_from(pair) {
+                Ok([pattern, target]) => Ok((pattern, target)),
+                Err(_) => Err(invalid()),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+/// The rules of a robots.txt file that apply to one user agent.
+///
+/// Follows RFC 9309: the most specific (longest) matching rule wins, and
+/// `Allow` wins over `Disallow` when both are equally specific. `*` and `$`
+/// are supported in paths.
+#[derive(Debug, Default)]
+pub struct Robots {
+    rules: Vec<(bool, String)>,
+    pub crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    /// Rules allowing everything, used when there is no robots.txt.
+    pub fn allow_all() -> Self {
+        Robots::default()
+    }
+
+    /// Rules disallowing everything, used when robots.txt is unreachable.
+    pub fn disallow_all() -> Self {
+        Robots {
+            rules: vec![(false, "/".to_owned())],
+            crawl_delay: None,
+        }
+    }
+
+    /// Parse `content` and keep the group of `agent`, or the `*` group.
+    pub fn parse(content: &str, agent: &str) -> Self {
+        let agent = agent.to_ascii_lowercase();
+        let mut specific = Robots::default();
+        let mut generic = Robots::default();
+        let mut found_specific = false;
+
+        // The user agents of the group being read, and whether its rules
+        // have started (a user-agent line after a rule starts a new group).
+        let mut group_agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            let value = value.trim();
+
+            if key == "user-agent" {
+                if in_rules {
+                    group_agents.clear();
+                    in_rules = false;
+                }
+                group_agents.push(value.to_ascii_lowercase());
+                continue;
+            }
+            in_rules = true;
+
+            let matches_agent = group_agents.contains(&agent);
+            let target = if matches_agent {
+                found_specific = true;
+                &mut specific
+            } else if group_agents.iter().any(|name| name == "*") {
+                &mut generic
+            } else {
+                continue;
+            };
+            match key.as_str() {
+                "allow" => target.rules.push((true, value.to_owned())),
+                // An empty Disallow allows everything.
+                "disallow" if !value.is_empty() => target.rules.push((false, value.to_owned())),
+                "crawl-delay" => {
+                    if let Ok(seconds) = value.parse::<f64>() {
+                        if seconds.is_finite() && seconds >= 0.0 {
+                            target.crawl_delay = Some(Duration::from_secs_f64(seconds));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if found_specific {
+            specific
+        } else {
+            generic
+        }
+    }
+
+    /// Whether `path` (including the query string) may be fetched.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        let mut best: Option<(usize, bool)> = None;
+        for (allow, pattern) in &self.rules {
+            if !pattern_matches(pattern, path) {
+                continue;
+            }
+            let length = pattern.len();
+            best = match best {
+                Some((best_length, best_allow))
+                    if best_length > length || (best_length == length && best_allow) =>
+                {
+                    Some((best_length, best_allow))
+                }
+                _ => Some((length, *allow)),
+            };
+        }
+        best.is_none_or(|(_, allow)| allow)
+    }
+}
+
+/// Match a robots.txt path pattern against the start of `path`.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        if last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "\
+User-agent: *
+Disallow: /private  # a comment
+Allow: /private/public
+Disallow: /*.pdf$
+Crawl-delay: 2.5
+
+User-agent: Pycurse
+User-agent: other
+Disallow: /
+Allow: /$
+Allow: /page
+Disallow: /page
+Crawl-delay: -1
+";
+
+    #[test]
+    fn groups() {
+        let generic = Robots::parse(ROBOTS, "curl");
+        assert_eq!(generic.crawl_delay, Some(Duration::from_millis(2500)));
+        assert!(generic.is_allowed("/"));
+        assert!(!generic.is_allowed("/private/page"));
+        let specific = Robots::parse(ROBOTS, "PyCurse");
+        assert_eq!(specific.crawl_delay, None);
+        assert!(specific.is_allowed("/"));
+        assert!(!specific.is_allowed("/private/public"));
+        assert!(Robots::parse("User-agent: other\nDisallow: /\n", "curl").is_allowed("/page"));
+        assert!(Robots::parse("", "curl").is_allowed("/page"));
+        assert!(Robots::parse("User-agent: *\nDisallow:\n", "curl").is_allowed("/page"));
+    }
+
+    #[test]
+    fn precedence() {
+        let generic = Robots::parse(ROBOTS, "curl");
+        // The longest matching rule wins, whatever their order.
+        assert!(generic.is_allowed("/private/public/page"));
+        assert!(!generic.is_allowed("/private/publi"));
+        let robots = Robots::parse("User-agent: *\nAllow: /a/b\nDisallow: /a\n", "curl");
+        assert!(robots.is_allowed("/a/b/c"));
+        assert!(!robots.is_allowed("/a/c"));
+        // Allow wins over an equally long Disallow.
+        let specific = Robots::parse(ROBOTS, "pycurse");
+        assert!(specific.is_allowed("/page"));
+        assert!(specific.is_allowed("/pages?query"));
+        assert!(!specific.is_allowed("/other"));
+    }
+
+    #[test]
+    fn wildcards() {
+        let generic = Robots::parse(ROBOTS, "curl");
+        assert!(!generic.is_allowed("/doc.pdf"));
+        assert!(!generic.is_allowed("/a/b/doc.pdf"));
+        assert!(generic.is_allowed("/doc.pdf?download"));
+        assert!(generic.is_allowed("/doc.pdf.html"));
+        // `$` alone anchors the end of the path.
+        let specific = Robots::parse(ROBOTS, "pycurse");
+        assert!(specific.is_allowed("/"));
+        assert!(!specific.is_allowed("/index.html"));
+        let robots = Robots::parse("User-agent: *\nDisallow: /*/edit*\nDisallow: /a*b$\n", "curl");
+        assert!(!robots.is_allowed("/wiki/edit"));
+        assert!(!robots.is_allowed("/wiki/page/edit?id=1"));
+        assert!(robots.is_allowed("/edit"));
+        assert!(!robots.is_allowed("/ab"));
+        assert!(!robots.is_allowed("/a/x/b"));
+        assert!(robots.is_allowed("/a/x/bc"));
+    }
+
+    #[test]
+    fn allow_and_disallow_all() {
+        assert!(Robots::allow_all().is_allowed("/private"));
+        assert!(!Robots::disallow_all().is_allowed("/"));
+        assert!(!Robots::disallow_all().is_allowed("/page?query"));
+    }
+}
@@ -0,0 +1,137 @@
+use url::Url;
+
+use crate::json::Value;
+use crate::xml;
+
+/// Parse a sitemap, https://www.sitemaps.org/protocol.html, received from
+/// `url`, as `{"urls": [...], "sitemaps": [...]}`.
+///
+/// The URLs of a `<urlset>` have their `loc`, `lastmod`, `changefreq` and
+/// `priority`, the sitemaps of a `<sitemapindex>` their `loc` and
+/// `lastmod`, None when missing; dates are kept as written. A text
+/// sitemap has one URL per line.
+pub fn parse(data: &[u8], url: &str) -> Result<Value, String> {
+    let base = Url::parse(url).ok();
+    let resolve = |loc: &str| match &base {
+        Some(base) => base.join(loc).ok().map(String::from),
+        None => Some(loc.to_owned()),
+    };
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    if !data.trim_ascii_start().starts_with(b"<") {
+        let urls = String::from_utf8_lossy(data)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .filter_map(resolve)
+            .map(|loc| url_entry(loc, None, None, None))
+            .collect();
+        return Ok(Value::object([("urls", Value::Array(urls)), ("sitemaps", Value::Array(Vec::new()))]));
+    }
+
+    let root = xml::parse(data)?;
+    let entries = |name| {
+        root.children(name)
+            .filter_map(|entry| {
+                let loc = resolve(entry.child_text("loc")?)?;
+                Some((entry, loc))
+            })
+            .collect::<Vec<_>>()
+    };
+    let (urls, sitemaps) = match root.name.as_str() {
+        "urlset" => (entries("url"), Vec::new()),
+        "sitemapindex" => (Vec::new(), entries("sitemap")),
+        name => return Err(format!("not a sitemap, the root element is <{}>", name)),
+    };
+    let urls = urls
+        .into_iter()
+        .map(|(entry, loc)| {
+            let priority = entry.child_text("priority").and_then(|priority| priority.parse().ok());
+            url_entry(loc, entry.child_text("lastmod"), entry.child_text("changefreq"), priority)
+        })
+        .collect();
+    let sitemaps = sitemaps
+        .into_iter()
+        .map(|(entry, loc)| {
+            Value::object([("loc", Value::String(loc)), ("lastmod", entry.child_text("lastmod").into())])
+        })
+        .collect();
+    Ok(Value::object([("urls", Value::Array(urls)), ("sitemaps", Value::Array(sitemaps))]))
+}
+
+fn url_entry(loc: String, lastmod: Option<&str>, changefreq: Option<&str>, priority: Option<f64>) -> Value {
+    Value::object([
+        ("loc", Value::String(loc)),
+        ("lastmod", lastmod.into()),
+        ("changefreq", changefreq.into()),
+        ("priority", priority.map_or(Value::Null, Value::Float)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(data: &str) -> String {
+        let mut out = String::new();
+        parse(data.as_bytes(), "https://example.com/sitemaps/main.xml").unwrap().write(&mut out);
+        out
+    }
+
+    #[test]
+    fn urlset() {
+        let sitemap = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url>
+                <loc> https://example.com/a?x=1&amp;y=2 </loc>
+                <lastmod>2024-01-02</lastmod>
+                <changefreq>daily</changefreq>
+                <priority>0.8</priority>
+              </url>
+              <url><loc>/relative</loc><priority>high</priority></url>
+              <url><lastmod>2024-01-02</lastmod></url>
+            </urlset>"#;
+        assert_eq!(
+            parsed(sitemap),
+            concat!(
+                r#"{"urls":["#,
+                r#"{"loc":"https://example.com/a?x=1&y=2","lastmod":"2024-01-02","#,
+                r#""changefreq":"daily","priority":0.8},"#,
+                r#"{"loc":"https://example.com/relative","lastmod":null,"changefreq":null,"priority":null}"#,
+                r#"],"sitemaps":[]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn index() {
+        let index = "<sitemapindex><sitemap><loc>part1.xml.gz</loc><lastmod>2024-03-04T05:06:07Z</lastmod></sitemap>\
+            <sitemap><loc>https://cdn.test/part2.xml</loc></sitemap></sitemapindex>";
+        assert_eq!(
+            parsed(index),
+            concat!(
+                r#"{"urls":[],"sitemaps":["#,
+                r#"{"loc":"https://example.com/sitemaps/part1.xml.gz","lastmod":"2024-03-04T05:06:07Z"},"#,
+                r#"{"loc":"https://cdn.test/part2.xml","lastmod":null}]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            parsed("\u{feff}https://example.com/a\r\n\n  /b  \n"),
+            concat!(
+                r#"{"urls":["#,
+                r#"{"loc":"https://example.com/a","lastmod":null,"changefreq":null,"priority":null},"#,
+                r#"{"loc":"https://example.com/b","lastmod":null,"changefreq":null,"priority":null}"#,
+                r#"],"sitemaps":[]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn not_a_sitemap() {
+        let error = parse(b"<html><body/></html>", "https://example.com/").err().unwrap();
+        assert_eq!(error, "not a sitemap, the root element is <html>");
+    }
+}
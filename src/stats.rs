@@ -0,0 +1,157 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::time::Duration;
+
+/// The labels of a request, like `{"engine": "google"}`.
+pub type Labels = BTreeMap<String, String>;
+
+/// Format labels for log messages, like `{engine=google}`.
+pub fn format_labels(labels: &Labels) -> String {
+    let pairs: Vec<String> = labels.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+    format!("{{{}}}", pairs.join(", "))
+}
+
+/// Upper bounds of the request duration histogram, in seconds.
+const DURATION_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Status classes counted separately: 1xx to 5xx, then transfer errors.
+const STATUS_CLASSES: [&str; 6] = ["1xx", "2xx", "3xx", "4xx", "5xx", "error"];
+
+/// Counters of the transfers sharing the same labels.
+#[derive(Clone, Default)]
+pub struct Counters {
+    pub requests: u64,
+    pub failures: u64,
+    pub total_time: Duration,
+    pub bytes: u64,
+    by_status_class: [u64; STATUS_CLASSES.len()],
+    duration_buckets: [u64; DURATION_BUCKETS.len()],
+}
+
+/// Transfer statistics, broken down by labels.
+#[derive(Default)]
+pub struct Stats {
+    by_labels: HashMap<Labels, Counters>,
+}
+
+impl Stats {
+    pub fn record(&mut self, labels: &Labels, status_code: i64, failed: bool, time: Duration, bytes: u64) {
+        let counters = match self.by_labels.get_mut(labels) {
+            Some(counters) => counters,
+            None => self.by_labels.entry(labels.clone()).or_default(),
+        };
+        counters.requests += 1;
+        if failed {
+            counters.failures += 1;
+        }
+        counters.total_time += time;
+        counters.bytes += bytes;
+        let class = match status_code {
+            100..=599 => (status_code / 100 - 1) as usize,
+            _ => STATUS_CLASSES.len() - 1,
+        };
+        counters.by_status_class[class] += 1;
+        let seconds = time.as_secs_f64();
+        for (bucket, bound) in counters.duration_buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+    }
+
+    /// A snapshot of the counters, sorted by labels.
+    pub fn snapshot(&self) -> Vec<(Labels, Counters)> {
+        let mut snapshot: Vec<_> = self
+            .by_labels
+            .iter()
+            .map(|(labels, counters)| (labels.clone(), counters.clone()))
+            .collect();
+        snapshot.sort_by(|a, b| a.0.cmp(&b.0));
+        snapshot
+    }
+
+    /// Render the counters in the Prometheus text exposition format.
+    ///
+    /// `gauges` are added as they are, like the queue depth.
+    pub fn prometheus(&self, gauges: &[(&str, &str, u64)]) -> String {
+        let snapshot = self.snapshot();
+        let mut out = String::new();
+
+        metric_header(&mut out, "pycurse_requests_total", "counter", "Transfers by status class.");
+        for (labels, counters) in &snapshot {
+            for (class, count) in STATUS_CLASSES.iter().zip(counters.by_status_class) {
+                if count > 0 {
+                    let labels = prometheus_labels(labels, &[("status_class", class)]);
+                    writeln!(out, "pycurse_requests_total{} {}", labels, count).unwrap();
+                }
+            }
+        }
+
+        metric_header(&mut out, "pycurse_failures_total", "counter", "Transfers counted as failures.");
+        for (labels, counters) in &snapshot {
+            writeln!(out, "pycurse_failures_total{} {}", prometheus_labels(labels, &[]), counters.failures).unwrap();
+        }
+
+        metric_header(&mut out, "pycurse_response_bytes_total", "counter", "Bytes of response bodies.");
+        for (labels, counters) in &snapshot {
+            writeln!(out, "pycurse_response_bytes_total{} {}", prometheus_labels(labels, &[]), counters.bytes).unwrap();
+        }
+
+        let name = "pycurse_request_duration_seconds";
+        metric_header(&mut out, name, "histogram", "Duration of the transfers.");
+        for (labels, counters) in &snapshot {
+            for (bound, count) in DURATION_BUCKETS.iter().zip(counters.duration_buckets) {
+                let le = bound.to_string();
+                writeln!(out, "{}_bucket{} {}", name, prometheus_labels(labels, &[("le", &le)]), count).unwrap();
+            }
+            let inf = prometheus_labels(labels, &[("le", "+Inf")]);
+            writeln!(out, "{}_bucket{} {}", name, inf, counters.requests).unwrap();
+            let labels = prometheus_labels(labels, &[]);
+            writeln!(out, "{}_sum{} {}", name, labels, counters.total_time.as_secs_f64()).unwrap();
+            writeln!(out, "{}_count{} {}", name, labels, counters.requests).unwrap();
+        }
+
+        for (name, help, value) in gauges {
+            metric_header(&mut out, name, "gauge", help);
+            writeln!(out, "{} {}", name, value).unwrap();
+        }
+        out
+    }
+}
+
+fn metric_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/// Format labels like `{engine="google",le="0.5"}`.
+///
+/// Label names are sanitized, since request labels are arbitrary strings.
+fn prometheus_labels(labels: &Labels, extra: &[(&str, &str)]) -> String {
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .chain(extra.iter().copied())
+        .map(|(key, value)| format!("{}=\"{}\"", label_name(key), escape_label_value(value)))
+        .collect();
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn label_name(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+
+/// The limits of a whole job: once one is reached the downloader stops
+/// starting transfers, so an unattended crawl cannot run away.
+pub struct StopConditions {
+    max_bytes: Option<u64>,
+    /// The transfers started, retries and redirects included.
+    max_requests: Option<u64>,
+    max_elapsed: Option<Duration>,
+    created: Instant,
+    bytes: u64,
+    requests: u64,
+    /// Why the downloader stopped, once it did.
+    reason: Option<String>,
+}
+
+impl StopConditions {
+    pub fn new(max_bytes: Option<u64>, max_requests: Option<u64>, max_elapsed: Option<Duration>) -> Self {
+        StopConditions {
+            max_bytes,
+            max_requests,
+            max_elapsed,
+            created: Instant::now(),
+            bytes: 0,
+            requests: 0,
+            reason: None,
+        }
+    }
+
+    /// Count a transfer starting.
+    pub fn start(&mut self) {
+        self.requests += 1;
+    }
+
+    /// Count the bytes downloaded by a finished transfer.
+    pub fn record(&mut self, bytes: u64) {
+        self.bytes += bytes;
+    }
+
+    /// Why the downloader stopped, None while it runs.
+    pub fn reason(&mut self) -> Option<&str> {
+        if self.reason.is_none() {
+            let elapsed = self.created.elapsed();
+            self.reason = if self.max_requests.is_some_and(|max| self.requests >= max) {
+                Some(format!("max_requests reached, {} requests started", self.requests))
+            } else if self.max_bytes.is_some_and(|max| self.bytes >= max) {
+                Some(format!("max_total_bytes reached, {} bytes downloaded", self.bytes))
+            } else if self.max_elapsed.is_some_and(|max| elapsed >= max) {
+                Some(format!(
+                    "max_elapsed reached, {:.0} seconds elapsed",
+                    elapsed.as_secs_f64()
+                ))
+            } else {
+                None
+            };
+            if let Some(reason) = &self.reason {
+                log::warn!("the downloader stops starting transfers: {}", reason);
+            }
+        }
+        self.reason.as_deref()
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The body of a streamed response, passed in chunks from the downloader
+/// thread to Python.
+///
+/// At most `max_chunks` chunks wait to be read: past that, the transfer is
+/// paused until the reader catches up, so a slow reader holds back the
+/// server rather than filling the memory.
+pub struct Stream {
+    /// The token of the transfer, to resume it.
+    pub token: usize,
+    state: Mutex<State>,
+    readable: Condvar,
+    max_chunks: usize,
+}
+
+#[derive(Default)]
+struct State {
+    chunks: VecDeque<Vec<u8>>,
+    /// Set once the transfer completed, with its error if it failed.
+    finished: Option<Option<String>>,
+    /// The transfer waits for the reader to make room.
+    paused: bool,
+    /// The reader is gone, the transfer is aborted.
+    closed: bool,
+}
+
+/// What happened to a chunk given to `Stream::push`.
+pub enum Push {
+    Queued,
+    /// The buffer is full: the transfer must pause, and give the chunk
+    /// again once resumed.
+    Full,
+    Closed,
+}
+
+/// What `Stream::next` returned.
+pub enum Next {
+    Chunk {
+        data: Vec<u8>,
+        /// The transfer was paused and can be resumed.
+        resume: bool,
+    },
+    /// The body is complete, or the transfer failed with this error.
+    End(Option<String>),
+    Timeout,
+}
+
+impl Stream {
+    pub fn new(token: usize, max_chunks: usize) -> Self {
+        Stream {
+            token,
+            state: Mutex::new(State::default()),
+            readable: Condvar::new(),
+            max_chunks,
+        }
+    }
+
+    /// Called by the downloader thread for each chunk of the body.
+    pub fn push(&self, data: &[u8]) -> Push {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Push::Closed;
+        }
+        if state.chunks.len() >= self.max_chunks {
+            state.paused = true;
+            return Push::Full;
+        }
+        state.chunks.push_back(data.to_vec());
+        self.readable.notify_all();
+        Push::Queued
+    }
+
+    /// Called by the downloader thread once the transfer completed.
+    pub fn finish(&self, error: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        state.finished = Some(error);
+        self.readable.notify_all();
+    }
+
+    /// Wait at most `timeout` for the next chunk.
+    pub fn next(&self, timeout: Duration) -> Next {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(data) = state.chunks.pop_front() {
+                let resume = state.paused && state.chunks.len() < self.max_chunks;
+                if resume {
+                    state.paused = false;
+                }
+                return Next::Chunk { data, resume };
+            }
+            if let Some(error) = &state.finished {
+                return Next::End(error.clone());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Next::Timeout;
+            }
+            state = self.readable.wait_timeout(state, remaining).unwrap().0;
+        }
+    }
+
+    /// Stop reading: the chunks are dropped and the transfer is aborted.
+    ///
+    /// Returns whether the transfer is paused, and must be resumed to
+    /// notice.
+    pub fn close(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.chunks.clear();
+        std::mem::take(&mut state.paused)
+    }
+}
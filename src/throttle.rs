@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The wait between the starts of the transfers to a host after its first
+/// slow down, and the longest one.
+const MIN_DELAY: Duration = Duration::from_millis(100);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Slows down the transfers to the hosts answering 429 Too Many Requests or
+/// 503 Service Unavailable, like TCP congestion control: each slow down
+/// halves the transfers running to the host and doubles the wait between
+/// their starts, and each success brings them back a little, an additional
+/// transfer once as many succeeded as are allowed.
+pub struct HostThrottle {
+    /// The transfers allowed to a host before any slow down.
+    max_transfers: usize,
+    hosts: HashMap<String, HostState>,
+}
+
+struct HostState {
+    /// The transfers allowed, a fraction counting the successes towards the
+    /// next one.
+    transfers: f64,
+    delay: Duration,
+    next_start: Instant,
+    /// The last slow down: the responses of the transfers started before
+    /// do not slow down again, they were sent at the former rate.
+    slowed: Option<Instant>,
+}
+
+impl HostThrottle {
+    pub fn new(max_transfers: usize) -> Self {
+        HostThrottle {
+            max_transfers,
+            hosts: HashMap::new(),
+        }
+    }
+
+    /// Whether a transfer to `host` may start now, with `running` ones
+    /// already; its start is then counted.
+    pub fn try_start(&mut self, host: &str, running: usize) -> bool {
+        let Some(state) = self.hosts.get_mut(host) else {
+            return true;
+        };
+        let now = Instant::now();
+        if running >= state.transfers as usize || now < state.next_start {
+            return false;
+        }
+        state.next_start = now + state.delay;
+        true
+    }
+
+    /// When the next transfer to `host` may start, None if it is now.
+    pub fn next_start(&self, host: &str) -> Option<Instant> {
+        self.hosts.get(host).map(|state| state.next_start).filter(|start| *start > Instant::now())
+    }
+
+    /// Count the response of a transfer to `host` started at `started`.
+    pub fn record(&mut self, host: &str, status_code: i64, started: Option<Instant>) {
+        let now = Instant::now();
+        let max_transfers = self.max_transfers as f64;
+        if matches!(status_code, 429 | 503) {
+            let state = self.hosts.entry(host.to_owned()).or_insert_with(|| HostState {
+                transfers: max_transfers,
+                delay: Duration::ZERO,
+                next_start: now,
+                slowed: None,
+            });
+            if started.zip(state.slowed).is_some_and(|(started, slowed)| started < slowed) {
+                return;
+            }
+            state.transfers = (state.transfers / 2.0).max(1.0);
+            state.delay = (state.delay * 2).clamp(MIN_DELAY, MAX_DELAY);
+            state.next_start = state.next_start.max(now + state.delay);
+            state.slowed = Some(now);
+            log::debug!(
+                "slowing down {}: {} transfers, {:?} between starts",
+                host,
+                state.transfers as usize,
+                state.delay
+            );
+        } else if (200..500).contains(&status_code) {
+            let Some(state) = self.hosts.get_mut(host) else {
+                return;
+            };
+            state.transfers = (state.transfers + 1.0 / state.transfers).min(max_transfers);
+            state.delay -= state.delay / 10;
+            if state.delay < MIN_DELAY / 10 {
+                state.delay = Duration::ZERO;
+            }
+            if state.delay.is_zero() && state.transfers >= max_transfers {
+                self.hosts.remove(host);
+            }
+        }
+    }
+}
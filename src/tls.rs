@@ -0,0 +1,156 @@
+use std::ffi::c_long;
+
+use curl::easy::{Easy2, Handler, SslVersion};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::curl_ext;
+
+/// TLS settings of a connection, set on the downloader and overridden per
+/// request.
+#[derive(Clone, Default)]
+pub struct TlsOptions {
+    min_version: Option<TlsVersion>,
+    max_version: Option<TlsVersion>,
+    /// The OpenSSL cipher list for TLS 1.2 and below.
+    ciphers: Option<String>,
+    /// The TLS 1.3 cipher suites.
+    tls13_ciphers: Option<String>,
+    /// Whether to negotiate the HTTP version with ALPN, on by default.
+    alpn: Option<bool>,
+    /// The settings of the downloader only, kept by `merge()`: whether
+    /// TLS sessions are resumed, and the OpenSSL engine used for the
+    /// cryptographic operations, with the keys it holds.
+    no_session_cache: bool,
+    engine: Option<String>,
+}
+
+impl TlsOptions {
+    pub fn new(
+        min_version: Option<&str>,
+        max_version: Option<&str>,
+        ciphers: Option<String>,
+        tls13_ciphers: Option<String>,
+        alpn: Option<bool>,
+    ) -> PyResult<Self> {
+        let options = TlsOptions {
+            min_version: min_version.map(TlsVersion::parse).transpose()?,
+            max_version: max_version.map(TlsVersion::parse).transpose()?,
+            ciphers,
+            tls13_ciphers,
+            alpn,
+            ..TlsOptions::default()
+        };
+        options.check()?;
+        Ok(options)
+    }
+
+    /// Set the settings of the downloader only, the engine checked to be
+    /// available to libcurl.
+    pub fn with_downloader_settings(mut self, session_cache: bool, engine: Option<String>) -> PyResult<Self> {
+        if let Some(engine) = &engine {
+            crate::options::check_text("ssl_engine", engine)?;
+            curl::easy::Easy::new().ssl_engine(engine).map_err(|error| {
+                PyValueError::new_err(format!("ssl_engine {:?} is not available: {}", engine, error))
+            })?;
+        }
+        self.no_session_cache = !session_cache;
+        self.engine = engine;
+        Ok(self)
+    }
+
+    /// These options, with the ones set in `other` replacing them.
+    pub fn merge(&self, other: TlsOptions) -> PyResult<Self> {
+        let options = TlsOptions {
+            min_version: other.min_version.or(self.min_version),
+            max_version: other.max_version.or(self.max_version),
+            ciphers: other.ciphers.or_else(|| self.ciphers.clone()),
+            tls13_ciphers: other.tls13_ciphers.or_else(|| self.tls13_ciphers.clone()),
+            alpn: other.alpn.or(self.alpn),
+            no_session_cache: self.no_session_cache,
+            engine: self.engine.clone(),
+        };
+        options.check()?;
+        Ok(options)
+    }
+
+    fn check(&self) -> PyResult<()> {
+        if let (Some(min), Some(max)) = (self.min_version, self.max_version) {
+            if min > max {
+                return Err(PyValueError::new_err("tls_min_version is above tls_max_version"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply<H: Handler>(&self, easy: &mut Easy2<H>) -> Result<(), curl::Error> {
+        if self.min_version.is_some() || self.max_version.is_some() {
+            // The default maximum is the highest version supported.
+            let version = |version: Option<TlsVersion>| version.map_or(SslVersion::Default, TlsVersion::to_curl);
+            easy.ssl_min_max_version(version(self.min_version), version(self.max_version))?;
+        }
+        if let Some(ciphers) = &self.ciphers {
+            easy.ssl_cipher_list(ciphers)?;
+        }
+        if let Some(ciphers) = &self.tls13_ciphers {
+            curl_ext::setopt_str(easy, curl_ext::TLS13_CIPHERS, ciphers)?;
+        }
+        if let Some(alpn) = self.alpn {
+            curl_ext::setopt_long(easy, curl_ext::SSL_ENABLE_ALPN, alpn as c_long)?;
+        }
+        if self.no_session_cache {
+            easy.ssl_sessionid_cache(false)?;
+        }
+        if let Some(engine) = &self.engine {
+            easy.ssl_engine(engine)?;
+            easy.ssl_engine_default(true)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the options to the TLS connection to an HTTPS proxy.
+    ///
+    /// Only the ciphers are used there, ALPN is not used with proxies.
+    pub fn apply_to_proxy<H: Handler>(&self, easy: &mut Easy2<H>) -> Result<(), curl::Error> {
+        if let Some(ciphers) = &self.ciphers {
+            easy.proxy_ssl_cipher_list(ciphers)?;
+        }
+        if let Some(ciphers) = &self.tls13_ciphers {
+            curl_ext::setopt_str(easy, curl_ext::PROXY_TLS13_CIPHERS, ciphers)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    V1_0,
+    V1_1,
+    V1_2,
+    V1_3,
+}
+
+impl TlsVersion {
+    /// Parse `1.0`, `1.1`, `1.2` or `1.3`.
+    fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "1.0" => Ok(TlsVersion::V1_0),
+            "1.1" => Ok(TlsVersion::V1_1),
+            "1.2" => Ok(TlsVersion::V1_2),
+            "1.3" => Ok(TlsVersion::V1_3),
+            _ => Err(PyValueError::new_err(format!(
+                "unknown TLS version: {}, expected 1.0, 1.1, 1.2 or 1.3",
+                name
+            ))),
+        }
+    }
+
+    fn to_curl(self) -> SslVersion {
+        match self {
+            TlsVersion::V1_0 => SslVersion::Tlsv10,
+            TlsVersion::V1_1 => SslVersion::Tlsv11,
+            TlsVersion::V1_2 => SslVersion::Tlsv12,
+            TlsVersion::V1_3 => SslVersion::Tlsv13,
+        }
+    }
+}
@@ -0,0 +1,555 @@
+//! The TOML a configuration file needs: a table of keys with string,
+//! integer, float, boolean and array values, in a document which may hold
+//! other tables, like `pyproject.toml`.
+
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple};
+use std::fmt::Write;
+
+use crate::json::Value;
+
+/// Serialize a dict of settings to TOML, one `key = value` line each; the
+/// `None` values, which TOML cannot represent, are left out.
+pub fn dumps(config: &Bound<'_, PyDict>) -> PyResult<String> {
+    let mut out = String::new();
+    for (key, value) in config.iter() {
+        if value.is_none() {
+            continue;
+        }
+        write_key(&key.extract::<String>()?, &mut out);
+        out.push_str(" = ");
+        write_value(&value, &mut out)?;
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+fn write_key(key: &str, out: &mut String) {
+    if !key.is_empty() && key.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-') {
+        out.push_str(key);
+    } else {
+        write_string(key, out);
+    }
+}
+
+fn write_value(value: &Bound<'_, PyAny>, out: &mut String) -> PyResult<()> {
+    if let Ok(value) = value.downcast::<PyBool>() {
+        out.push_str(if value.is_true() { "true" } else { "false" });
+    } else if value.is_instance_of::<PyLong>() {
+        write!(out, "{}", value.extract::<i64>()?).unwrap();
+    } else if value.is_instance_of::<PyFloat>() {
+        match value.extract::<f64>()? {
+            value if value.is_nan() => out.push_str("nan"),
+            value if value.is_infinite() => out.push_str(if value > 0.0 { "inf" } else { "-inf" }),
+            value => write!(out, "{:?}", value).unwrap(),
+        }
+    } else if let Ok(value) = value.downcast::<PyString>() {
+        write_string(value.to_str()?, out);
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        out.push('[');
+        for (i, item) in value.iter()?.enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            write_value(&item?, out)?;
+        }
+        out.push(']');
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "cannot write a {} to TOML",
+            value.get_type().name()?
+        )));
+    }
+    Ok(())
+}
+
+fn write_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Parse the keys of `table` of a TOML document, or its top-level keys,
+/// into an object. The other tables are skipped. Dotted keys are kept as
+/// written, like `a.b`, and dates and times as strings; arrays of tables
+/// are skipped.
+pub fn parse(text: &str, table: Option<&str>) -> Result<Value, String> {
+    let mut parser = Parser {
+        text,
+        data: text.as_bytes(),
+        pos: 0,
+        line: 1,
+    };
+    let mut members: Vec<(String, Value)> = Vec::new();
+    // Whether the keys are the ones of `table`.
+    let mut selected = table.is_none();
+    let mut found = selected;
+    loop {
+        parser.skip_blank(true);
+        match parser.data.get(parser.pos) {
+            None => break,
+            Some(b'[') => {
+                let array = parser.eat(b"[[");
+                if !array {
+                    parser.pos += 1;
+                }
+                parser.skip_blank(false);
+                let name = parser.key()?;
+                if !parser.eat(if array { b"]]" } else { b"]" }) {
+                    return Err(parser.error("expecting ']' after the table name"));
+                }
+                selected = !array && table == Some(name.as_str());
+                found |= selected;
+            }
+            Some(_) => {
+                let key = parser.key()?;
+                if !parser.eat(b"=") {
+                    return Err(parser.error("expecting '=' after the key"));
+                }
+                parser.skip_blank(false);
+                let value = parser.value()?;
+                if selected {
+                    if members.iter().any(|(name, _)| *name == key) {
+                        return Err(parser.error(&format!("duplicate key {:?}", key)));
+                    }
+                    members.push((key, value));
+                }
+            }
+        }
+        parser.skip_blank(false);
+        if !matches!(parser.data.get(parser.pos), None | Some(b'\n' | b'\r')) {
+            return Err(parser.error("expecting a new line"));
+        }
+    }
+    match found {
+        true => Ok(Value::Object(members)),
+        false => Err(format!("no [{}] table", table.unwrap_or_default())),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    data: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} on line {}", message, self.line)
+    }
+
+    /// Skip the spaces and the comment up to the end of the line, and with
+    /// `lines` the next blank lines too.
+    fn skip_blank(&mut self, lines: bool) {
+        while let Some(&byte) = self.data.get(self.pos) {
+            match byte {
+                b' ' | b'\t' => self.pos += 1,
+                b'#' => {
+                    while !matches!(self.data.get(self.pos), None | Some(b'\n' | b'\r')) {
+                        self.pos += 1;
+                    }
+                }
+                b'\r' | b'\n' if lines => {
+                    self.line += (byte == b'\n') as usize;
+                    self.pos += 1;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn eat(&mut self, literal: &[u8]) -> bool {
+        let matched = self.data[self.pos..].starts_with(literal);
+        if matched {
+            self.pos += literal.len();
+        }
+        matched
+    }
+
+    /// A key, its dotted parts joined by dots and the spaces around them
+    /// dropped.
+    fn key(&mut self) -> Result<String, String> {
+        let mut key = self.simple_key()?;
+        self.skip_blank(false);
+        while self.eat(b".") {
+            self.skip_blank(false);
+            key = format!("{}.{}", key, self.simple_key()?);
+            self.skip_blank(false);
+        }
+        Ok(key)
+    }
+
+    fn simple_key(&mut self) -> Result<String, String> {
+        match self.data.get(self.pos) {
+            Some(&quote @ (b'"' | b'\'')) => self.string(quote),
+            _ => {
+                let start = self.pos;
+                while self
+                    .data
+                    .get(self.pos)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || b"_-".contains(c))
+                {
+                    self.pos += 1;
+                }
+                match self.pos > start {
+                    true => Ok(self.text[start..self.pos].to_owned()),
+                    false => Err(self.error("expecting a key")),
+                }
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        if self.eat(b"true") {
+            return Ok(Value::Bool(true));
+        }
+        if self.eat(b"false") {
+            return Ok(Value::Bool(false));
+        }
+        match self.data.get(self.pos) {
+            Some(&quote @ (b'"' | b'\'')) => self.string(quote).map(Value::String),
+            Some(b'[') => self.array(),
+            Some(b'{') => self.inline_table(),
+            Some(_) => self.scalar(),
+            None => Err(self.error("expecting a value")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank(true);
+            if self.eat(b"]") {
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank(true);
+            if self.eat(b"]") {
+                return Ok(Value::Array(items));
+            }
+            if !self.eat(b",") {
+                return Err(self.error("expecting ',' between array items"));
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_blank(false);
+        if self.eat(b"}") {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_blank(false);
+            let key = self.key()?;
+            if !self.eat(b"=") {
+                return Err(self.error("expecting '=' after the key"));
+            }
+            self.skip_blank(false);
+            members.push((key, self.value()?));
+            self.skip_blank(false);
+            if self.eat(b"}") {
+                return Ok(Value::Object(members));
+            }
+            if !self.eat(b",") {
+                return Err(self.error("expecting ',' between inline table members"));
+            }
+        }
+    }
+
+    /// A number, or a date or time, kept as written.
+    fn scalar(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .data
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || b"+-._:".contains(c))
+        {
+            self.pos += 1;
+            // The space between a date and its time, like `1979-05-27 07:32:00`.
+            let time_follows = self.data.get(self.pos..self.pos + 4).is_some_and(|next| {
+                next[0] == b' ' && next[1].is_ascii_digit() && next[2].is_ascii_digit() && next[3] == b':'
+            });
+            if time_follows && self.data[start..self.pos].contains(&b'-') {
+                self.pos += 1;
+            }
+        }
+        let written = &self.text[start..self.pos];
+        let is_date = written.len() >= 8 && written.as_bytes()[..2].iter().all(u8::is_ascii_digit);
+        if is_date && (written.as_bytes()[2] == b':' || written.as_bytes().get(4) == Some(&b'-')) {
+            return Ok(Value::String(written.to_owned()));
+        }
+        let text = written.replace('_', "");
+        let (sign, unsigned) = match text.strip_prefix('-') {
+            Some(unsigned) => (-1, unsigned),
+            None => (1, text.strip_prefix('+').unwrap_or(&text)),
+        };
+        if let Ok(value) = text.parse::<i64>() {
+            return Ok(Value::Int(value));
+        }
+        for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+            if let Some(value) = text
+                .strip_prefix(prefix)
+                .and_then(|digits| i64::from_str_radix(digits, radix).ok())
+            {
+                return Ok(Value::Int(value));
+            }
+        }
+        match unsigned {
+            "inf" => return Ok(Value::Float(sign as f64 * f64::INFINITY)),
+            "nan" => return Ok(Value::Float(f64::NAN)),
+            // Digits are required around the dot, unlike in Rust.
+            _ if unsigned.starts_with('.') || unsigned.contains(".e") || unsigned.contains(".E") => {}
+            _ if unsigned.starts_with(|c: char| c.is_ascii_digit()) && !unsigned.ends_with('.') => {
+                if let Ok(value) = text.parse::<f64>() {
+                    return Ok(Value::Float(value));
+                }
+            }
+            _ => {}
+        }
+        self.pos = start;
+        Err(self.error("invalid value"))
+    }
+
+    /// A string between `quote`s, basic with `"` or literal with `'`, on
+    /// one line or, between three quotes, on several.
+    fn string(&mut self, quote: u8) -> Result<String, String> {
+        let delimiter = [quote; 3];
+        let multiline = self.data[self.pos..].starts_with(&delimiter);
+        let delimiter = if multiline { &delimiter[..] } else { &delimiter[..1] };
+        self.pos += delimiter.len();
+        // A line break right after the opening quotes is not part of it.
+        if multiline && !self.eat(b"\n") {
+            self.eat(b"\r\n");
+        }
+        let mut out = String::new();
+        loop {
+            if self.data[self.pos..].starts_with(delimiter) {
+                // Up to two quotes before the closing ones are content.
+                let mut quotes = 0;
+                while multiline && quotes < 2 && self.data[self.pos + 1..].starts_with(delimiter) {
+                    out.push(quote as char);
+                    self.pos += 1;
+                    quotes += 1;
+                }
+                self.pos += delimiter.len();
+                return Ok(out);
+            }
+            let Some(c) = self.next_char() else {
+                return Err(self.error("unterminated string"));
+            };
+            match c {
+                '\\' if quote == b'"' => match self.next_char() {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('u') => out.push(self.unicode_escape(4)?),
+                    Some('U') => out.push(self.unicode_escape(8)?),
+                    // A line ending backslash trims the line break and the
+                    // spaces after it.
+                    Some(' ' | '\t' | '\r' | '\n') if multiline => {
+                        self.pos -= 1;
+                        while let Some(&byte @ (b' ' | b'\t' | b'\r' | b'\n')) = self.data.get(self.pos) {
+                            self.line += (byte == b'\n') as usize;
+                            self.pos += 1;
+                        }
+                    }
+                    _ => return Err(self.error("invalid escape")),
+                },
+                '\n' | '\r' if !multiline => return Err(self.error("unterminated string")),
+                c => {
+                    self.line += (c == '\n') as usize;
+                    out.push(c);
+                }
+            }
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        // Only whole characters are skipped, a character starts at `pos`.
+        let c = self.text[self.pos..].chars().next()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn unicode_escape(&mut self, digits: usize) -> Result<char, String> {
+        let code = self
+            .data
+            .get(self.pos..self.pos + digits)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .filter(|hex| hex.bytes().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += digits;
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The TOML of the Python dict `expression`.
+    fn dumps_of(expression: &str) -> PyResult<String> {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| dumps(py.eval_bound(expression, None, None)?.downcast()?))
+    }
+
+    /// `text` parsed, as JSON.
+    fn parsed(text: &str, table: Option<&str>) -> Result<String, String> {
+        let mut out = String::new();
+        parse(text, table)?.write(&mut out);
+        Ok(out)
+    }
+
+    /// The dict `expression` written and parsed again equals it.
+    fn assert_round_trip(expression: &str) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let config = py.eval_bound(expression, None, None).unwrap();
+            let text = dumps(config.downcast().unwrap()).unwrap();
+            let parsed = parse(&text, None).unwrap().to_python(py).unwrap();
+            assert!(parsed.bind(py).eq(&config).unwrap(), "{expression} became {text}");
+        });
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_round_trip("{'a': 1, 'b': -2, 'c': 2**63 - 1, 'd': -2**63, 'e': True, 'f': False}");
+        assert_round_trip("{'list': [1, 'two', [3.5, []]], 'empty': []}");
+        assert_eq!(dumps_of("{'tuple': ('a', (1,))}").unwrap(), "tuple = [\"a\", [1]]\n");
+        assert_round_trip("{'pairs': [['example.com', 'mirror.example.org:8080'], ['*.test', 'localhost']]}");
+    }
+
+    #[test]
+    fn round_trip_strings() {
+        assert_round_trip(r#"{'quotes': 'say "hi" it\'s', 'backslashes': 'C:\\dir\\', 'empty': ''}"#);
+        assert_round_trip(r"{'escapes': 'a\nb\rc\td\x00e\x1f\x7f\x85', 'unicode': 'café \u20ac \U0001f600'}");
+        assert_round_trip(r"{'hash': '# not a comment', 'multiline': '\'\'\'\n\'\'\''}");
+        assert_eq!(dumps_of(r#"{'a': 'say "hi"\\\n\x7f'}"#).unwrap(), "a = \"say \\\"hi\\\"\\\\\\n\\u007f\"\n");
+    }
+
+    #[test]
+    fn round_trip_keys() {
+        assert_round_trip(r#"{'a-b_c9': 1, 'a.b': 2, 'with space': 3, '': 4, 'quote"': 5, 'é': 6, '[x]': 7}"#);
+        assert_eq!(dumps_of("{'a.b': 1, 'c_d': 2, '': 3}").unwrap(), "\"a.b\" = 1\nc_d = 2\n\"\" = 3\n");
+    }
+
+    #[test]
+    fn round_trip_floats() {
+        assert_round_trip("{'a': 0.1, 'b': 1.0, 'c': -2.5, 'd': 1e300, 'e': 5e-324, 'f': 123456789.125, 'g': 1e-7}");
+        assert_round_trip("{'inf': float('inf'), 'ninf': float('-inf'), 'zero': 0.0, 'nzero': -0.0}");
+        assert_eq!(dumps_of("{'a': 1.0, 'b': 1e300, 'c': float('-inf')}").unwrap(), "a = 1.0\nb = 1e300\nc = -inf\n");
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let text = dumps_of("{'nan': float('nan'), 'nzero': -0.0}").unwrap();
+            assert_eq!(text, "nan = nan\nnzero = -0.0\n");
+            let parsed = parse(&text, None).unwrap().to_python(py).unwrap();
+            let parsed = parsed.downcast_bound::<PyDict>(py).unwrap();
+            let value = |key: &str| parsed.get_item(key).unwrap().unwrap().extract::<f64>().unwrap();
+            assert!(value("nan").is_nan());
+            assert!(value("nzero").is_sign_negative());
+        });
+    }
+
+    #[test]
+    fn dumps_skips_none() {
+        assert_eq!(dumps_of("{'a': None, 'b': 1}").unwrap(), "b = 1\n");
+    }
+
+    #[test]
+    fn dumps_errors() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let message = |expression: &str| dumps_of(expression).unwrap_err().value_bound(py).to_string();
+            assert_eq!(message("{'a': {'b': 1}}"), "cannot write a dict to TOML");
+            assert_eq!(message("{'a': [b'']}"), "cannot write a bytes to TOML");
+        });
+    }
+
+    #[test]
+    fn tables() {
+        let document = r#"
+top = 1  # the top-level keys
+[tool.pycurse]
+max_retries = 3
+"quoted key" = 'literal\n'
+dotted . key = "x"
+inline = { a = 1, b.c = [2, 3] }
+
+[tool.pycurse.nested]
+max_retries = 4
+
+[[tool.pycurse]]
+max_retries = 5
+
+[ tool . "pycurse" ]
+"#;
+        assert_eq!(parsed(document, None).unwrap(), r#"{"top":1}"#);
+        assert_eq!(
+            parsed(document, Some("tool.pycurse")).unwrap(),
+            r#"{"max_retries":3,"quoted key":"literal\\n","dotted.key":"x","inline":{"a":1,"b.c":[2,3]}}"#
+        );
+        assert_eq!(parsed(document, Some("tool.pycurse.nested")).unwrap(), r#"{"max_retries":4}"#);
+        assert_eq!(parsed(document, Some("tool")).unwrap_err(), "no [tool] table");
+        assert_eq!(parsed("[a]\nx = 1\n[b]\n[a]\nx = 2\n", Some("a")).unwrap_err(), "duplicate key \"x\" on line 5");
+    }
+
+    #[test]
+    fn values() {
+        let document = "\
+ints = [+1, -0, 1_000, 0xff, 0o17, 0b101]
+floats = [1.5, -1e3, 6.25E-1, +inf, -nan, 1_0.5]
+dates = [1979-05-27, 1979-05-27T07:32:00Z, 1979-05-27 07:32:00, 07:32:00]
+strings = ['''
+no 'escape' \\ here''', \"\"\"
+a \\
+    b \"\"quoted\"\" \\u00e9\\U0001F600\"\"\"]
+";
+        assert_eq!(
+            parsed(document, None).unwrap(),
+            concat!(
+                r#"{"ints":[1,0,1000,255,15,5],"floats":[1.5,-1000.0,0.625,null,null,10.5],"#,
+                r#""dates":["1979-05-27","1979-05-27T07:32:00Z","1979-05-27 07:32:00","07:32:00"],"#,
+                "\"strings\":[\"no 'escape' \\\\ here\",\"a b \\\"\\\"quoted\\\"\\\" \u{e9}\u{1f600}\"]}"
+            )
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text: &str| parsed(text, None).unwrap_err();
+        assert_eq!(error("a = "), "expecting a value on line 1");
+        assert_eq!(error("\na = .5"), "invalid value on line 2");
+        assert_eq!(error("a = 1."), "invalid value on line 1");
+        assert_eq!(error("a = 1 b = 2"), "expecting a new line on line 1");
+        assert_eq!(error("a 1"), "expecting '=' after the key on line 1");
+        assert_eq!(error("= 1"), "expecting a key on line 1");
+        assert_eq!(error("a = \"b"), "unterminated string on line 1");
+        assert_eq!(error("a = \"b\nc\""), "unterminated string on line 1");
+        assert_eq!(error("a = \"\\q\""), "invalid escape on line 1");
+        assert_eq!(error("a = \"\\ud800\""), "invalid unicode escape on line 1");
+        assert_eq!(error("a = [1 2]"), "expecting ',' between array items on line 1");
+        assert_eq!(error("a = 1\na = 2"), "duplicate key \"a\" on line 2");
+        assert_eq!(error("[a"), "expecting ']' after the table name on line 1");
+    }
+}
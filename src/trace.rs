@@ -0,0 +1,128 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::stats::Labels;
+
+/// A point in time, both monotonic and wall clock.
+#[derive(Clone, Copy)]
+pub struct Timestamp {
+    pub instant: Instant,
+    pub wall: SystemTime,
+}
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Timestamp {
+            instant: Instant::now(),
+            wall: SystemTime::now(),
+        }
+    }
+
+    /// Nanoseconds since the epoch, as used by OpenTelemetry.
+    pub fn unix_nanos(&self) -> u128 {
+        self.wall.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+    }
+
+    pub fn since(&self, earlier: &Timestamp) -> Duration {
+        self.instant.saturating_duration_since(earlier.instant)
+    }
+}
+
+/// The W3C trace context of a request span.
+#[derive(Clone, Copy)]
+pub struct SpanContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub parent_span_id: Option<u64>,
+    pub sampled: bool,
+}
+
+impl SpanContext {
+    /// A new span, child of the `traceparent` header value if there is one.
+    pub fn new(traceparent: Option<&str>) -> Result<Self, String> {
+        let span_id = random_u64();
+        match traceparent {
+            Some(value) => {
+                let (trace_id, parent_span_id, sampled) = parse_traceparent(value)
+                    .ok_or_else(|| format!("invalid traceparent: {}", value))?;
+                Ok(SpanContext {
+                    trace_id,
+                    span_id,
+                    parent_span_id: Some(parent_span_id),
+                    sampled,
+                })
+            }
+            None => Ok(SpanContext {
+                trace_id: (u128::from(random_u64()) << 64) | u128::from(random_u64()),
+                span_id,
+                parent_span_id: None,
+                sampled: true,
+            }),
+        }
+    }
+
+    /// A new span with the same parent, for another request of the same
+    /// operation.
+    pub fn sibling(&self) -> Self {
+        SpanContext {
+            span_id: random_u64(),
+            ..*self
+        }
+    }
+
+    /// The `traceparent` header propagating this span.
+    pub fn traceparent(&self) -> String {
+        format!(
+            "traceparent: 00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.sampled as u8
+        )
+    }
+}
+
+/// The phases of a transfer, as measured by curl from its start.
+#[derive(Clone, Copy, Default)]
+pub struct Timings {
+    pub namelookup: Duration,
+    pub connect: Duration,
+    pub appconnect: Duration,
+    pub starttransfer: Duration,
+    pub total: Duration,
+}
+
+/// A finished request span.
+pub struct Span {
+    pub context: SpanContext,
+    pub queued: Timestamp,
+    pub started: Timestamp,
+    pub finished: Timestamp,
+    pub timings: Timings,
+    pub attempts: u32,
+    pub labels: Labels,
+}
+
+/// Parse `00-<trace-id>-<parent-id>-<flags>`.
+fn parse_traceparent(value: &str) -> Option<(u128, u64, bool)> {
+    let mut parts = value.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let parent_id = parts.next()?;
+    let flags = parts.next()?;
+    if version.len() != 2 || version == "ff" || trace_id.len() != 32 || parent_id.len() != 16 || flags.len() != 2 {
+        return None;
+    }
+    let trace_id = u128::from_str_radix(trace_id, 16).ok().filter(|id| *id != 0)?;
+    let parent_id = u64::from_str_radix(parent_id, 16).ok().filter(|id| *id != 0)?;
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    Some((trace_id, parent_id, flags & 1 == 1))
+}
+
+pub fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(Timestamp::now().unix_nanos());
+    // Zero is not a valid id.
+    hasher.finish().max(1)
+}
@@ -0,0 +1,293 @@
+/// Nesting deeper than this is not a sitemap or a feed.
+const MAX_DEPTH: usize = 256;
+
+/// An element of a parsed XML document, for the formats read out of
+/// responses, like sitemaps and feeds.
+///
+/// Names are local, without their namespace prefix: `<atom:link>` is
+/// `link`.
+#[derive(Default)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// The text directly in the element, CDATA sections included.
+    pub text: String,
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    /// The trimmed text of the child `name`, None when missing or empty.
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.trim()).filter(|text| !text.is_empty())
+    }
+
+    /// The elements named `name` at any depth, in document order, not
+    /// searched into.
+    pub fn descendants<'a>(&'a self, name: &str, found: &mut Vec<&'a Element>) {
+        for child in &self.children {
+            if child.name == name {
+                found.push(child);
+            } else {
+                child.descendants(name, found);
+            }
+        }
+    }
+}
+
+/// Parse the root element of a document, leniently: unclosed elements are
+/// closed at the end, stray end tags ignored. The document is decoded as
+/// UTF-8, whatever its declaration says.
+pub fn parse(data: &[u8]) -> Result<Element, String> {
+    let text = String::from_utf8_lossy(data);
+    let mut rest: &str = &text;
+    // The elements open, the root first.
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+    while root.is_none() && !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            push_text(&mut stack, rest);
+            break;
+        };
+        push_text(&mut stack, &rest[..start]);
+        rest = &rest[start..];
+        if let Some(section) = rest.strip_prefix("<![CDATA[") {
+            let end = section.find("]]>").unwrap_or(section.len());
+            if let Some(element) = stack.last_mut() {
+                element.text.push_str(&section[..end]);
+            }
+            rest = section.get(end + 3..).unwrap_or_default();
+        } else if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+        } else if rest.starts_with("<?") {
+            rest = rest.find("?>").map_or("", |end| &rest[end + 2..]);
+        } else if rest.starts_with("<!") {
+            // A doctype, with its internal subset.
+            let end = match (rest.find('['), rest.find('>')) {
+                (Some(open), Some(close)) if open < close => rest.find("]>").map(|end| end + 1),
+                (_, close) => close,
+            };
+            rest = end.map_or("", |end| &rest[end + 1..]);
+        } else if let Some(tag) = rest.strip_prefix("</") {
+            let end = tag.find('>').unwrap_or(tag.len());
+            let name = local_name(tag[..end].trim());
+            rest = tag.get(end + 1..).unwrap_or_default();
+            if let Some(open) = stack.iter().rposition(|element| element.name == name) {
+                while stack.len() > open {
+                    close(&mut stack, &mut root);
+                }
+            }
+        } else {
+            let (element, length, empty) = start_tag(&rest[1..]);
+            rest = &rest[1 + length..];
+            let Some(element) = element else {
+                push_text(&mut stack, "<");
+                continue;
+            };
+            if stack.len() >= MAX_DEPTH {
+                return Err("XML document is too deeply nested".to_owned());
+            }
+            stack.push(element);
+            if empty {
+                close(&mut stack, &mut root);
+            }
+        }
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut root);
+    }
+    root.ok_or_else(|| "not an XML document".to_owned())
+}
+
+/// Close the innermost element: it goes into its parent, or is the root.
+fn close(stack: &mut Vec<Element>, root: &mut Option<Element>) {
+    let element = stack.pop().unwrap();
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(element),
+        None => *root = Some(element),
+    }
+}
+
+fn push_text(stack: &mut [Element], text: &str) {
+    if let Some(element) = stack.last_mut() {
+        element.text.push_str(&decode_references(text));
+    }
+}
+
+/// The element of a start tag, from after its `<`, the length of the tag
+/// and whether it is empty, like `<br/>`. None when it is not a tag.
+fn start_tag(tag: &str) -> (Option<Element>, usize, bool) {
+    let name_length = tag
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(tag.len());
+    if name_length == 0 {
+        return (None, 0, false);
+    }
+    let mut element = Element {
+        name: local_name(&tag[..name_length]).to_owned(),
+        ..Default::default()
+    };
+    let mut rest = &tag[name_length..];
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            return (Some(element), tag.len() - after.len(), true);
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return (Some(element), tag.len() - after.len(), false);
+        }
+        let Some(equals) = rest.find('=').filter(|equals| !rest[..*equals].contains('>')) else {
+            // A truncated tag, or an attribute without a value.
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            return (Some(element), tag.len() - rest.len() + end, rest[..end].ends_with("/>"));
+        };
+        let name = rest[..equals].trim();
+        rest = rest[equals + 1..].trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'');
+        let value = match quote {
+            Some(quote) => {
+                let end = rest[1..].find(quote).map_or(rest.len(), |end| end + 1);
+                let value = &rest[1..end];
+                rest = rest.get(end + 1..).unwrap_or_default();
+                value
+            }
+            None => {
+                let end = rest.find(|c: char| c.is_whitespace() || c == '>').unwrap_or(rest.len());
+                let value = &rest[..end];
+                rest = &rest[end..];
+                value
+            }
+        };
+        element.attributes.push((name.to_owned(), decode_references(value)));
+    }
+}
+
+/// The name without its namespace prefix. Attributes keep theirs, to tell
+/// `xml:base` from `base`.
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// Decode the predefined entities and the character references.
+fn decode_references(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_owned();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest[1..].find(';').filter(|end| *end <= 10).and_then(|end| {
+            let tail = &rest[1..];
+            let character = match &tail[..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                reference => {
+                    let number = reference.strip_prefix('#')?;
+                    let code = match number.strip_prefix('x') {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => number.parse().ok()?,
+                    };
+                    char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                }
+            };
+            Some((character, end + 2))
+        });
+        match decoded {
+            Some((character, length)) => {
+                out.push(character);
+                rest = &rest[length..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements() {
+        let root = parse(
+            br#"<?xml version="1.0"?>
+            <!DOCTYPE feed [ <!ENTITY x "y"> ]>
+            <!-- a comment -->
+            <a:feed xmlns:a="http://www.w3.org/2005/Atom" xml:lang=en>
+              <a:link rel='alternate' href="/one?a=1&amp;b=2"/>
+              <title>Fish &amp; chips &#233;&#x20AC; &unknown; &#xFFFFFF;</title>
+              <summary><![CDATA[<b>bold</b> & raw]]></summary>
+              <empty></empty>
+            </a:feed>"#,
+        )
+        .unwrap();
+        assert_eq!(root.name, "feed");
+        assert_eq!(root.attribute("xmlns:a"), Some("http://www.w3.org/2005/Atom"));
+        assert_eq!(root.attribute("xml:lang"), Some("en"));
+        let link = root.child("link").unwrap();
+        assert_eq!(link.attribute("rel"), Some("alternate"));
+        assert_eq!(link.attribute("href"), Some("/one?a=1&b=2"));
+        assert_eq!(root.child_text("title"), Some("Fish & chips é€ &unknown; \u{fffd}"));
+        assert_eq!(root.child_text("summary"), Some("<b>bold</b> & raw"));
+        assert_eq!(root.child_text("empty"), None);
+        assert_eq!(root.child_text("missing"), None);
+        let names: Vec<&str> = root.children.iter().map(|child| child.name.as_str()).collect();
+        assert_eq!(names, ["link", "title", "summary", "empty"]);
+    }
+
+    #[test]
+    fn lenient() {
+        // Unclosed elements are closed at the end, stray end tags ignored.
+        let root = parse(b"<urlset><url><loc>a</loc></stray><url><loc>b").unwrap();
+        assert_eq!(root.name, "urlset");
+        let url = root.child("url").unwrap();
+        assert_eq!(url.child_text("loc"), Some("a"));
+        assert_eq!(url.child("url").unwrap().child_text("loc"), Some("b"));
+        // A `<` which starts no tag is text.
+        assert_eq!(parse(b"<p>1 < 2</p>").unwrap().text, "1 < 2");
+        // An end tag closes the elements left open in it.
+        let root = parse(b"<a><b><c>text</a>").unwrap();
+        assert_eq!(root.child("b").unwrap().child("c").unwrap().text, "text");
+        // Invalid UTF-8 is replaced.
+        assert_eq!(parse(b"<a>caf\xe9</a>").unwrap().text, "caf\u{fffd}");
+    }
+
+    #[test]
+    fn descendants() {
+        let root = parse(b"<rdf><channel/><item>1</item><group><item>2<item>3</item></item></group></rdf>").unwrap();
+        let mut items = Vec::new();
+        root.descendants("item", &mut items);
+        let texts: Vec<&str> = items.iter().map(|item| item.text.as_str()).collect();
+        assert_eq!(texts, ["1", "2"]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(b"").err().unwrap(), "not an XML document");
+        assert_eq!(parse(b"just text").err().unwrap(), "not an XML document");
+        assert_eq!(parse(b"<!-- only a comment -->").err().unwrap(), "not an XML document");
+        let deep = "<a>".repeat(MAX_DEPTH + 1);
+        assert_eq!(parse(deep.as_bytes()).err().unwrap(), "XML document is too deeply nested");
+        assert!(parse("<a>".repeat(MAX_DEPTH).as_bytes()).is_ok());
+    }
+}
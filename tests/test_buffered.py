@@ -0,0 +1,71 @@
+import os
+import threading
+import unittest
+
+from pycurse.testing import MockTransport, downloader
+
+HTML = '<p>compressible</p>' * 500
+NOISE = os.urandom(4096)
+
+
+class CompressBufferedTest(unittest.TestCase):
+    def setUp(self):
+        self.transport = MockTransport()
+        self.addCleanup(self.transport.close)
+        self.transport.add('http://example.com/html', body=HTML)
+        self.transport.add('http://example.com/noise', body=NOISE)
+        self.transport.add('http://example.com/short', body='short')
+
+    def pending_bytes(self, d):
+        for line in d.metrics_text().splitlines():
+            if line.startswith('pycurse_responses_pending_bytes '):
+                return int(float(line.split()[1]))
+
+    def fetch_all(self, d, urls):
+        for url in urls:
+            d.add_request(url)
+        responses = {}
+        for _ in urls:
+            response = d.fetch(5000)
+            responses[response.url] = response
+        return responses
+
+    def test_bodies(self):
+        urls = ['http://example.com/html', 'http://example.com/noise', 'http://example.com/short']
+        responses = self.fetch_all(downloader(self.transport, compress_buffered=True), urls)
+        self.assertEqual(responses[urls[0]].text, HTML)
+        self.assertEqual(responses[urls[1]].content, NOISE)
+        self.assertEqual(responses[urls[2]].text, 'short')
+
+    def test_buffered_bytes(self):
+        sizes = []
+        for compress_buffered in (False, True):
+            d = downloader(self.transport, compress_buffered=compress_buffered)
+            d.add_request('http://example.com/html')
+            response = None
+            while response is None:
+                pending = self.pending_bytes(d)
+                if pending:
+                    sizes.append(pending)
+                    response = d.fetch(5000)
+            self.assertEqual(response.text, HTML)
+            self.assertEqual(self.pending_bytes(d), 0)
+        self.assertEqual(sizes[0], len(HTML))
+        self.assertLess(sizes[1], len(HTML) // 10)
+
+    def test_callback(self):
+        done = threading.Event()
+        texts = []
+
+        def callback(response):
+            texts.append(response.text)
+            done.set()
+
+        d = downloader(self.transport, compress_buffered=True)
+        d.add_request('http://example.com/html', callback=callback)
+        self.assertTrue(done.wait(10))
+        self.assertEqual(texts, [HTML])
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,138 @@
+"""Each request added gets exactly one response: none lost, none delivered
+twice, across panics of the downloader thread, cancellations and drains."""
+
+import gc
+import os
+import time
+import unittest
+
+from pycurse.testing import MockTransport, downloader
+
+COUNT = 8
+PANICKED = 'downloader thread panicked: injected panic'
+
+
+class DeliveryTest(unittest.TestCase):
+    def setUp(self):
+        self.transport = MockTransport()
+        self.addCleanup(self.transport.close)
+
+    def urls(self, **response):
+        urls = ['http://example.com/%d' % index for index in range(COUNT)]
+        for url in urls:
+            self.transport.add(url, body=url, **response)
+        return urls
+
+    def wait_for_requests(self, count):
+        deadline = time.monotonic() + 10
+        while len(self.transport.requests) < count:
+            self.assertLess(time.monotonic(), deadline, 'the requests did not reach the transport')
+            time.sleep(0.01)
+
+    def assert_delivered_once(self, d, urls):
+        """Fetch the responses of `urls`, one each, and check no other comes."""
+        responses = {}
+        deadline = time.monotonic() + 20
+        while len(responses) < len(urls) and time.monotonic() < deadline:
+            response = d.fetch(500)
+            if response is not None:
+                self.assertNotIn(response.url, responses, 'a second response to ' + response.url)
+                responses[response.url] = response
+        self.assertEqual(sorted(responses), sorted(urls))
+        self.assertIsNone(d.fetch(500))
+        self.assertEqual(d.queue_status(), {'pending': 0, 'in_flight': 0, 'completed': 0})
+        self.assertEqual(d.worker_status()['duplicates'], 0)
+        self.assertTrue(d.worker_alive())
+        return responses
+
+    def test_panic_with_queued_requests(self):
+        urls = self.urls()
+        d = downloader(self.transport)
+        d.pause()
+        for url in urls:
+            d.add_request(url)
+        d._inject_panic()
+        time.sleep(0.3)
+        d.resume()
+        responses = self.assert_delivered_once(d, urls)
+        for url, response in responses.items():
+            self.assertEqual(response.status_code, 200)
+            self.assertEqual(response.text, url)
+
+    def test_panic_with_running_transfers(self):
+        urls = self.urls(delay=1)
+        d = downloader(self.transport)
+        for url in urls:
+            d.add_request(url)
+        self.wait_for_requests(COUNT)
+        d._inject_panic()
+        responses = self.assert_delivered_once(d, urls)
+        self.assertEqual({response.error for response in responses.values()}, {PANICKED})
+
+    def test_panic_in_retry_backoff(self):
+        urls = self.urls(status=503)
+        d = downloader(self.transport, max_retries=3, fail_on_status=True, retry_backoff=3600, retry_max_backoff=3600)
+        for url in urls:
+            d.add_request(url)
+        self.wait_for_requests(COUNT)
+        time.sleep(0.3)
+        d._inject_panic()
+        responses = self.assert_delivered_once(d, urls)
+        self.assertEqual(len(self.transport.requests), COUNT)
+        self.assertEqual({response.error for response in responses.values()}, {PANICKED})
+
+    def test_panic_then_more_requests(self):
+        urls = self.urls(delay=0.5)
+        d = downloader(self.transport)
+        for url in urls[:COUNT // 2]:
+            d.add_request(url)
+        self.wait_for_requests(COUNT // 2)
+        d._inject_panic()
+        for url in urls[COUNT // 2:]:
+            d.add_request(url)
+        self.assert_delivered_once(d, urls)
+
+    def test_cancel_all_in_flight(self):
+        urls = self.urls(delay=1)
+        d = downloader(self.transport)
+        for url in urls:
+            d.add_request(url)
+        self.wait_for_requests(COUNT)
+        d.cancel_all()
+        responses = self.assert_delivered_once(d, urls)
+        self.assertEqual({response.error for response in responses.values()}, {'cancelled'})
+
+    def test_drain_in_flight(self):
+        urls = self.urls(delay=0.5)
+        d = downloader(self.transport)
+        for url in urls:
+            d.add_request(url)
+        self.wait_for_requests(COUNT)
+        self.assertTrue(d.drain(timeout=10))
+        with self.assertRaises(RuntimeError):
+            d.add_request(urls[0])
+        responses = self.assert_delivered_once(d, urls)
+        self.assertEqual({response.status_code for response in responses.values()}, {200})
+
+    @unittest.skipUnless(os.path.isdir('/proc/self/task'), 'needs /proc')
+    def test_drop_with_pending_requests(self):
+        urls = self.urls(delay=0.5)
+        threads = len(os.listdir('/proc/self/task'))
+        d = downloader(self.transport, max_transfers=2)
+        for url in urls:
+            d.add_request(url)
+        self.wait_for_requests(2)
+        del d
+        gc.collect()
+        deadline = time.monotonic() + 10
+        while len(os.listdir('/proc/self/task')) > threads:
+            self.assertLess(time.monotonic(), deadline, 'the downloader thread did not stop')
+            time.sleep(0.05)
+        time.sleep(1)
+        received = [request.url for request in self.transport.requests]
+        self.assertEqual(len(received), len(set(received)))
+        self.assertLess(len(received), COUNT)
+
+
+if __name__ == '__main__':
+    unittest.main()
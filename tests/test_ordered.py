@@ -0,0 +1,46 @@
+import threading
+import time
+import unittest
+
+from pycurse.testing import MockTransport, downloader
+
+
+class OrderedTest(unittest.TestCase):
+    def setUp(self):
+        self.transport = MockTransport()
+        self.addCleanup(self.transport.close)
+
+    def test_order_kept(self):
+        self.transport.add('http://example.com/slow', body='slow', delay=0.3)
+        self.transport.add('http://example.com/fast', body='fast')
+        d = downloader(self.transport, ordered=True)
+        d.add_request('http://example.com/slow')
+        d.add_request('http://example.com/fast')
+        self.assertEqual([d.fetch(5000).text, d.fetch(5000).text], ['slow', 'fast'])
+
+    def test_callback_not_waited_for(self):
+        self.transport.add('http://example.com/callback', body='callback', delay=0.3)
+        self.transport.add('http://example.com/fetched', body='fetched')
+        called = threading.Event()
+        d = downloader(self.transport, ordered=True)
+        d.add_request('http://example.com/callback', callback=lambda response: called.set())
+        d.add_request('http://example.com/fetched')
+        started = time.monotonic()
+        response = d.fetch(5000)
+        self.assertIsNotNone(response)
+        self.assertEqual(response.text, 'fetched')
+        self.assertLess(time.monotonic() - started, 3)
+        self.assertTrue(called.wait(5))
+
+    def test_callback_between_requests(self):
+        for name in ('first', 'callback', 'last'):
+            self.transport.add('http://example.com/' + name, body=name)
+        d = downloader(self.transport, ordered=True)
+        d.add_request('http://example.com/first')
+        d.add_request('http://example.com/callback', callback=lambda response: None)
+        d.add_request('http://example.com/last')
+        self.assertEqual([response.text for response in d], ['first', 'last'])
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,82 @@
+import unittest
+
+from pycurse.testing import MockTransport, downloader
+
+
+class RedirectTest(unittest.TestCase):
+    def setUp(self):
+        self.transport = MockTransport()
+        self.addCleanup(self.transport.close)
+        self.downloader = downloader(self.transport)
+
+    def redirect(self, url, location, status=302):
+        self.transport.add(url, status=status, headers={'Location': location})
+
+    def fetch(self, url, **options):
+        self.downloader.add_request(url, **options)
+        return self.downloader.fetch(5000)
+
+    def test_follow(self):
+        self.redirect('http://example.com/old', '/new')
+        self.transport.add('http://example.com/new', body='new')
+        for response in (
+            self.downloader.request('http://example.com/old', follow_redirects=True),
+            self.fetch('http://example.com/old', follow_redirects=True),
+        ):
+            self.assertEqual((response.status_code, response.text), (200, 'new'))
+            self.assertEqual(response.redirects, ['http://example.com/old'])
+
+    def test_file_scheme(self):
+        self.redirect('http://example.com/', 'file:///etc/hostname')
+        for response in (
+            self.downloader.request('http://example.com/', follow_redirects=True),
+            self.fetch('http://example.com/', follow_redirects=True),
+        ):
+            self.assertEqual(response.status_code, -1)
+            self.assertEqual(response.error_category, 'protocol')
+            self.assertIn('disallowed scheme', response.error)
+            self.assertEqual(response.content, b'')
+
+    def test_file_scheme_from_on_redirect(self):
+        self.redirect('http://example.com/', '/next')
+        response = self.fetch('http://example.com/', on_redirect=lambda url, target, status: 'file:///etc/hostname')
+        self.assertEqual(response.status_code, -1)
+        self.assertIn('disallowed scheme', response.error)
+        self.assertEqual(len(self.transport.requests), 1)
+
+    def test_on_redirect(self):
+        self.redirect('http://example.com/', '/next', status=301)
+        self.transport.add('http://example.com/next', body='next')
+        self.transport.add('http://example.com/other', body='other')
+        calls = []
+
+        def on_redirect(url, target, status):
+            calls.append((url, target, status))
+            return verdict
+
+        verdict = None
+        self.assertEqual(self.fetch('http://example.com/', on_redirect=on_redirect).text, 'next')
+        self.assertEqual(calls, [('http://example.com/', 'http://example.com/next', 301)])
+        verdict = 'http://example.com/other'
+        self.assertEqual(self.fetch('http://example.com/', on_redirect=on_redirect).text, 'other')
+        verdict = False
+        response = self.fetch('http://example.com/', on_redirect=on_redirect)
+        self.assertEqual((response.status_code, response.redirects), (301, []))
+
+    def test_credentials(self):
+        self.redirect('http://example.com/', '/same')
+        self.redirect('http://example.com/same', 'http://example.com:8080/port')
+        self.transport.add('http://example.com:8080/port', body='port')
+        headers = {'Authorization': 'Bearer secret', 'Cookie': 'session=secret', 'X-Other': 'kept'}
+        response = self.fetch('http://example.com/', follow_redirects=True, headers=headers)
+        self.assertEqual(response.text, 'port')
+        first, same, port = self.transport.requests
+        self.assertEqual(same.headers['authorization'], 'Bearer secret')
+        self.assertEqual(same.headers['cookie'], 'session=secret')
+        self.assertNotIn('authorization', port.headers)
+        self.assertNotIn('cookie', port.headers)
+        self.assertEqual(port.headers['x-other'], 'kept')
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,42 @@
+import json
+import threading
+import time
+import unittest
+
+from pycurse.testing import MockTransport, downloader
+
+
+class RequestTest(unittest.TestCase):
+    def setUp(self):
+        self.transport = MockTransport()
+        self.addCleanup(self.transport.close)
+
+    def test_concurrent_requests(self):
+        self.transport.add('http://example.com/', body='ok', delay=1)
+        d = downloader(self.transport)
+        texts = []
+
+        def request():
+            texts.append(d.request('http://example.com/').text)
+
+        threads = [threading.Thread(target=request) for _ in range(3)]
+        started = time.monotonic()
+        for thread in threads:
+            thread.start()
+        for thread in threads:
+            thread.join()
+        self.assertLess(time.monotonic() - started, 2)
+        self.assertEqual(texts, ['ok'] * 3)
+
+    def test_json_lone_surrogate(self):
+        self.transport.add('http://example.com/', method='POST')
+        d = downloader(self.transport)
+        self.assertEqual(d.request('http://example.com/', json={'text': 'a\ud800b'}).status_code, 200)
+        [request] = self.transport.requests
+        self.assertEqual(request.headers['content-type'], 'application/json')
+        self.assertEqual(request.body, b'{"text":"a\\ud800b"}')
+        self.assertEqual(json.loads(request.body), {'text': 'a\ud800b'})
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,50 @@
+import unittest
+
+from pycurse.testing import MockTransport, downloader
+
+
+class ResponseTest(unittest.TestCase):
+    def setUp(self):
+        self.transport = MockTransport()
+        self.addCleanup(self.transport.close)
+        self.downloader = downloader(self.transport)
+
+    def test_data(self):
+        self.transport.add('http://example.com/', body='café')
+        self.assertEqual(self.downloader.request('http://example.com/').data, 'café')
+
+    def test_data_of_binary_body(self):
+        self.transport.add('http://example.com/', body=b'\x89PNG\r\n\x1a\n\xff')
+        response = self.downloader.request('http://example.com/')
+        with self.assertRaises(UnicodeDecodeError):
+            response.data
+        self.assertEqual(response.content, b'\x89PNG\r\n\x1a\n\xff')
+
+    def fetch(self, url, **options):
+        self.downloader.add_request(url, **options)
+        return self.downloader.fetch(5000)
+
+    def test_parts(self):
+        body = b'--b\r\nContent-Range: bytes 0-1/10\r\n\r\nab\r\n--b\r\nContent-Range: bytes 8-9/10\r\n\r\nij\r\n--b--'
+        headers = {'Content-Type': 'multipart/byteranges; boundary=b'}
+        self.transport.add('http://example.com/', status=206, body=body, headers=headers)
+        response = self.fetch('http://example.com/', ranges=[(0, 1), (8, None)])
+        self.assertEqual(response.parts, [((0, 1), b'ab'), ((8, 9), b'ij')])
+
+    def test_parts_without_ranges(self):
+        headers = {'Content-Range': 'bytes 0-1/10'}
+        self.transport.add('http://example.com/', status=206, body='ab', headers=headers)
+        response = self.fetch('http://example.com/', headers={'Range': 'bytes=0-1'})
+        self.assertIsNone(response.parts)
+        self.assertEqual(response.content, b'ab')
+
+    def test_unsplittable_parts(self):
+        self.transport.add('http://example.com/', status=206, body='ab')
+        response = self.fetch('http://example.com/', ranges=[(0, 1)])
+        self.assertEqual((response.status_code, response.error), (206, None))
+        self.assertIsNone(response.parts)
+        self.assertEqual(response.content, b'ab')
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,64 @@
+import unittest
+
+from pycurse.testing import FakeClock, MockTransport, downloader
+
+ROBOTS = 'http://example.com/robots.txt'
+
+
+class RobotsTest(unittest.TestCase):
+    def setUp(self):
+        self.transport = MockTransport()
+        self.addCleanup(self.transport.close)
+        self.transport.add('http://example.com/page', body='page')
+        self.clock = FakeClock()
+        self.downloader = downloader(self.transport, respect_robots=True, clock=self.clock)
+
+    def fetch(self):
+        self.downloader.add_request('http://example.com/page')
+        return self.downloader.fetch(5000)
+
+    def robots_requests(self):
+        return len(self.transport.requests_to(ROBOTS))
+
+    def assert_disallowed(self, response):
+        self.assertEqual((response.status_code, response.error), (-1, 'disallowed by robots.txt'))
+
+    def test_rules(self):
+        self.transport.add(ROBOTS, body='User-agent: *\nDisallow: /page$\n')
+        self.transport.add(ROBOTS, body='User-agent: *\nDisallow: /private\n')
+        self.assert_disallowed(self.fetch())
+        # Cached for a day.
+        self.clock.advance(23 * 3600)
+        self.assert_disallowed(self.fetch())
+        self.clock.advance(3600)
+        self.assertEqual(self.fetch().text, 'page')
+        self.assertEqual(self.robots_requests(), 2)
+
+    def test_missing(self):
+        self.transport.add(ROBOTS, status=404)
+        self.transport.add(ROBOTS, body='User-agent: *\nDisallow: /\n')
+        self.assertEqual(self.fetch().text, 'page')
+        self.clock.advance(3600)
+        self.assertEqual(self.fetch().text, 'page')
+        self.assertEqual(self.robots_requests(), 1)
+
+    def test_unreachable(self):
+        self.transport.add(ROBOTS, status=503)
+        self.transport.add(ROBOTS, status=503)
+        self.transport.add(ROBOTS, body='User-agent: *\nDisallow:\n')
+        self.assert_disallowed(self.fetch())
+        self.assertEqual(self.robots_requests(), 1)
+        # Downloaded again after a minute, then two after the next failure.
+        self.clock.advance(61)
+        self.assert_disallowed(self.fetch())
+        self.assertEqual(self.robots_requests(), 2)
+        self.clock.advance(61)
+        self.assert_disallowed(self.fetch())
+        self.assertEqual(self.robots_requests(), 2)
+        self.clock.advance(60)
+        self.assertEqual(self.fetch().text, 'page')
+        self.assertEqual(self.robots_requests(), 3)
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,74 @@
+import os
+import unittest
+
+from pycurse import CurlDownloader
+
+
+def thread_count():
+    return len(os.listdir('/proc/self/task'))
+
+
+class InvalidSettingsTest(unittest.TestCase):
+    @unittest.skipUnless(os.path.isdir('/proc/self/task'), 'needs /proc')
+    def test_no_thread_left(self):
+        invalid = [
+            {'http_version': 'bogus'},
+            {'tls_min_version': 'bogus'},
+            {'connect_to': ['bogus']},
+            {'dns_local_ip4': 'bogus'},
+            {'dns_cache_timeout': -1},
+            {'callback_executor': 'bogus'},
+            {'profile': 'bogus'},
+            {'locale': ''},
+            {'inactivity_timeout': -1},
+        ]
+        downloader = CurlDownloader()
+        before = thread_count()
+        for settings in invalid:
+            with self.subTest(settings=settings):
+                with self.assertRaises(Exception):
+                    CurlDownloader(**settings)
+        # Other tests may leave threads finishing meanwhile.
+        self.assertLessEqual(thread_count(), before)
+        del downloader
+
+
+
+class TomlTest(unittest.TestCase):
+    SETTINGS = {
+        'robots_agent': 'my "bot"\\1.0\t\u00e9\U0001f600',
+        'tls_ciphers': 'ECDHE-RSA-AES128-GCM-SHA256:ECDHE-RSA-AES256-GCM-SHA384',
+        'connect_to': ['example.com:80:127.0.0.1:8080', 'example.org:443:[::1]:8443'],
+        'host_map': {'example.com': 'mirror.example.org:8080', '*.test': 'localhost'},
+        'force_https': ['*'],
+        'max_retries': 3,
+        'respect_robots': True,
+        'poll_interval': 0.25,
+        'retry_backoff': 1e-3,
+        'retry_max_backoff': 1e6,
+        'retry_budget': 0.2,
+        'max_elapsed': 1.0,
+    }
+
+    def test_round_trip(self):
+        downloader = CurlDownloader(**self.SETTINGS)
+        config = downloader.config()
+        self.assertEqual(CurlDownloader.from_toml(downloader.to_toml()).config(), config)
+        self.assertEqual(config['robots_agent'], self.SETTINGS['robots_agent'])
+        self.assertEqual(config['retry_backoff'], 1e-3)
+
+    def test_table(self):
+        toml = CurlDownloader(**self.SETTINGS).to_toml()
+        document = '[project]\nname = "app"\n\n[tool.pycurse]\n%s\n[tool.pycurse.other]\nmax_retries = 9\n' % toml
+        config = CurlDownloader.from_toml(document, table='tool.pycurse').config()
+        self.assertEqual(config, CurlDownloader(**self.SETTINGS).config())
+        with self.assertRaises(ValueError):
+            CurlDownloader.from_toml(document, table='tool')
+
+    def test_overrides(self):
+        toml = CurlDownloader(max_retries=3).to_toml()
+        self.assertEqual(CurlDownloader.from_toml(toml, max_retries=5).config()['max_retries'], 5)
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,55 @@
+import threading
+import unittest
+
+from pycurse.testing import MockTransport, downloader
+
+TRACEPARENT = '00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01'
+
+
+class SpanTest(unittest.TestCase):
+    def setUp(self):
+        self.transport = MockTransport()
+        self.addCleanup(self.transport.close)
+        self.transport.add('http://example.com/', body='ok')
+        self.spans = []
+        self.downloader = downloader(self.transport, span_exporter=self.spans.append)
+
+    def assert_span(self, span):
+        self.assertEqual(span['name'], 'HTTP request')
+        self.assertEqual(span['status'], 'ok')
+        self.assertEqual(span['attributes']['http.url'], 'http://example.com/')
+        self.assertEqual(span['attributes']['http.status_code'], 200)
+        self.assertEqual(span['attributes']['pycurse.attempts'], 1)
+        self.assertLessEqual(span['start_time'], span['end_time'])
+
+    def test_fetch(self):
+        self.downloader.add_request('http://example.com/', traceparent=TRACEPARENT)
+        self.assertEqual(self.downloader.fetch(5000).text, 'ok')
+        [span] = self.spans
+        self.assert_span(span)
+        self.assertEqual(span['trace_id'], '0af7651916cd43dd8448eb211c80319c')
+        self.assertEqual(span['parent_span_id'], 'b7ad6b7169203331')
+
+    def test_request(self):
+        self.assertEqual(self.downloader.request('http://example.com/', traceparent=TRACEPARENT).text, 'ok')
+        [span] = self.spans
+        self.assert_span(span)
+        self.assertEqual(span['parent_span_id'], 'b7ad6b7169203331')
+
+    def test_callback(self):
+        done = threading.Event()
+        spans = []
+
+        def callback(response):
+            spans.extend(self.spans)
+            done.set()
+
+        self.downloader.add_request('http://example.com/', callback=callback)
+        self.assertTrue(done.wait(10))
+        [span] = spans
+        self.assert_span(span)
+        self.assertIsNone(span['parent_span_id'])
+
+
+if __name__ == '__main__':
+    unittest.main()
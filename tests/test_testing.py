@@ -0,0 +1,130 @@
+import time
+import unittest
+
+from pycurse.testing import FakeClock, MockTransport, downloader
+
+
+class MockTransportTest(unittest.TestCase):
+    def setUp(self):
+        self.transport = MockTransport()
+        self.addCleanup(self.transport.close)
+
+    def test_responses(self):
+        self.transport.add('http://example.com/text', body='text', headers={'X-Test': '1'})
+        self.transport.add('http://api.test:8080/json?q=1', json={'a': 1}, method='POST', status=201)
+        d = downloader(self.transport)
+        response = d.request('http://example.com/text')
+        self.assertEqual((response.status_code, response.text), (200, 'text'))
+        self.assertEqual(response.header('x-test'), '1')
+        response = d.request('http://api.test:8080/json?q=1', json={'b': 2})
+        self.assertEqual((response.status_code, response.text), (201, '{"a": 1}'))
+        self.assertEqual(response.header('content-type'), 'application/json')
+        request = self.transport.requests_to('http://api.test:8080/json?q=1', method='POST')[0]
+        self.assertEqual(request.headers['host'], 'api.test:8080')
+        self.assertEqual(request.body, b'{"b":2}')
+
+    def test_unmatched(self):
+        self.transport.add('http://example.com/', body='root')
+        d = downloader(self.transport)
+        self.assertEqual(d.request('http://other.test/').status_code, 404)
+        self.assertEqual(d.request('http://example.com/?q').status_code, 404)
+        self.assertEqual(len(self.transport.requests), 2)
+
+    def test_responses_in_turn(self):
+        self.transport.add('http://example.com/', status=503)
+        self.transport.add('http://example.com/', body='ok')
+        d = downloader(self.transport, max_retries=2, fail_on_status=True)
+        self.assertEqual(d.request('http://example.com/').text, 'ok')
+        self.assertEqual(d.request('http://example.com/').text, 'ok')
+        self.assertEqual(len(self.transport.requests), 3)
+
+    def test_reset(self):
+        self.transport.add('http://example.com/', body='ok')
+        d = downloader(self.transport)
+        d.request('http://example.com/')
+        self.transport.reset()
+        self.assertEqual(self.transport.requests, [])
+        self.assertEqual(d.request('http://example.com/').status_code, 404)
+
+
+class FakeClockTest(unittest.TestCase):
+    def test_sleep(self):
+        clock = FakeClock(100)
+        clock.sleep(30)
+        clock.advance(5)
+        self.assertEqual((clock.time(), clock.monotonic(), clock.now), (135, 135, 135))
+        self.assertEqual(clock.advanced, 35)
+        self.assertEqual(clock.sleeps, [30])
+        with self.assertRaises(ValueError):
+            clock.sleep(-1)
+        with self.assertRaises(ValueError):
+            clock.advance(-1)
+
+    def test_install(self):
+        real = time.time, time.monotonic, time.sleep
+        with FakeClock(1000) as clock:
+            started = real[1]()
+            time.sleep(3600)
+            self.assertEqual((time.time(), time.monotonic()), (4600, 4600))
+            self.assertLess(real[1]() - started, 1)
+            self.assertEqual(clock.sleeps, [3600])
+        self.assertEqual((time.time, time.monotonic, time.sleep), real)
+
+
+class DownloaderClockTest(unittest.TestCase):
+    def setUp(self):
+        self.transport = MockTransport()
+        self.addCleanup(self.transport.close)
+        self.clock = FakeClock(time.time())
+
+    def wait_for_requests(self, count):
+        deadline = time.monotonic() + 10
+        while len(self.transport.requests) < count:
+            self.assertLess(time.monotonic(), deadline, 'the requests did not reach the transport')
+            time.sleep(0.01)
+
+    def test_retry_backoff(self):
+        self.transport.add('http://example.com/', status=503)
+        self.transport.add('http://example.com/', body='ok')
+        d = downloader(
+            self.transport, clock=self.clock, max_retries=1, fail_on_status=True,
+            retry_backoff=3600, retry_max_backoff=3600,
+        )
+        d.add_request('http://example.com/')
+        self.wait_for_requests(1)
+        self.assertIsNone(d.fetch(300))
+        self.assertEqual(len(self.transport.requests), 1)
+        self.clock.advance(3600)
+        response = d.fetch(5000)
+        self.assertEqual((response.status_code, response.text), (200, 'ok'))
+        self.assertEqual(len(self.transport.requests), 2)
+
+    def test_request_backoff(self):
+        self.transport.add('http://example.com/', status=503)
+        self.transport.add('http://example.com/', body='ok')
+        d = downloader(
+            self.transport, clock=self.clock, max_retries=1, fail_on_status=True,
+            retry_backoff=3600, retry_max_backoff=3600,
+        )
+        started = time.monotonic()
+        self.assertEqual(d.request('http://example.com/').text, 'ok')
+        self.assertLess(time.monotonic() - started, 5)
+        self.assertEqual(len(self.transport.requests), 2)
+        self.assertLessEqual(self.clock.advanced, 3600)
+
+    def test_deadline(self):
+        self.transport.add('http://example.com/slow', body='slow', delay=1)
+        self.transport.add('http://example.com/queued', body='queued')
+        d = downloader(self.transport, clock=self.clock, max_transfers=1)
+        d.add_request('http://example.com/slow')
+        d.add_request('http://example.com/queued', deadline=self.clock.time() + 60)
+        self.wait_for_requests(1)
+        self.clock.advance(61)
+        response = d.fetch(5000)
+        self.assertEqual((response.url, response.error), ('http://example.com/queued', 'deadline exceeded'))
+        self.assertEqual(d.fetch(5000).text, 'slow')
+        self.assertEqual(len(self.transport.requests), 1)
+
+
+if __name__ == '__main__':
+    unittest.main()